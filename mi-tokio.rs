@@ -1,17 +1,31 @@
 //! 演示了如何实现一个（非常）基本的异步rust执行器和定时器。
 //! 本文件的目的是提供一些关于各种构件如何结合的背景。
 
-use std::cell::RefCell;
+// 文件中演示的API并不都会在`main`中用到。
+#![allow(dead_code, unused_macros)]
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt;
 use std::future::Future;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Once, OnceLock, RwLock, RwLockReadGuard, Weak};
 use std::task::{Context, Poll, Waker};
 use std::thread;
 use std::time::{Duration, Instant};
-// 一个允许我们实现`std::task::Waker`的工具，而不必使用`不安全`的代码。
-use futures::task::{self, ArcWake};
-// 用作排队预定任务的通道。
-use crossbeam::channel;
+// 把一个`Arc`包裹的类型变成`std::task::Waker`的工具，见下面的`waker`模块。
+use waker::ArcWake;
+// 用作排队预定任务的通道，以及多线程模式下工作线程的本地队列。
+// 启用`std-only`特性时换成只用标准库实现的版本，见`std_queue`。
+#[cfg(not(feature = "std-only"))]
+use crossbeam::{channel, deque};
+#[cfg(feature = "std-only")]
+use std_queue::{channel, deque};
 
 // 主入口。一个mini-tokio实例被创建，一些任务被催生出来。
 // 我们的mini-tokio实现只支持生成任务和设置延迟。
@@ -19,9 +33,9 @@ fn main() {
     // 创建mini-tokio实例.
     let mini_tokio = MiniTokio::new();
 
-    // 产生根任务. 所有其他任务都是从这个根任务的上下文中产生的。
-    // 在调用`mini_tokio.run()`之前，没有任何工作发生。
-    mini_tokio.spawn(async {
+    // 在当前线程上驱动根未来。所有其他任务都是从这个根未来的上下文中产生的。
+    // 根未来完成时`block_on`返回它的输出，进程随之正常退出。
+    let sum = mini_tokio.block_on(async {
         // Spawn a task
         spawn(async {
             // 等待一点时间，以便在 "hello "之后打印 "world"。
@@ -34,13 +48,39 @@ fn main() {
             println!("hello");
         });
 
-        // 我们还没有实现执行器关闭，所以要强制进程退出。
+        // 催生的任务可以返回一个值，通过等待它的`JoinHandle`取得。
+        let sum = spawn(async { 2 + 2 });
+
+        // 等待"world"被打印出来，然后让根未来带着任务的输出完成。
         delay(Duration::from_millis(200)).await;
-        std::process::exit(0);
+        sum.await.unwrap()
+    });
+
+    assert_eq!(sum, 4);
+
+    // 同样的任务也可以在多个工作线程上执行。任务的调度状态保证它同时最多只在一个队列中：
+    // 多次唤醒只会让它排队一次，在轮询期间被唤醒的任务由轮询它的线程在轮询结束后重新排队，
+    // 所以一个任务永远不会被两个线程同时轮询。
+    let mini_tokio = MiniTokio::new_multi_thread(4);
+
+    let total = mini_tokio.block_on(async {
+        let handles: Vec<_> = (0..1000u64)
+            .map(|n| {
+                spawn(async move {
+                    yield_now().await;
+                    n
+                })
+            })
+            .collect();
+
+        let mut total = 0;
+        for handle in handles {
+            total += handle.await.unwrap();
+        }
+        total
     });
 
-    // 启动mini-tokio执行器循环。预定的任务被接收并执行。
-    mini_tokio.run();
+    assert_eq!(total, 999 * 1000 / 2);
 }
 
 /// 一个非常基本的基于通道的期货执行器。
@@ -55,206 +95,12361 @@ struct MiniTokio {
     // 例如，一个套接字收到了数据，一个`读'的调用将成功。
     scheduled: channel::Receiver<Arc<Task>>,
 
-    // 调度测验的另一半发送者.
+    // 调度通道的另一半发送者，连同关闭状态一起，放在可以被克隆的句柄中。
+    handle: Handle,
+}
+
+/// 运行时的句柄。
+///
+/// 句柄可以被克隆并发送到其他线程，用来催生任务或者关闭运行时，而不需要借用`MiniTokio`本身。
+#[derive(Clone)]
+struct Handle {
+    shared: Arc<Shared>,
+}
+
+// 执行器和它的所有句柄之间共享的状态。
+struct Shared {
+    // 调度通道的发送者。
     sender: channel::Sender<Arc<Task>>,
+    // 调度通道的接收者。新的工作线程从这里得到它的克隆，`Handle::metrics`用它统计调度通道的长度。
+    scheduled: channel::Receiver<Arc<Task>>,
+
+    // 高优先级和低优先级任务的调度通道，见`spawn_with_priority`。它们没有容量限制。
+    // 普通优先级的任务使用上面的调度通道，和本地队列、LIFO槽。
+    high_sender: channel::Sender<Arc<Task>>,
+    high_scheduled: channel::Receiver<Arc<Task>>,
+    low_sender: channel::Sender<Arc<Task>>,
+    low_scheduled: channel::Receiver<Arc<Task>>,
+
+    // 调度通道有容量限制（见`Builder::max_queue_depth`）并且已满时，被唤醒的任务暂存在这里。
+    // 唤醒路径不能阻塞，也不能丢弃唤醒；每当有任务从调度通道中被取走，最早的溢出任务就被移进腾出的位置。
+    overflow: Mutex<VecDeque<Arc<Task>>>,
+
+    // 所有还没有被释放的任务，按任务编号索引。只持有弱引用，任务被释放时把自己移除。
+    // 不在任何队列中的任务（例如只被某个定时器的唤醒者引用的任务）也在这里，
+    // 所以`MiniTokio`被丢弃时能找到并丢弃它们的未来，见`Shared::close_tasks`。
+    tasks: Mutex<HashMap<task::Id, Weak<Task>>>,
+
+    // `MiniTokio::metrics`报告的计数器。
+    // `worker_metrics`按工作线程的编号排列，单线程模式下只有调用`run`的线程一个。
+    // 已经退休的工作线程（见`Handle::remove_worker`）的位置是`None`，编号不会被重新使用。
+    worker_metrics: RwLock<Vec<Option<Arc<WorkerCounters>>>>,
+    tasks_spawned: AtomicU64,
+    tasks_completed: AtomicU64,
+    total_polls: AtomicU64,
+    tasks_alive: AtomicU64,
+    wakeups_deduplicated: AtomicU64,
+
+    // 是否已经请求关闭。设置后，新催生的任务和之后的唤醒都不会再进入调度通道。
+    is_shutdown: AtomicBool,
+
+    // 是否已经停止接受新的任务，见`MiniTokio::shutdown_timeout`。
+    // 设置后新催生的任务被拒绝，但已经存在的任务仍然会被唤醒和轮询。
+    is_closed: AtomicBool,
+
+    // 关闭信号。执行器循环同时在`scheduled`和这个接收器上等待。
+    // 这个通道上从不发送消息：`shutdown`通过丢弃唯一的发送端来关闭它，
+    // 这样阻塞在等待中的循环会立即被唤醒。
+    shutdown_rx: channel::Receiver<()>,
+    shutdown_tx: Mutex<Option<channel::Sender<()>>>,
+
+    // 是否是多线程模式，见`Builder::worker_threads`。
+    multi_thread: bool,
+
+    // 多线程模式下每个工作线程本地队列的窃取端，按工作线程的编号排列。单线程模式下为空。
+    // 和`worker_metrics`一样，退休的工作线程的位置是`None`。
+    stealers: RwLock<Vec<Option<deque::Stealer<Arc<Task>>>>>,
+
+    // 没有被标记为退休的工作线程数，和等待退休的工作线程数，见`Handle::remove_worker`。
+    live_workers: AtomicUsize,
+    retiring: AtomicUsize,
+
+    // 每个工作线程的固定队列，按工作线程的编号排列，见`Handle::spawn_pinned`。
+    // 固定在某个工作线程上的任务被唤醒时总是进入它的这个队列，其他工作线程不会从中窃取。
+    // 和`stealers`一样，退休的工作线程的位置是`None`。
+    pinned: RwLock<Vec<Option<PinnedQueue>>>,
+    // `spawn_pinned_auto`和重新固定任务时轮转选择工作线程的计数。
+    next_pinned: AtomicUsize,
+    // 因为工作线程退休而被固定到另一个工作线程上的次数。
+    tasks_repinned: AtomicU64,
+
+    // 找不到任务的工作线程在`idle_condvar`上park，见`WorkerContext::park`。
+    // 任务被推入任何一个队列之后，`unpark_one`叫醒其中至多一个。
+    idle: Mutex<IdleState>,
+    idle_condvar: Condvar,
+
+    // 执行`spawn_blocking`闭包的线程池。
+    blocking: BlockingPool,
+
+    // 多线程模式下的工作线程，由`run`等待它们退出。单线程模式下为空，任务由调用`run`的线程执行。
+    // 放在共享状态中是因为`block_in_place`会在运行期间创建替代的工作线程。
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
+
+    // 运行时创建的工作线程和阻塞线程的名字和栈大小，见`Builder::thread_name`。
+    thread_name: String,
+    thread_stack_size: Option<usize>,
+
+    // 执行任务或者阻塞闭包的线程开始和结束时调用的回调，见`Builder::on_thread_start`。
+    on_thread_start: Option<ThreadHook>,
+    on_thread_stop: Option<ThreadHook>,
+
+    // 工作线程绑定的CPU核，见`Builder::pin_workers`。不支持绑定时为`None`。
+    pin_workers: Option<Vec<usize>>,
+    // 接收警告的回调，见`Builder::on_warning`。
+    on_warning: Option<WarningHook>,
+    // 一次轮询超过多久算作缓慢，以及那时调用的回调，见`Builder::slow_poll_threshold`。
+    // 检测被关闭时`slow_poll_threshold`为`None`。
+    slow_poll_threshold: Option<Duration>,
+    on_slow_poll: Option<SlowPollHook>,
+
+    // 调度通道已满时催生任务怎么做，见`Builder::spawn_backpressure`。
+    spawn_backpressure: SpawnBackpressure,
+
+    // 工作线程每查找多少次普通优先级的任务先查看一次调度通道，见`Builder::global_queue_interval`。
+    global_queue_interval: usize,
+
+    // 同时存活的任务数的上限，和`tasks_alive`比较，见`Builder::max_tasks`。
+    max_tasks: Option<u64>,
+
+    // 脱离的任务panic时怎么做，见`Builder::unhandled_panic`。
+    unhandled_panic: UnhandledPanic,
+    // 按照`UnhandledPanic::ShutdownRuntime`关闭运行时的那次panic的载荷，由驱动运行时的线程重新抛出。
+    panic_payload: Mutex<Option<Box<dyn Any + Send>>>,
+    // 任务panic时调用的回调，见`Builder::on_task_panic`。
+    on_task_panic: Option<TaskPanicHook>,
+
+    // 检测到所有任务都不可能再取得进展时调用的回调，以及停滞持续多久才报告，见`Builder::on_deadlock`。
+    on_deadlock: Option<DeadlockHook>,
+    deadlock_timeout: Duration,
+    // 上一次报告时的`total_polls`：之后没有任何任务被轮询过的话，同一次停滞不再重复报告。
+    deadlock_reported_at: AtomicU64,
+    // 正在`MiniTokio::block_on`中的线程数。根未来可能唤醒任务，这时不做检测。
+    blocked_on: AtomicUsize,
+
+    // 这个运行时中的`Sleep`读取时间和登记截止时间的时钟，见`Builder::clock`。
+    clock: Arc<dyn Clock>,
+}
+
+// 空闲的工作线程。和`BlockingState`一样，被叫醒的线程在通知时就从`sleeping`中扣除，
+// 计入`notified`，这样两个紧挨着的通知不会叫醒同一个线程两次。
+#[derive(Default)]
+struct IdleState {
+    // 正在`idle_condvar`上等待、还没有被分配通知的工作线程数。
+    sleeping: usize,
+    // 已经被通知、即将醒来的工作线程数。
+    notified: usize,
+}
+
+// 一个工作线程的计数器，见`Metrics::worker_poll_count`等方法。
+#[derive(Default)]
+struct WorkerCounters {
+    polls: AtomicU64,
+    steals: AtomicU64,
+    steal_failures: AtomicU64,
+    parks: AtomicU64,
+    busy_nanos: AtomicU64,
+}
+
+impl WorkerCounters {
+    fn record_poll(&self, busy: Duration) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.busy_nanos
+            .fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+// 一个工作线程的固定队列，见`Shared::pinned`。
+type PinnedQueue = Arc<Mutex<VecDeque<Arc<Task>>>>;
+
+// `Builder::on_task_panic`设置的回调。
+type TaskPanicHook = Arc<dyn Fn(&TaskPanicInfo<'_>) + Send + Sync>;
+
+// `Builder::on_thread_start`和`Builder::on_thread_stop`设置的回调。
+type ThreadHook = Arc<dyn Fn() + Send + Sync>;
+
+// 在一个执行运行时的工作的线程上调用`on_thread_start`，被丢弃时调用`on_thread_stop`，
+// 所以线程因为panic而离开执行者循环时停止回调也会被调用。
+struct ThreadHooks {
+    shared: Arc<Shared>,
+}
+
+impl ThreadHooks {
+    fn enter(shared: &Arc<Shared>) -> ThreadHooks {
+        call_thread_hook(&shared.on_thread_start, "on_thread_start");
+        ThreadHooks {
+            shared: shared.clone(),
+        }
+    }
+}
+
+impl Drop for ThreadHooks {
+    fn drop(&mut self) {
+        call_thread_hook(&self.shared.on_thread_stop, "on_thread_stop");
+    }
+}
+
+// `Builder::on_deadlock`设置的回调。
+type DeadlockHook = Arc<dyn Fn(&TaskDump) + Send + Sync>;
+
+// `Builder::on_warning`设置的回调。
+type WarningHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+// `Builder::on_slow_poll`设置的回调。
+type SlowPollHook = Arc<dyn Fn(&TaskMeta<'_>, Duration) + Send + Sync>;
+
+// 报告一个不影响运行时继续运行的问题。没有设置回调时打印到标准错误。
+fn report_warning(hook: &Option<WarningHook>, message: &str) {
+    match hook {
+        Some(hook) => hook(message),
+        None => eprintln!("mini-tokio: {}", message),
+    }
+}
+
+// 线程的CPU亲和性，见`Builder::pin_workers`。
+//
+// 只有启用了`affinity`特性、在Linux上才真正绑定线程（通过`sched_setaffinity`）。
+// 其他情况下`SUPPORTED`为`false`，由`Builder::build`报告一个警告，运行时照常创建。
+mod affinity {
+    use super::*;
+
+    pub(crate) const SUPPORTED: bool = cfg!(all(feature = "affinity", target_os = "linux"));
+
+    // 第`index`个线程绑定的核。线程比给定的核多时从头开始循环。
+    pub(crate) fn core_for(cores: &[usize], index: usize) -> usize {
+        cores[index % cores.len()]
+    }
+
+    // 定时器线程是整个进程共用的，它可能在某个运行时要求绑定它之前或者之后才启动：
+    // 两边都在持有锁时查看对方留下的信息，所以无论谁先谁后，线程都会被绑定。
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    struct TimerThread {
+        tid: Option<libc::pid_t>,
+        core: Option<usize>,
+    }
+
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    static TIMER_THREAD: Mutex<TimerThread> = Mutex::new(TimerThread {
+        tid: None,
+        core: None,
+    });
+
+    // 把线程`tid`绑定到`core`上，`tid`为0时是当前线程。
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    fn set_affinity(tid: libc::pid_t, core: usize) -> io::Result<()> {
+        check_core(core)?;
+
+        // `cpu_set_t`是一个普通的位图，全零就是空集合；`sched_setaffinity`只读取它。
+        let ret = unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(core, &mut set);
+            libc::sched_setaffinity(tid, std::mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // `cpu_set_t`只能表示前`CPU_SETSIZE`个核。
+    #[cfg(all(feature = "affinity", target_os = "linux"))]
+    fn check_core(core: usize) -> io::Result<()> {
+        if core >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "CPU index is out of range",
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(all(feature = "affinity", target_os = "linux")))]
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pinning threads to CPUs requires the `affinity` feature on Linux",
+        )
+    }
+
+    // 把当前线程绑定到`core`上。
+    pub(crate) fn pin_current(core: usize) -> io::Result<()> {
+        #[cfg(all(feature = "affinity", target_os = "linux"))]
+        return set_affinity(0, core);
+
+        #[cfg(not(all(feature = "affinity", target_os = "linux")))]
+        {
+            let _ = core;
+            Err(unsupported())
+        }
+    }
+
+    // 定时器线程启动时调用。已经有运行时要求绑定它时在这里绑定，失败时没有可以报告的地方。
+    pub(crate) fn timer_thread_started() {
+        #[cfg(all(feature = "affinity", target_os = "linux"))]
+        {
+            let mut timer = TIMER_THREAD.lock().unwrap();
+            timer.tid = Some(unsafe { libc::gettid() });
+
+            if let Some(core) = timer.core {
+                let _ = set_affinity(0, core);
+            }
+        }
+    }
+
+    // 把定时器线程绑定到`core`上。线程还没有启动时，它在启动时绑定自己，所以这里先检查核的编号。
+    pub(crate) fn pin_timer(core: usize) -> io::Result<()> {
+        #[cfg(all(feature = "affinity", target_os = "linux"))]
+        {
+            check_core(core)?;
+            let mut timer = TIMER_THREAD.lock().unwrap();
+            timer.core = Some(core);
+
+            match timer.tid {
+                Some(tid) => set_affinity(tid, core),
+                None => Ok(()),
+            }
+        }
+
+        #[cfg(not(all(feature = "affinity", target_os = "linux")))]
+        {
+            let _ = core;
+            Err(unsupported())
+        }
+    }
+}
+
+// 回调panic时不能让线程跟着退出：工作线程会带走它的本地队列，阻塞线程会永远占着线程池中的名额。
+// panic消息已经由标准库的panic钩子打印，这里再报告是哪一个回调，然后继续。
+fn call_thread_hook(hook: &Option<ThreadHook>, which: &str) {
+    if let Some(hook) = hook {
+        if panic::catch_unwind(AssertUnwindSafe(|| hook())).is_err() {
+            eprintln!("mini-tokio: the `{}` callback panicked", which);
+        }
+    }
+}
+
+// 工作线程的上下文，保存在线程本地变量`WORKER`中，使唤醒路径能找到当前工作线程的本地队列。
+//
+// 上下文归线程本地变量所有，而不是归执行者循环所有：`block_in_place`会把它取走，
+// 连同本地队列一起交给一个替代的工作线程。
+struct WorkerContext {
+    // 工作线程所属的运行时。只有属于这个运行时的任务才会被推入本地队列。
+    shared: Arc<Shared>,
+    // 工作线程的编号，也是它的窃取端在`Shared::stealers`中的位置。
+    index: usize,
+    // 这个工作线程在`Shared::worker_metrics`中的计数器。
+    counters: Arc<WorkerCounters>,
+    // 固定在这个工作线程上的任务，见`Handle::spawn_pinned`。
+    pinned: PinnedQueue,
+    // 本地队列。只有这个工作线程会从中弹出任务，其他工作线程通过窃取端从中窃取。
+    local: deque::Worker<Arc<Task>>,
+    // LIFO槽：在这个工作线程上最近被唤醒的任务，它在本地队列中的任务之前被执行。
+    // 两个任务互相唤醒时（请求和响应），每一跳都不必排到整个队列的末尾。
+    // 槽中的任务不能被其他工作线程窃取。
+    lifo: Cell<Option<Arc<Task>>>,
+    // 连续从LIFO槽中取出任务的次数，见`LIFO_SLOT_LIMIT`。
+    lifo_polls: Cell<usize>,
+    // 取出的任务数，用来在`PRIORITY_CYCLE`中轮转。
+    tick: Cell<usize>,
+    // 查找普通优先级任务的次数，见`Builder::global_queue_interval`。
+    normal_tick: Cell<usize>,
+    // 调度通道的接收端。
+    scheduled: channel::Receiver<Arc<Task>>,
 }
 
 impl MiniTokio {
     /// Initialize a new mini-tokio instance.
+    ///
+    /// 等价于`Builder::new().build()`。
+    ///
+    /// # Panics
+    ///
+    /// 无法创建运行时的线程时panic。
     fn new() -> MiniTokio {
-        let (sender, scheduled) = channel::unbounded();
+        Builder::new().build().expect("failed to build the runtime")
+    }
+
+    /// 创建一个多线程的mini-tokio实例，其中`n`个工作线程立即开始执行任务。
+    ///
+    /// 每个工作线程都有自己的本地队列：在工作线程上发生的唤醒会进入它自己的队列，
+    /// 新催生的任务和从其他线程发出的唤醒进入共享的调度通道。
+    /// 空闲的工作线程先查看调度通道，再从其他工作线程的本地队列中窃取一半的任务。
+    /// 因为任务已经在工作线程上执行，`run`只是阻塞到关闭被请求并等待工作线程退出。
+    ///
+    /// 等价于`Builder::new().worker_threads(n).build()`。
+    ///
+    /// # Panics
+    ///
+    /// `n`为零，或者无法创建工作线程时panic。
+    fn new_multi_thread(n: usize) -> MiniTokio {
+        Builder::new()
+            .worker_threads(n)
+            .build()
+            .expect("failed to build the runtime")
+    }
+
+    /// 创建一个定时器使用给定时钟的单线程mini-tokio实例。
+    ///
+    /// 等价于`Builder::new().clock(clock).build()`。
+    ///
+    /// # Panics
+    ///
+    /// 无法创建运行时的线程时panic。
+    fn with_clock<C: Clock + 'static>(clock: C) -> MiniTokio {
+        Builder::new()
+            .clock(clock)
+            .build()
+            .expect("failed to build the runtime")
+    }
 
-        MiniTokio { scheduled, sender }
+    /// 返回这个运行时的句柄。
+    fn handle(&self) -> Handle {
+        self.handle.clone()
     }
 
     /// 在mini-tokio实例上产生一个未来。
     ///
     /// 给定的未来将被包裹在 "任务 "线束中，并被推入 "调度 "队列。
     /// 当`run'被调用时，未来将被执行。
-    fn spawn<F>(&self, future: F)
+    /// 返回的`JoinHandle`可以被等待以取得未来的输出。
+    ///
+    /// # Panics
+    ///
+    /// 在`shutdown`之后调用时panic：未来永远不会被执行，不能让它悄无声息地消失。
+    /// 调度通道有容量限制（见`Builder::max_queue_depth`）并且已满时也会panic。
+    /// 需要处理这些情况时使用`Handle::spawn`，它返回`SpawnError::Shutdown`或者`SpawnError::QueueFull`。
+    fn spawn<F, T>(&self, future: F) -> JoinHandle<T>
     where
-        F: Future<Output = ()> + Send + 'static,
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
     {
-        Task::spawn(future, &self.sender);
+        self.handle.spawn_or_panic(future, Priority::Normal, None)
     }
 
-    /// 运行执行器。
+    /// 在当前线程上驱动给定的未来直到完成，并返回它的输出。
     ///
-    /// 这将启动执行器循环并无限期地运行。
-    /// 没有实现关闭机制。
+    /// 在根未来处于`Poll::Pending`期间，执行器会继续执行从"调度"通道收到的其他任务，
+    /// 所以在根未来中催生的任务也能取得进展。根未来不需要是`Send`，因为它从不离开当前线程。
     ///
-    /// 任务从 "调度"通道接收器中弹出。
-    /// 在通道上接收到一个任务标志着该任务已经准备好被执行。
-    /// 这发生在任务第一次被创建和它的唤醒者被使用时。
-    fn run(&self) {
-        // 设置CURRENT thread-local，使其指向当前的执行器。
-        // Tokio使用线程本地变量来实现`tokio::spwn`。
-        // 当进入运行时，执行器用线程-本地存储必要的上下文，以支持催生新任务。
-        CURRENT.with(|cell| {
-            *cell.borrow_mut() = Some(self.sender.clone());
-        });
+    /// 可以在另一个运行时的任务或者`block_on`中调用：当前线程在返回之前同时替那个运行时阻塞着，
+    /// 那个运行时的其他任务（单线程模式下是全部任务）要等到这里返回才能继续执行。
+    ///
+    /// # Panics
+    ///
+    /// 在正在驱动这个运行时的线程上调用时panic，也就是在这个运行时的任务中，
+    /// 或者在这个运行时的`block_on`的根未来中：外层正在轮询的任务要等这里返回才能继续，
+    /// 单线程模式下很容易不声不响地死锁。
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        assert!(
+            !self.handle.is_driven_here(),
+            "Cannot start a runtime from within a runtime: \
+             `MiniTokio::block_on` was called from a thread that is driving the same runtime"
+        );
 
-        // 执行者循环。预定的任务被接收。
-        // 如果通道是空的，线程就会阻塞，直到有任务被接收。
-        while let Ok(task) = self.scheduled.recv() {
-            // 执行任务，直到它完成或无法取得进一步进展，并返回`Poll::Pending`。
-            task.poll();
+        // 和`run`一样设置CURRENT，使根未来中的`spawn`调用能找到这个执行器。
+        // 返回时（包括根未来panic时）守卫恢复之前的CURRENT，所以`block_on`可以嵌套在另一个运行时的任务中。
+        let _enter = self.handle.enter();
+
+        // 这个线程在返回之前负责轮询任务，标记它，使`Handle::block_on`拒绝在这里阻塞。
+        struct Reset(bool);
+
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                BLOCK_ON.with(|block_on| block_on.set(self.0));
+            }
         }
+
+        let _reset = Reset(BLOCK_ON.with(|block_on| block_on.replace(true)));
+        let _hooks = ThreadHooks::enter(&self.handle.shared);
+
+        // 根未来等待期间运行时不报告停滞，见`Builder::on_deadlock`。
+        struct BlockedOn<'a>(&'a AtomicUsize);
+
+        impl Drop for BlockedOn<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        let blocked_on = &self.handle.shared.blocked_on;
+        blocked_on.fetch_add(1, Ordering::SeqCst);
+        let _blocked_on = BlockedOn(blocked_on);
+
+        // 根未来的唤醒者向这个通道发送一个通知。容量为1就足够了：
+        // 一个尚未处理的通知已经保证根未来会被再次轮询，多余的唤醒可以合并。
+        let (notify_tx, notify_rx) = channel::bounded(1);
+        let waker = waker::waker(Arc::new(BlockOnWaker { notify: notify_tx }));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut future = Box::pin(future);
+
+        // 关闭信号只需要被看到一次：它被看到之后通道一直处于关闭状态，继续等待它会变成忙等。
+        let mut shutdown_rx = self.handle.shared.shutdown_rx.clone();
+
+        let output = loop {
+            if let Poll::Ready(output) = with_budget(|| future.as_mut().poll(&mut cx)) {
+                break output;
+            }
+
+            // 根未来还没有完成。阻塞等待，直到它被唤醒，期间执行其他被调度的任务。
+            loop {
+                channel::select! {
+                    recv(notify_rx) -> _ => break,
+                    recv(self.scheduled) -> task => {
+                        if let Ok(task) = task {
+                            self.handle.shared.refill();
+                            task.poll();
+                        }
+                    }
+                    // `block_on`不按照`PRIORITY_CYCLE`轮转，只是执行任意一个就绪的任务。
+                    recv(self.handle.shared.high_scheduled) -> task => {
+                        if let Ok(task) = task {
+                            task.poll();
+                        }
+                    }
+                    recv(self.handle.shared.low_scheduled) -> task => {
+                        if let Ok(task) = task {
+                            task.poll();
+                        }
+                    }
+                    // 运行时被关闭了。如果是因为脱离的任务panic，根未来等待的东西可能永远不会完成。
+                    recv(shutdown_rx) -> _ => shutdown_rx = channel::never(),
+                }
+
+                self.handle.resume_unhandled_panic();
+            }
+        };
+
+        output
     }
-}
 
-//相当于`tokio::spawn`。
-// 当进入mini-tokio执行器时，`CURRENT`线程本地被设置为指向该执行器的通道的Send half。
-// 然后，spwn需要为给定的`future`创建`Task`线束，并将其推入计划队列。
-pub fn spawn<F>(future: F)
-where
-    F: Future<Output = ()> + Send + 'static,
-{
-    CURRENT.with(|cell| {
-        let borrow = cell.borrow();
-        let sender = borrow.as_ref().unwrap();
-        Task::spawn(future, sender);
-    });
-}
+    /// 驱动执行器，直到给定的根未来完成，然后关闭运行时并返回根未来的输出。
+    ///
+    /// 和`block_on`一样，根未来等待期间其他被调度的任务也会被执行。不同的是`run_until`消耗运行时：
+    /// 根未来完成时还没有完成的后台任务不会再被轮询，它们的未来和`run`返回时一样被丢弃，
+    /// 等待它们的`JoinHandle`会得到`JoinError::Cancelled`。这样一个测试或者示例可以在根未来完成时正常结束，
+    /// 而不必像`run`那样依赖某个任务去请求关闭。
+    ///
+    /// 之后到期的`delay`仍然会唤醒它们的任务，唤醒被关闭的运行时只是什么都不做。
+    fn run_until<F: Future>(self, future: F) -> F::Output {
+        let output = self.block_on(future);
 
-// 与`thread::sleep`异步等效。在这个函数上的等待会在给定的时间内暂停。
-//
-// mini-tokio通过生成一个定时器线程来实现延迟，该线程在所要求的时间内睡眠，并在延迟完成后通知调用者。
-// 每**次调用 "delay "就会产生一个线程。
-// 这显然是一个糟糕的实现策略，没有人应该在生产中使用这个策略。
-// Tokio并没有使用这种策略。
-// 然而，它可以用几行代码来实现，所以我们在这里。
-async fn delay(dur: Duration) {
-    // `delay`是一个`叶子`的未来。有时，这被称为 "资源"。
-    // 其他资源包括`套接字`和`通道`。
-    // `资源`可能无法用`async/await`来实现，因为它们必须与一些操作系统的细节相结合。
-    // 正因为如此，我们必须手动实现"未来"。
-    //
-    // 然而，将API暴露为`async fn'是很好的。一个有用的习惯是手动定义一个私有的未来，然后从一个公共的`async fn`API中使用它。
-    struct Delay {
-        // delay时长.
-        when: Instant,
-        // 延迟完成后通知的唤醒者。
-        // 唤醒者必须能被定时器线程和未来线程访问，所以它被`Arc<Mutex<_>'包裹起来。
-        waker: Option<Arc<Mutex<Waker>>>,
+        // 关闭已被请求，`run`不会再轮询任何任务，只是等工作线程退出并丢弃队列中的任务。
+        self.shutdown();
+        self.run();
+
+        output
     }
 
-    impl Future for Delay {
-        type Output = ();
+    /// 停止接受新的任务，在给定的时间内继续执行已经存在的任务和阻塞闭包，然后关闭运行时。
+    ///
+    /// 之后催生的任务和`spawn_blocking`闭包被拒绝（`Handle::spawn`返回`SpawnError::Shutdown`），
+    /// 已经存在的任务仍然会被唤醒和轮询，所以它们可以在期限内正常完成。
+    /// 所有任务和阻塞闭包都完成，或者期限已到时，关闭运行时并丢弃剩下的任务的未来，和`run`返回时一样。
+    ///
+    /// 这个函数总会在期限之后不久返回：不会等待工作线程或者阻塞线程退出，
+    /// 一个永远不返回的阻塞闭包只是让它的线程在后台脱离运行。返回的`ShutdownSummary`
+    /// 报告期限内完成了多少个任务、放弃了多少个任务和阻塞闭包。
+    fn shutdown_timeout(self, timeout: Duration) -> ShutdownSummary {
+        let shared = &self.handle.shared;
+        let deadline = Instant::now() + timeout;
+        let completed_before = shared.tasks_completed.load(Ordering::Relaxed);
 
-        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
-            // 首先，如果这是第一次调用future，则催生定时器线程。
-            // 如果定时器线程已经在运行，确保存储的`Waker'与当前任务的Waker相匹配。
-            if let Some(waker) = &self.waker {
-                let mut waker = waker.lock().unwrap();
+        shared.is_closed.store(true, Ordering::SeqCst);
 
-                // 检查存储的waker是否与当前任务的waker一致。
-                // 这是必要的，因为在调用`poll'之间，`Delay'的未来实例可能会转移到不同的任务。
-                // 如果发生这种情况，给定的`Context'所包含的waker就会不同，我们必须更新我们存储的waker以反映这种变化。
-                if !waker.will_wake(cx.waker()) {
-                    *waker = cx.waker().clone();
-                }
-            } else {
-                let when = self.when;
-                let waker = Arc::new(Mutex::new(cx.waker().clone()));
-                self.waker = Some(waker.clone());
+        // 和`run`一样设置CURRENT，使这里轮询的任务中的`spawn`调用能找到这个执行器（然后被拒绝）。
+        let enter = self.handle.enter();
 
-                // 这是第一次调用`poll`，催生定时器线程。
-                thread::spawn(move || {
-                    let now = Instant::now();
+        // 任务和阻塞闭包完成时不会通知这里，所以至多等待这么久就要再检查一次。
+        const CHECK_INTERVAL: Duration = Duration::from_millis(10);
 
-                    if now < when {
-                        thread::sleep(when - now);
-                    }
+        // 运行时也可能因为脱离的任务panic而被关闭，那之后任务不会再被轮询，不必等到期限。
+        while !self.handle.is_shutdown() {
+            if shared.tasks_alive.load(Ordering::SeqCst) == 0 && shared.blocking.pending() == 0 {
+                break;
+            }
 
-                    // 持续时间已经过了。通过调用唤醒器通知调用者。
-                    let waker = waker.lock().unwrap();
-                    waker.wake_by_ref();
-                });
+            let now = Instant::now();
+            if now >= deadline {
+                break;
             }
+            let wait = CHECK_INTERVAL.min(deadline - now);
 
-            // 一旦唤醒者被存储起来，定时器线程被启动，就是检查延迟是否已经完成的时候了。
-            // 这是通过检查当前的瞬间完成的。
-            // 如果持续时间已经过了，那么未来就已经完成了，`Poll::Ready`将被返回。
-            if Instant::now() >= self.when {
-                Poll::Ready(())
+            if !shared.multi_thread {
+                // 单线程模式：没有工作线程，任务在这里被轮询，和`block_on`一样执行任意一个就绪的任务。
+                channel::select! {
+                    recv(self.scheduled) -> task => {
+                        if let Ok(task) = task {
+                            shared.refill();
+                            task.poll();
+                        }
+                    }
+                    recv(shared.high_scheduled) -> task => {
+                        if let Ok(task) = task {
+                            task.poll();
+                        }
+                    }
+                    recv(shared.low_scheduled) -> task => {
+                        if let Ok(task) = task {
+                            task.poll();
+                        }
+                    }
+                    default(wait) => {}
+                }
             } else {
-                // 持续时间没有过去，未来没有完成，所以返回`Poll::Pending`。
-                //
-                // `Future`特质契约要求，当返回`Pending`时，未来确保一旦未来应该再次轮询，就会向给定的唤醒者发出信号。
-                // 在我们的例子中，通过在这里返回`Pending'，我们承诺一旦请求的持续时间结束，我们将调用包括在`Context'参数中的指定唤醒者。我们通过催生上面的定时器线程来确保这一点。
-                //
-                // 如果我们忘记调用唤醒器，任务将无限期地挂起。
-                Poll::Pending
+                // 多线程模式：工作线程继续执行任务，这里只需等待。
+                thread::sleep(wait);
             }
         }
+
+        let summary = ShutdownSummary {
+            completed: shared.tasks_completed.load(Ordering::Relaxed) - completed_before,
+            abandoned: shared.tasks_alive.load(Ordering::SeqCst),
+            blocking_abandoned: shared.blocking.pending(),
+        };
+
+        drop(enter);
+        self.handle.resume_unhandled_panic();
+
+        // `drop`发出关闭信号并丢弃剩下的任务，但不等待工作线程退出：
+        // 某个工作线程可能正卡在一个不返回的任务中。
+        drop(self);
+
+        summary
     }
 
-    // Create an instance of our `Delay` future.
-    let future = Delay {
-        when: Instant::now() + dur,
-        waker: None,
-    };
+    /// 关闭运行时并立即返回，不等待任何任务、工作线程或者阻塞线程。
+    ///
+    /// 和丢弃`MiniTokio`一样：发出关闭信号，之后`Handle::spawn`返回`SpawnError::Shutdown`，
+    /// 还在队列中的任务和仍然存活的任务的未来在这里被丢弃。工作线程在当前任务执行完之后看到关闭信号，
+    /// 自己退出；正在执行的阻塞闭包返回后它的线程也会退出。
+    /// 适合在不能阻塞的地方拆除运行时，例如在另一个运行时的任务中。
+    /// 之后通过句柄再次关闭（`Handle::shutdown`）是无害的。
+    fn shutdown_background(self) {
+        drop(self);
+    }
+
+    /// 返回运行时的统计数据的快照。等价于`self.handle().metrics()`。
+    fn metrics(&self) -> Metrics {
+        self.handle.metrics()
+    }
+
+    /// 列出运行时中所有存活的任务。等价于`self.handle().dump()`。
+    fn dump(&self) -> TaskDump {
+        self.handle.dump()
+    }
+
+    /// 进入运行时的上下文。等价于`self.handle().enter()`。
+    fn enter(&self) -> EnterGuard {
+        self.handle.enter()
+    }
+
+    /// 请求关闭执行器。等价于`self.handle().shutdown()`。
+    fn shutdown(&self) {
+        self.handle.shutdown();
+    }
+
+    /// 运行执行器。
+    ///
+    /// 这将启动执行器循环并一直运行，直到通过`shutdown`或者任意一个句柄请求关闭。
+    /// 返回时，所有还在队列中的任务都已被丢弃。
+    ///
+    /// 任务从 "调度"通道接收器中弹出。
+    /// 在通道上接收到一个任务标志着该任务已经准备好被执行。
+    /// 这发生在任务第一次被创建和它的唤醒者被使用时。
+    fn run(&self) {
+        let shared = &self.handle.shared;
+
+        if !shared.multi_thread {
+            // 单线程模式：任务在调用`run`的线程上执行。没有其他工作线程会从它的本地队列中窃取。
+            let _hooks = ThreadHooks::enter(shared);
+            let counters = shared.worker_metrics.read().unwrap()[0].clone().unwrap();
+            run_worker(WorkerContext {
+                shared: shared.clone(),
+                index: 0,
+                counters,
+                // 单线程模式下不能固定任务，这个队列总是空的。
+                pinned: PinnedQueue::default(),
+                local: deque::Worker::new_fifo(),
+                lifo: Cell::new(None),
+                lifo_polls: Cell::new(0),
+                tick: Cell::new(0),
+                normal_tick: Cell::new(0),
+                scheduled: self.scheduled.clone(),
+            });
+        } else {
+            // 多线程模式：任务由工作线程执行，这里只需等待关闭，然后等所有工作线程退出。
+            // 关闭信号通道上从不发送消息，`recv`在`shutdown`丢弃发送端时返回。
+            let _ = shared.shutdown_rx.recv();
+
+            // 等待期间可能有`block_in_place`创建的替代工作线程加入，所以一直取到列表为空。
+            loop {
+                let workers = std::mem::take(&mut *shared.workers.lock().unwrap());
+                if workers.is_empty() {
+                    break;
+                }
+
+                for worker in workers {
+                    let _ = worker.join();
+                }
+            }
+        }
+
+        // 关闭已被请求。丢弃所有还在队列中的任务，连同它们的未来一起释放。
+        while self.scheduled.try_recv().is_ok() {}
+        while shared.high_scheduled.try_recv().is_ok() {}
+        while shared.low_scheduled.try_recv().is_ok() {}
+        shared.overflow.lock().unwrap().clear();
 
-    // Wait for the duration to complete.
-    future.await;
+        self.handle.resume_unhandled_panic();
+    }
 }
 
-// 用于跟踪当前的mini-tokio实例，以便`spawn'函数能够安排催生的任务。
-thread_local! {
-    static CURRENT: RefCell<Option<channel::Sender<Arc<Task>>>> =
-        RefCell::new(None);
+impl Drop for MiniTokio {
+    fn drop(&mut self) {
+        // 多线程模式下工作线程各自持有调度通道的接收端，不关闭的话它们永远不会退出。
+        // 这里只发出关闭信号而不等待它们：`MiniTokio`可能是在某个工作线程上被丢弃的。
+        self.handle.shutdown();
+
+        // 关闭之后任务不会再被轮询。丢弃还在队列中的任务，再丢弃其余仍然存活的任务的未来，
+        // 这样它们捕获的资源的析构函数现在就会运行，而不是等到最后一个唤醒者被丢弃。
+        let shared = &self.handle.shared;
+        while self.scheduled.try_recv().is_ok() {}
+        while shared.high_scheduled.try_recv().is_ok() {}
+        while shared.low_scheduled.try_recv().is_ok() {}
+        let overflow = std::mem::take(&mut *shared.overflow.lock().unwrap());
+        drop(overflow);
+        shared.close_tasks();
+    }
 }
 
-// 任务。包含未来以及未来被唤醒后安排的必要数据。
-struct Task {
-    // 未来被一个 "Mutex "包裹着，使 "任务 "结构 "同步"。
-    // 只有一个线程试图使用`future`。
-    // Tokio运行时通过使用 "不安全 "代码来避免mutex。盒子也被避免了。
-    future: Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>,
+/// `MiniTokio::shutdown_timeout`的结果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ShutdownSummary {
+    /// 在期限内完成（包括panic）的任务数。
+    pub completed: u64,
+    /// 期限到时还没有完成、未来随着关闭被丢弃的任务数。
+    pub abandoned: u64,
+    /// 期限到时还在执行或者还在排队的阻塞闭包数。正在执行的闭包所在的线程在后台脱离运行。
+    pub blocking_abandoned: usize,
+}
 
-    // 当一个任务被通知时，它被排入这个通道。
-    // 执行者会弹出被通知的任务并执行它们。
-    executor: channel::Sender<Arc<Task>>,
+/// 用显式的配置创建`MiniTokio`。
+///
+/// ```ignore
+/// let mini_tokio = Builder::new()
+///     .worker_threads(4)
+///     .max_blocking_threads(8)
+///     .thread_name("my-worker")
+///     .build()?;
+/// ```
+///
+/// 无效的配置不会在设置时panic，而是由`build`返回错误。
+struct Builder {
+    // 工作线程的数量。为`None`时是单线程模式，任务由调用`run`的线程执行。
+    worker_threads: Option<usize>,
+    // 阻塞线程池中最多同时存在的线程数。
+    max_blocking_threads: usize,
+    // 空闲的阻塞线程在退出之前等待新闭包的时间。
+    thread_keep_alive: Duration,
+    // 运行时创建的线程的名字。
+    thread_name: String,
+    // 运行时创建的线程的栈大小。为`None`时使用标准库的默认值。
+    thread_stack_size: Option<usize>,
+    // 调度通道的容量。为`None`时调度通道没有容量限制。
+    max_queue_depth: Option<usize>,
+    // 调度通道已满时催生任务怎么做。
+    spawn_backpressure: SpawnBackpressure,
+    // 工作线程每执行多少个普通优先级的任务先查看一次调度通道。
+    global_queue_interval: usize,
+    // 同时存活的任务数的上限。为`None`时没有限制。
+    max_tasks: Option<usize>,
+    // 脱离的任务panic时怎么做。
+    unhandled_panic: UnhandledPanic,
+    // 任务panic时调用的回调。
+    on_task_panic: Option<TaskPanicHook>,
+    // 检测到停滞时调用的回调，以及停滞持续多久才报告。
+    on_deadlock: Option<DeadlockHook>,
+    deadlock_timeout: Duration,
+    // 线程开始和结束时调用的回调。
+    on_thread_start: Option<ThreadHook>,
+    on_thread_stop: Option<ThreadHook>,
+    // 工作线程绑定的CPU核。
+    pin_workers: Option<Vec<usize>>,
+    // 接收警告的回调。
+    on_warning: Option<WarningHook>,
+    // 缓慢的轮询的阈值和回调。
+    slow_poll_threshold: Option<Duration>,
+    on_slow_poll: Option<SlowPollHook>,
+    // 定时器使用的时钟。
+    clock: Arc<dyn Clock>,
 }
 
-impl Task {
-    // Spawns a new taks with the given future.
+impl Builder {
+    /// 返回默认的配置：单线程模式，最多4个阻塞线程，空闲的阻塞线程保留10秒，
+    /// 线程名为"mini-tokio-worker"，使用默认的栈大小。
+    fn new() -> Builder {
+        Builder {
+            worker_threads: None,
+            max_blocking_threads: 4,
+            thread_keep_alive: Duration::from_secs(10),
+            thread_name: "mini-tokio-worker".to_string(),
+            thread_stack_size: None,
+            max_queue_depth: None,
+            spawn_backpressure: SpawnBackpressure::Error,
+            global_queue_interval: 31,
+            max_tasks: None,
+            unhandled_panic: UnhandledPanic::Ignore,
+            on_task_panic: None,
+            on_deadlock: None,
+            deadlock_timeout: Duration::from_secs(1),
+            on_thread_start: None,
+            on_thread_stop: None,
+            pin_workers: None,
+            on_warning: None,
+            slow_poll_threshold: Some(Duration::from_millis(100)),
+            on_slow_poll: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
 
-    // 初始化一个新的包含给定未来的任务束，并将其推送给`sender`。通道的接收方将获得该任务并执行它。
-    fn spawn<F>(future: F, sender: &channel::Sender<Arc<Task>>)
-    where
-        F: Future<Output = ()> + Send + 'static,
-    {
-        let task = Arc::new(Task {
-            future: Mutex::new(Box::pin(future)),
-            executor: sender.clone(),
-        });
+    /// 设置工作线程的数量，运行时变为多线程模式。
+    ///
+    /// 不调用这个方法时是单线程模式，任务由调用`run`的线程执行。
+    /// `n`为零时`build`返回错误。
+    fn worker_threads(&mut self, n: usize) -> &mut Self {
+        self.worker_threads = Some(n);
+        self
+    }
 
-        let _ = sender.send(task);
+    /// 设置阻塞线程池中最多同时存在的线程数。
+    ///
+    /// 所有阻塞线程都在忙碌且数量已经达到上限时，新的`spawn_blocking`闭包会排队，
+    /// 按提交的顺序等待空闲下来的线程。默认值是4：这个小运行时面向的是少量的阻塞调用，
+    /// 同时执行大量阻塞闭包的程序应该调大它。
+    ///
+    /// `n`为零时`build`返回错误。
+    fn max_blocking_threads(&mut self, n: usize) -> &mut Self {
+        self.max_blocking_threads = n;
+        self
     }
 
-    // 执行一个计划任务。这将创建必要的`task::Context`，包含任务的waker。
-    // 这个waker将任务推送到mini-redis计划通道上。然后用waker轮询未来。
-    fn poll(self: Arc<Self>) {
-        // Get a waker referencing the task.
-        let waker = task::waker(self.clone());
-        // Initialize the task context with the waker.
-        let mut cx = Context::from_waker(&waker);
+    /// 设置空闲的阻塞线程在退出之前等待新闭包的时间。
+    fn thread_keep_alive(&mut self, duration: Duration) -> &mut Self {
+        self.thread_keep_alive = duration;
+        self
+    }
 
-        // This will never block as only a single thread ever locks the future.
-        let mut future = self.future.try_lock().unwrap();
+    /// 设置运行时创建的工作线程和阻塞线程的名字，它们会以这个名字出现在调试器和panic消息中。
+    fn thread_name(&mut self, name: &str) -> &mut Self {
+        self.thread_name = name.to_string();
+        self
+    }
 
-        // Poll the future
-        let _ = future.as_mut().poll(&mut cx);
+    /// 设置运行时创建的工作线程和阻塞线程的栈大小，以字节为单位。
+    ///
+    /// `size`为零时`build`返回错误。
+    fn thread_stack_size(&mut self, size: usize) -> &mut Self {
+        self.thread_stack_size = Some(size);
+        self
     }
-}
+
+    /// 限制调度通道中最多排队的任务数。默认没有限制。
+    ///
+    /// 调度通道已满时，`Handle::spawn`返回`SpawnError::QueueFull`而不是阻塞，
+    /// 这样催生任务的风暴不会让内存无限增长。已经存在的任务被唤醒时不受限制：
+    /// 唤醒者不能阻塞，所以它们暂存在一个溢出列表中，等调度通道腾出位置。
+    /// 需要让催生任务的一方等待而不是失败时，见`spawn_backpressure`。
+    ///
+    /// `depth`为零时`build`返回错误。
+    fn max_queue_depth(&mut self, depth: usize) -> &mut Self {
+        self.max_queue_depth = Some(depth);
+        self
+    }
+
+    /// 限制同时存活的任务数（已经催生、还没有完成也没有被取消的任务）。默认没有限制。
+    ///
+    /// 达到上限之后，`Handle::spawn`返回`SpawnError::AtCapacity`，`spawn`和`MiniTokio::spawn`panic，
+    /// 直到有任务完成或者被取消。任务的名额在它的未来被丢弃时归还，而不是在任务本身被释放时：
+    /// 已经完成的任务可能还被某个唤醒者引用着。`spawn_blocking`和`spawn_local`催生的任务不受限制。
+    ///
+    /// `max`为零时`build`返回错误。
+    fn max_tasks(&mut self, max: usize) -> &mut Self {
+        self.max_tasks = Some(max);
+        self
+    }
+
+    /// 设置调度通道已满时（见`max_queue_depth`）催生任务的行为。默认是`SpawnBackpressure::Error`。
+    fn spawn_backpressure(&mut self, behavior: SpawnBackpressure) -> &mut Self {
+        self.spawn_backpressure = behavior;
+        self
+    }
+
+    /// 设置工作线程每执行多少个普通优先级的任务，先查看一次共享的调度通道，默认是31。
+    ///
+    /// 工作线程平时先执行LIFO槽和本地队列中的任务（在这个线程上被唤醒的任务），
+    /// 只有它们都为空时才查看调度通道。新催生的任务和从其他线程唤醒的任务在调度通道中排队，
+    /// 这个间隔保证它们不会因为本地队列一直不为空而永远得不到执行。
+    /// 更小的值对调度通道中的任务更公平，更大的值让本地的任务更少被打断。
+    ///
+    /// `interval`为零时`build`返回错误。
+    fn global_queue_interval(&mut self, interval: usize) -> &mut Self {
+        self.global_queue_interval = interval;
+        self
+    }
+
+    /// 设置运行时中的定时器（`delay`、`sleep`、`Interval`、`DelayQueue`和`timeout`）使用的时钟。
+    ///
+    /// 默认使用真实的时间。测试中传入一个`MockClock`，时间就只在调用`MockClock::advance`时前进，
+    /// 等待10秒的任务不需要真的等待10秒。运行时本身的计时（阻塞线程的`thread_keep_alive`、
+    /// `shutdown_timeout`的期限和统计数据中的忙碌时间）不受影响。
+    fn clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// 设置脱离的任务（`JoinHandle`已经被丢弃的任务）panic时运行时的行为。默认是`UnhandledPanic::Ignore`。
+    ///
+    /// 还有人持有`JoinHandle`的任务不受影响：panic总是通过`JoinError::Panic`交给等待句柄的人。
+    fn unhandled_panic(&mut self, behavior: UnhandledPanic) -> &mut Self {
+        self.unhandled_panic = behavior;
+        self
+    }
+
+    /// 设置一个在任务panic时调用的回调，例如用来记录那些没有人等待的任务的崩溃。
+    ///
+    /// 回调在panic被捕获之后、在panic的那个线程上被调用，无论任务是否已经脱离，
+    /// 也在`UnhandledPanic`生效之前。默认没有回调：panic消息照常由标准库的panic钩子打印到标准错误，
+    /// 和tokio一样。
+    fn on_task_panic<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&TaskPanicInfo<'_>) + Send + Sync + 'static,
+    {
+        self.on_task_panic = Some(Arc::new(f));
+        self
+    }
+
+    /// 设置一个在运行时停滞时调用的回调：所有任务都在等待，却没有任何东西还能唤醒它们。
+    ///
+    /// 所有工作线程都找不到任务、准备休眠时，运行时检查是否还有存活的任务，以及是否还有可能唤醒它们的来源：
+    /// 排队的任务、还没有到期的定时器、在I/O驱动中登记的套接字、阻塞线程池中的闭包、
+    /// 以及正在`MiniTokio::block_on`中的根未来。存活的任务都在等待、而这些来源都不存在时，
+    /// `run`会永远阻塞下去，回调得到这时所有任务的`TaskDump`。同一次停滞只报告一次，直到又有任务被轮询。
+    ///
+    /// 运行时看不到它之外的线程：一个普通线程手中的唤醒者、通道的发送者或者`Handle`都可能在稍后唤醒任务，
+    /// 运行时无法把这和真正的停滞区分开。所以停滞要持续`deadlock_timeout`，期间没有任何任务被轮询，才会被报告；
+    /// 之后才唤醒任务的外部线程仍然会造成误报，回调不应该假定报告一定是真的死锁。
+    /// 检测默认是关闭的，只在设置了回调（或者启用了`debug-deadlock`特性）时进行。
+    /// 启用了`debug-deadlock`特性而没有设置回调时，`TaskDump`通过`on_warning`报告。
+    /// 运行时不会因为报告而被关闭，需要的话由回调调用`Handle::shutdown`。
+    fn on_deadlock<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&TaskDump) + Send + Sync + 'static,
+    {
+        self.on_deadlock = Some(Arc::new(f));
+        self
+    }
+
+    /// 设置停滞持续多久才被报告，默认是1秒，见`on_deadlock`。
+    ///
+    /// 最后一个准备休眠的工作线程看到停滞时只休眠这么久，醒来时如果还没有任何任务被轮询过才报告。
+    /// 时间越短，被外部线程稍后唤醒的任务越容易被误报。`timeout`为零时`build`返回错误。
+    fn deadlock_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.deadlock_timeout = timeout;
+        self
+    }
+
+    /// 设置一个在执行运行时的工作的线程开始时调用的回调，例如用来设置线程本地的状态。
+    ///
+    /// 回调在每个工作线程和阻塞线程上、在它执行任何任务或者闭包之前被调用。
+    /// 调用`run`（单线程模式）和`MiniTokio::block_on`的线程在这里轮询任务，所以每次调用时也会在那个线程上调用它。
+    /// 回调panic时panic被捕获并报告到标准错误，线程照常继续。
+    fn on_thread_start<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_thread_start = Some(Arc::new(f));
+        self
+    }
+
+    /// 设置一个在执行运行时的工作的线程结束之前调用的回调，和`on_thread_start`对应。
+    ///
+    /// 工作线程和阻塞线程退出之前、`run`和`MiniTokio::block_on`返回之前调用它。回调panic时的处理和`on_thread_start`相同。
+    fn on_thread_stop<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_thread_stop = Some(Arc::new(f));
+        self
+    }
+
+    /// 把每个工作线程绑定到一个CPU核上，避免线程在核之间迁移带来的延迟抖动。
+    ///
+    /// 第`i`个工作线程绑定到`cores[i % cores.len()]`：工作线程比给定的核多时从头开始循环，
+    /// 之后通过`Handle::add_worker`增加的工作线程也一样。定时器线程绑定到紧接着最后一个工作线程的那个核。
+    /// 只有启用了`affinity`特性、在Linux上才会生效；其他平台上`build`通过`on_warning`回调报告一个警告，
+    /// 运行时照常创建，线程不被绑定。绑定某个线程失败时（例如核的编号不存在）也只是报告一个警告。
+    ///
+    /// `cores`为空时`build`返回错误。
+    fn pin_workers(&mut self, cores: Vec<usize>) -> &mut Self {
+        self.pin_workers = Some(cores);
+        self
+    }
+
+    /// 设置一个接收警告的回调：运行时遇到不影响它继续运行的问题时调用，例如`pin_workers`不被支持。
+    ///
+    /// 默认把警告打印到标准错误。
+    fn on_warning<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_warning = Some(Arc::new(f));
+        self
+    }
+
+    /// 设置一次轮询超过多久算作缓慢，默认是100毫秒。
+    ///
+    /// 一个任务的某一次轮询超过这个时间时，运行时调用`on_slow_poll`回调，没有设置回调时通过`on_warning`报告一个警告。
+    /// 这通常意味着任务在未来中做了阻塞的工作（同步的I/O、`thread::sleep`、长时间的计算），
+    /// 期间这个工作线程上的其他任务都得不到执行。这样的工作应该交给`spawn_blocking`或者`task::block_in_place`。
+    ///
+    /// 只测量任务的轮询，`block_on`的未来、`spawn_blocking`的闭包和`LocalSet`中的任务不在此列。
+    /// `threshold`为零时`build`返回错误。
+    fn slow_poll_threshold(&mut self, threshold: Duration) -> &mut Self {
+        self.slow_poll_threshold = Some(threshold);
+        self
+    }
+
+    /// 关闭缓慢的轮询的检测，见`slow_poll_threshold`。之后轮询任务时不再读取时间。
+    fn disable_slow_poll_detection(&mut self) -> &mut Self {
+        self.slow_poll_threshold = None;
+        self
+    }
+
+    /// 设置一个在一次轮询超过`slow_poll_threshold`时调用的回调，代替默认的警告。
+    ///
+    /// 回调得到任务的编号和名字以及这次轮询用去的时间，在轮询之后、在轮询任务的那个线程上被调用。
+    fn on_slow_poll<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&TaskMeta<'_>, Duration) + Send + Sync + 'static,
+    {
+        self.on_slow_poll = Some(Arc::new(f));
+        self
+    }
+
+    /// 创建配置好的运行时。多线程模式下工作线程立即开始运行。
+    ///
+    /// 配置无效时返回`ErrorKind::InvalidInput`错误，无法创建工作线程时返回创建线程的错误。
+    fn build(&mut self) -> io::Result<MiniTokio> {
+        if self.worker_threads == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "worker_threads cannot be set to 0",
+            ));
+        }
+
+        if self.max_blocking_threads == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_blocking_threads cannot be set to 0",
+            ));
+        }
+
+        if self.max_tasks == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_tasks cannot be set to 0",
+            ));
+        }
+
+        if self.global_queue_interval == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "global_queue_interval cannot be set to 0",
+            ));
+        }
+
+        if self.slow_poll_threshold == Some(Duration::ZERO) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "slow_poll_threshold cannot be set to 0",
+            ));
+        }
+
+        if self.deadlock_timeout == Duration::ZERO {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "deadlock_timeout cannot be set to 0",
+            ));
+        }
+
+        if self.thread_stack_size == Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "thread_stack_size cannot be set to 0",
+            ));
+        }
+
+        let (sender, scheduled) = match self.max_queue_depth {
+            Some(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "max_queue_depth cannot be set to 0",
+                ));
+            }
+            Some(depth) => channel::bounded(depth),
+            None => channel::unbounded(),
+        };
+        let (shutdown_tx, shutdown_rx) = channel::bounded(0);
+
+        let pin_workers = match &self.pin_workers {
+            Some(cores) if cores.is_empty() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "pin_workers cannot be given an empty list of cores",
+                ));
+            }
+            Some(_) if !affinity::SUPPORTED => {
+                report_warning(
+                    &self.on_warning,
+                    "pinning threads to CPUs is not supported on this platform; \
+                     worker threads are not pinned",
+                );
+                None
+            }
+            pin_workers => pin_workers.clone(),
+        };
+
+        let locals: Vec<_> = (0..self.worker_threads.unwrap_or(0))
+            .map(|_| deque::Worker::new_fifo())
+            .collect();
+        let counters: Vec<_> = (0..self.worker_threads.unwrap_or(1))
+            .map(|_| Arc::new(WorkerCounters::default()))
+            .collect();
+        let pinned: Vec<_> = locals.iter().map(|_| PinnedQueue::default()).collect();
+
+        let (high_sender, high_scheduled) = channel::unbounded();
+        let (low_sender, low_scheduled) = channel::unbounded();
+
+        let shared = Arc::new(Shared {
+            sender,
+            scheduled: scheduled.clone(),
+            high_sender,
+            high_scheduled,
+            low_sender,
+            low_scheduled,
+            overflow: Mutex::new(VecDeque::new()),
+            tasks: Mutex::new(HashMap::new()),
+            worker_metrics: RwLock::new(counters.iter().cloned().map(Some).collect()),
+            tasks_spawned: AtomicU64::new(0),
+            tasks_completed: AtomicU64::new(0),
+            total_polls: AtomicU64::new(0),
+            tasks_alive: AtomicU64::new(0),
+            wakeups_deduplicated: AtomicU64::new(0),
+            is_shutdown: AtomicBool::new(false),
+            is_closed: AtomicBool::new(false),
+            shutdown_rx,
+            shutdown_tx: Mutex::new(Some(shutdown_tx)),
+            multi_thread: self.worker_threads.is_some(),
+            stealers: RwLock::new(locals.iter().map(|local| Some(local.stealer())).collect()),
+            live_workers: AtomicUsize::new(locals.len()),
+            retiring: AtomicUsize::new(0),
+            pinned: RwLock::new(pinned.iter().cloned().map(Some).collect()),
+            next_pinned: AtomicUsize::new(0),
+            tasks_repinned: AtomicU64::new(0),
+            idle: Mutex::new(IdleState::default()),
+            idle_condvar: Condvar::new(),
+            blocking: BlockingPool::new(self.max_blocking_threads, self.thread_keep_alive),
+            workers: Mutex::new(Vec::new()),
+            thread_name: self.thread_name.clone(),
+            thread_stack_size: self.thread_stack_size,
+            on_thread_start: self.on_thread_start.clone(),
+            on_thread_stop: self.on_thread_stop.clone(),
+            pin_workers,
+            on_warning: self.on_warning.clone(),
+            slow_poll_threshold: self.slow_poll_threshold,
+            on_slow_poll: self.on_slow_poll.clone(),
+            spawn_backpressure: self.spawn_backpressure,
+            global_queue_interval: self.global_queue_interval,
+            max_tasks: self.max_tasks.map(|max| max as u64),
+            unhandled_panic: self.unhandled_panic,
+            panic_payload: Mutex::new(None),
+            on_task_panic: self.on_task_panic.clone(),
+            on_deadlock: self.on_deadlock.clone(),
+            deadlock_timeout: self.deadlock_timeout,
+            deadlock_reported_at: AtomicU64::new(u64::MAX),
+            blocked_on: AtomicUsize::new(0),
+            clock: self.clock.clone(),
+        });
+
+        // 先创建运行时再启动工作线程：某个线程创建失败时，提前返回会丢弃运行时，
+        // 已经启动的工作线程随之收到关闭信号并退出。
+        let mini_tokio = MiniTokio {
+            scheduled: scheduled.clone(),
+            handle: Handle {
+                shared: shared.clone(),
+            },
+        };
+
+        if let Some(cores) = &shared.pin_workers {
+            let core = affinity::core_for(cores, locals.len());
+            if let Err(err) = affinity::pin_timer(core) {
+                shared.warn(&format!(
+                    "failed to pin the timer thread to CPU {}: {}",
+                    core, err
+                ));
+            }
+        }
+
+        let workers = locals.into_iter().zip(counters).zip(pinned);
+        for (index, ((local, counters), pinned)) in workers.enumerate() {
+            shared.spawn_worker(WorkerContext {
+                shared: shared.clone(),
+                index,
+                counters,
+                pinned,
+                local,
+                lifo: Cell::new(None),
+                lifo_polls: Cell::new(0),
+                tick: Cell::new(0),
+                normal_tick: Cell::new(0),
+                scheduled: scheduled.clone(),
+            })?;
+        }
+
+        Ok(mini_tokio)
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+/// 脱离的任务panic时运行时的行为，见`Builder::unhandled_panic`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnhandledPanic {
+    /// 忽略panic，运行时和其他任务继续运行。panic消息仍然由panic钩子打印出来。
+    Ignore,
+    /// 关闭运行时：不再轮询任何任务，还在队列中的任务被丢弃。
+    /// 正在驱动运行时的`run`或者`block_on`随后带着同一个载荷重新panic，让这个错误无法被忽视。
+    ShutdownRuntime,
+}
+
+/// 传给`set_unhandled_panic_hook`设置的钩子和`Builder::on_slow_poll`回调的任务信息。
+#[derive(Debug, Clone, Copy)]
+pub struct TaskMeta<'a> {
+    id: task::Id,
+    name: Option<&'a str>,
+}
+
+impl TaskMeta<'_> {
+    /// 任务的编号，和它的`JoinHandle::id`相同。
+    pub fn id(&self) -> task::Id {
+        self.id
+    }
+
+    /// 任务的名字，见`task::Builder::name`。没有名字的任务返回`None`。
+    pub fn name(&self) -> Option<&str> {
+        self.name
+    }
+}
+
+// `set_unhandled_panic_hook`设置的钩子。
+type UnhandledPanicHook = Arc<dyn Fn(&TaskMeta<'_>, Box<dyn Any + Send>) + Send + Sync>;
+
+static UNHANDLED_PANIC_HOOK: RwLock<Option<UnhandledPanicHook>> = RwLock::new(None);
+
+/// 设置一个进程范围的钩子，在一个任务panic、而且没有任何`JoinHandle`会看到这次panic时调用。
+///
+/// 钩子得到任务的编号和名字，以及panic的载荷。它对所有运行时中的任务都生效，包括`spawn_blocking`和`spawn_local`
+/// 催生的任务，新的钩子替换之前设置的那个。钩子在捕获panic的那个线程上被调用：通常是轮询那个任务的工作线程，
+/// 任务在句柄被丢弃之前就已经panic时则是丢弃句柄的线程。钩子本身panic时panic被捕获并报告到标准错误，
+/// 执行器不受影响。
+///
+/// 按照`UnhandledPanic::ShutdownRuntime`，钩子先被调用，然后运行时被关闭。载荷已经交给了钩子，
+/// 所以驱动运行时的线程重新抛出的是一个携带同样消息的载荷（消息不是字符串时是一条通用的消息）。
+pub fn set_unhandled_panic_hook<F>(hook: F)
+where
+    F: Fn(&TaskMeta<'_>, Box<dyn Any + Send>) + Send + Sync + 'static,
+{
+    *UNHANDLED_PANIC_HOOK.write().unwrap() = Some(Arc::new(hook));
+}
+
+// 一个没有人会看到的任务panic：先交给`set_unhandled_panic_hook`的钩子，再按照运行时的`UnhandledPanic`处理。
+// `spawn_blocking`和`spawn_local`的任务没有`executor`，它们的panic只会交给钩子。
+fn unhandled_task_panic(
+    executor: Option<Handle>,
+    meta: &TaskMeta<'_>,
+    payload: Box<dyn Any + Send>,
+) {
+    // 调用钩子时不持有锁，这样钩子里可以设置新的钩子。
+    let hook = UNHANDLED_PANIC_HOOK.read().unwrap().clone();
+
+    let payload = match hook {
+        None => payload,
+        Some(hook) => {
+            let replacement = match &executor {
+                Some(executor)
+                    if executor.shared.unhandled_panic == UnhandledPanic::ShutdownRuntime =>
+                {
+                    let replacement: Box<dyn Any + Send> = match payload.downcast_ref::<&str>() {
+                        Some(message) => Box::new(*message),
+                        None => match payload.downcast_ref::<String>() {
+                            Some(message) => Box::new(message.clone()),
+                            None => Box::new("a detached task panicked"),
+                        },
+                    };
+                    Some(replacement)
+                }
+                _ => None,
+            };
+
+            if panic::catch_unwind(AssertUnwindSafe(|| hook(meta, payload))).is_err() {
+                eprintln!("mini-tokio: the unhandled panic hook panicked");
+            }
+
+            match replacement {
+                Some(replacement) => replacement,
+                None => return,
+            }
+        }
+    };
+
+    if let Some(executor) = executor {
+        executor.unhandled_panic(payload);
+    }
+}
+
+/// 调度通道已满时催生任务的行为，见`Builder::spawn_backpressure`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnBackpressure {
+    /// `Handle::spawn`返回`SpawnError::QueueFull`，`spawn`和`MiniTokio::spawn`panic。
+    Error,
+    /// 在驱动运行时的线程上（工作线程、`run`或者`MiniTokio::block_on`的线程）催生任务时，
+    /// 新任务像被唤醒的任务一样暂存在溢出列表中，等调度通道腾出位置后再被移入；
+    /// 同时催生它的任务用完了这次轮询的预算，在下一个资源操作（通道、定时器、`yield_now`等）处让出执行器。
+    /// 一个不停催生任务的任务因此会被它自己催生的任务拖慢。溢出列表的长度取决于它在两次让出之间催生了多少任务，
+    /// 一个从不等待任何东西的循环仍然会让它无限增长。
+    ///
+    /// 在其他线程上催生任务时和`Error`一样：那里没有可以执行任务的执行器。
+    YieldAndRetry,
+}
+
+/// 传给`Builder::on_task_panic`回调的panic信息。
+pub struct TaskPanicInfo<'a> {
+    payload: &'a (dyn Any + Send),
+    name: Option<&'a str>,
+}
+
+impl TaskPanicInfo<'_> {
+    /// panic的载荷。
+    pub fn payload(&self) -> &(dyn Any + Send) {
+        self.payload
+    }
+
+    /// panic的消息。载荷是`panic!`产生的字符串时返回它，否则返回`None`。
+    pub fn message(&self) -> Option<&str> {
+        match self.payload.downcast_ref::<&str>() {
+            Some(message) => Some(message),
+            None => self.payload.downcast_ref::<String>().map(String::as_str),
+        }
+    }
+
+    /// panic的任务的名字，见`task::Builder::name`。没有名字的任务返回`None`。
+    pub fn task_name(&self) -> Option<&str> {
+        self.name
+    }
+}
+
+// 执行者循环，被单线程模式下的`run`和多线程模式下的每个工作线程共用。
+// 一直运行到关闭被请求，或者本地队列被`block_in_place`交给了替代的工作线程。
+fn run_worker(context: WorkerContext) {
+    let handle = Handle {
+        shared: context.shared.clone(),
+    };
+
+    // 设置CURRENT thread-local，使其指向当前的执行器。
+    // Tokio使用线程本地变量来实现`tokio::spwn`。
+    // 当进入运行时，执行器用线程-本地存储必要的上下文，以支持催生新任务。
+    // 每个工作线程都有自己的线程本地变量，所以每个线程都需要设置一次。
+    // 单线程模式下`run`可能在另一个运行时的线程上被调用，守卫在返回时恢复那个运行时。
+    let _enter = handle.enter();
+
+    // 本地队列放进`WORKER`中，在这个线程上发生的唤醒会把任务推入其中。
+    WORKER.with(|cell| {
+        *cell.borrow_mut() = Some(Rc::new(context));
+    });
+
+    // 执行者循环。预定的任务被接收。
+    // 如果没有可以执行的任务，线程就会park，直到有任务被推入某个队列或者关闭被请求。
+    loop {
+        // 每次执行任务前都检查关闭状态，保证关闭之后不会再有任务被轮询。
+        if handle.is_shutdown() {
+            break;
+        }
+
+        // 上一个任务调用了`block_in_place`，本地队列已经交给了替代的工作线程，这个线程不再是工作线程。
+        let context = match WORKER.with(|cell| cell.borrow().clone()) {
+            Some(context) => context,
+            None => return,
+        };
+
+        // 运行时要减少一个工作线程，由这个线程来退休。
+        if handle.shared.take_retirement() {
+            WORKER.with(|cell| *cell.borrow_mut() = None);
+            context.retire();
+            return;
+        }
+
+        // 依次查看本地队列、调度通道和其他工作线程的本地队列。
+        // 找不到任务时阻塞等待。
+        let counters = context.counters.clone();
+
+        let task = match context.next_task() {
+            Some(task) => Some(task),
+            None => context.park(),
+        };
+
+        // 轮询任务时不能持有上下文的引用，否则`block_in_place`无法把它取走。
+        drop(context);
+
+        if let Some(task) = task {
+            // 执行任务，直到它完成或无法取得进一步进展，并返回`Poll::Pending`。
+            let start = Instant::now();
+            task.poll();
+            counters.record_poll(start.elapsed());
+        }
+    }
+
+    WORKER.with(|cell| {
+        *cell.borrow_mut() = None;
+    });
+}
+
+impl Shared {
+    // 丢弃所有仍然存活的任务的未来。只在关闭之后调用，被关闭的任务之后的唤醒都被忽略。
+    fn close_tasks(&self) {
+        // 先取出任务再逐个关闭：丢弃未来可能会释放其他任务，它们在被释放时需要锁住注册表。
+        let tasks: Vec<_> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .drain()
+            .filter_map(|(_, task)| task.upgrade())
+            .collect();
+
+        for task in tasks {
+            task.close();
+        }
+    }
+
+    // 在每次从调度通道中取走一个任务之后调用，把最早的溢出任务移进调度通道。
+    //
+    // 唤醒者在持有溢出列表的锁时尝试发送，这里也在持有锁时移动任务，所以只要溢出列表不为空，
+    // 调度通道就是满的，等待调度通道的执行器不会错过溢出列表中的任务。
+    fn refill(&self) {
+        // 无界的调度通道从不溢出。
+        if self.sender.capacity().is_none() {
+            return;
+        }
+
+        let mut overflow = self.overflow.lock().unwrap();
+
+        if let Some(task) = overflow.pop_front() {
+            if let Err(channel::TrySendError::Full(task)) = self.sender.try_send(task) {
+                overflow.push_front(task);
+            }
+        }
+    }
+
+    // 有新的任务可以执行了，叫醒至多一个空闲的工作线程。
+    //
+    // 必须在任务被推入队列之后调用，而且调用时不能持有溢出列表的锁（工作线程在持有`idle`的锁时会查看它）。
+    // 空闲的线程在持有`idle`的锁时最后查看一遍队列才开始等待，所以它要么看到这个任务，
+    // 要么已经在等待、在这里被叫醒：任务不会在两者之间丢失。
+    fn unpark_one(&self) {
+        let mut idle = self.idle.lock().unwrap();
+
+        if idle.sleeping > 0 {
+            idle.sleeping -= 1;
+            idle.notified += 1;
+            self.idle_condvar.notify_one();
+        }
+    }
+
+    // 叫醒所有空闲的工作线程，让它们看到关闭。
+    fn unpark_all(&self) {
+        let _idle = self.idle.lock().unwrap();
+        self.idle_condvar.notify_all();
+    }
+
+    // 轮流选择一个还存在的工作线程，返回它的编号，见`Handle::spawn_pinned_auto`。
+    fn pick_worker(&self, queues: &[Option<PinnedQueue>]) -> Option<usize> {
+        let alive: Vec<_> = (0..queues.len()).filter(|&i| queues[i].is_some()).collect();
+        if alive.is_empty() {
+            return None;
+        }
+
+        let next = self.next_pinned.fetch_add(1, Ordering::Relaxed);
+        Some(alive[next % alive.len()])
+    }
+
+    // 报告一个警告，见`Builder::on_warning`。
+    fn warn(&self, message: &str) {
+        report_warning(&self.on_warning, message);
+    }
+
+    // 在所有工作线程都准备休眠时调用。运行时已经停滞时返回所有任务的快照，见`Builder::on_deadlock`。
+    fn detect_deadlock(self: &Arc<Self>) -> Option<TaskDump> {
+        if self.on_deadlock.is_none() && !cfg!(feature = "debug-deadlock") {
+            return None;
+        }
+
+        // 上次报告之后没有任何任务被轮询过，这还是同一次停滞。
+        let polls = self.total_polls.load(Ordering::Relaxed);
+        if self.deadlock_reported_at.load(Ordering::Relaxed) == polls {
+            return None;
+        }
+
+        let handle = Handle {
+            shared: self.clone(),
+        };
+        let idle = self.tasks_alive.load(Ordering::SeqCst) > 0
+            && self.retiring.load(Ordering::SeqCst) == 0
+            && self.blocked_on.load(Ordering::SeqCst) == 0
+            && self.blocking.pending() == 0
+            && handle.metrics().current_queue_depth == 0
+            && !self.clock.timer().has_pending()
+            && Reactor::registrations() == 0;
+        if !idle {
+            return None;
+        }
+
+        // 还有任务在被轮询（例如在`block_in_place`中）或者在排队时，它可能唤醒其他任务。
+        let dump = handle.dump();
+        let waiting =
+            |task: &TaskInfo| matches!(task.state(), TaskState::Idle | TaskState::Completed);
+        if !dump.tasks().iter().all(waiting) {
+            return None;
+        }
+
+        Some(dump)
+    }
+
+    // 报告一次停滞。没有设置回调时（只可能是启用了`debug-deadlock`特性）报告一个警告。
+    fn report_deadlock(&self, dump: &TaskDump) {
+        match &self.on_deadlock {
+            Some(hook) => hook(dump),
+            None => self.warn(&format!(
+                "deadlock detected, no task can make progress:\n{}",
+                dump
+            )),
+        }
+    }
+
+    // 一个任务的一次轮询用去了`elapsed`，超过了`Builder::slow_poll_threshold`。
+    fn slow_poll(&self, meta: &TaskMeta<'_>, elapsed: Duration) {
+        match &self.on_slow_poll {
+            Some(hook) => hook(meta, elapsed),
+            None => {
+                let task = match meta.name() {
+                    Some(name) => format!("task {} ({:?})", meta.id(), name),
+                    None => format!("task {}", meta.id()),
+                };
+                self.warn(&format!(
+                    "{} took {:?} to poll; is it blocking the worker thread?",
+                    task, elapsed
+                ));
+            }
+        }
+    }
+
+    // 有工作线程等待退休时，认领其中一个名额。返回`true`的工作线程应该退休。
+    fn take_retirement(&self) -> bool {
+        self.retiring
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    // 在一个新线程上运行工作线程的循环，并记录下线程，使`run`能等待它退出。
+    fn spawn_worker(&self, context: WorkerContext) -> io::Result<()> {
+        let worker = self.thread_builder().spawn(move || {
+            if let Some(cores) = &context.shared.pin_workers {
+                let core = affinity::core_for(cores, context.index);
+                if let Err(err) = affinity::pin_current(core) {
+                    context.shared.warn(&format!(
+                        "failed to pin worker {} to CPU {}: {}",
+                        context.index, core, err
+                    ));
+                }
+            }
+
+            let _hooks = ThreadHooks::enter(&context.shared);
+            run_worker(context)
+        })?;
+        self.workers.lock().unwrap().push(worker);
+        Ok(())
+    }
+
+    // 按照`Builder`中的配置准备创建运行时线程。
+    fn thread_builder(&self) -> thread::Builder {
+        let builder = thread::Builder::new().name(self.thread_name.clone());
+
+        match self.thread_stack_size {
+            Some(size) => builder.stack_size(size),
+            None => builder,
+        }
+    }
+}
+
+// 找不到任务的工作线程在park之前让出CPU、再查看一遍队列的次数。
+// 任务常常在几微秒之后就会到来（例如另一个线程刚刚完成一次IO），这时自旋比一次park再被叫醒的往返便宜得多。
+const IDLE_SPINS: usize = 16;
+
+// 工作线程连续从LIFO槽中取出任务的最多次数，和tokio的上限相同。
+const LIFO_SLOT_LIMIT: usize = 3;
+
+// 所有优先级的任务都在排队时，工作线程依次首先查看的优先级：
+// 七个任务中高优先级的占四个，普通优先级的占两个，低优先级的占一个，所以低优先级的任务不会被饿死。
+const PRIORITY_CYCLE: [Priority; 7] = [
+    Priority::High,
+    Priority::Normal,
+    Priority::High,
+    Priority::Low,
+    Priority::High,
+    Priority::Normal,
+    Priority::High,
+];
+
+impl WorkerContext {
+    // 找到下一个要执行的任务，找不到时返回`None`而不阻塞。
+    //
+    // 首先查看`PRIORITY_CYCLE`中轮到的优先级，那里没有任务时按照从高到低的顺序查看其他优先级。
+    fn next_task(&self) -> Option<Arc<Task>> {
+        let tick = self.tick.get();
+        self.tick.set(tick.wrapping_add(1));
+
+        let first = PRIORITY_CYCLE[tick % PRIORITY_CYCLE.len()];
+        let rest = [Priority::High, Priority::Normal, Priority::Low];
+
+        std::iter::once(first)
+            .chain(rest.iter().copied().filter(|&priority| priority != first))
+            .find_map(|priority| match priority {
+                Priority::High => self.shared.high_scheduled.try_recv().ok(),
+                Priority::Normal => self.next_normal_task(),
+                Priority::Low => self.shared.low_scheduled.try_recv().ok(),
+            })
+    }
+
+    // 找到下一个普通优先级的任务：依次查看LIFO槽、本地队列、调度通道和其他工作线程的本地队列。
+    //
+    // 每`global_queue_interval`次先查看一次调度通道：不停唤醒自己或者互相唤醒的任务使本地队列一直不为空时，
+    // 新催生的任务和从其他线程唤醒的任务也能得到执行。
+    fn next_normal_task(&self) -> Option<Arc<Task>> {
+        let tick = self.normal_tick.get();
+        self.normal_tick.set(tick.wrapping_add(1));
+
+        if tick.is_multiple_of(self.shared.global_queue_interval) {
+            if let Ok(task) = self.scheduled.try_recv() {
+                self.shared.refill();
+                return Some(task);
+            }
+        }
+
+        // 固定在这个工作线程上的任务只能在这里执行。
+        if let Some(task) = self.pinned.lock().unwrap().pop_front() {
+            return Some(task);
+        }
+
+        if let Some(task) = self.lifo.take() {
+            // 互相唤醒的任务可以一直占据LIFO槽，本地队列中的任务因此永远轮不到。
+            // 连续使用槽的次数达到上限后，把槽中的任务放回本地队列的末尾。
+            if self.lifo_polls.get() < LIFO_SLOT_LIMIT {
+                self.lifo_polls.set(self.lifo_polls.get() + 1);
+                return Some(task);
+            }
+
+            self.local.push(task);
+        }
+
+        self.lifo_polls.set(0);
+
+        if let Some(task) = self.local.pop() {
+            return Some(task);
+        }
+
+        if let Ok(task) = self.scheduled.try_recv() {
+            self.shared.refill();
+            return Some(task);
+        }
+
+        self.steal()
+    }
+
+    // 找不到任务时的空闲策略：先自旋`IDLE_SPINS`次，每次让出CPU之后再查看一遍队列，
+    // 仍然没有任务时在`idle_condvar`上park。
+    //
+    // 返回自旋期间或者park之前找到的任务。被叫醒或者关闭被请求时返回`None`，由调用者回到循环开始处：
+    // 叫醒它的任务可能已经被另一个工作线程取走了，那样它只会再park一次。
+    fn park(&self) -> Option<Arc<Task>> {
+        let shared = &self.shared;
+
+        for _ in 0..IDLE_SPINS {
+            if shared.is_shutdown.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            thread::yield_now();
+
+            if let Some(task) = self.next_task() {
+                return Some(task);
+            }
+        }
+
+        let mut idle = shared.idle.lock().unwrap();
+
+        // 持有锁时最后查看一遍。之后推入的任务的`unpark_one`要等这里开始等待才能拿到锁，所以一定会叫醒这个线程。
+        // 关闭也一样：`unpark_all`在设置`is_shutdown`之后才拿锁。
+        // 等待退休的名额也一样：`remove_worker`在增加`retiring`之后才调用`unpark_all`。
+        if shared.is_shutdown.load(Ordering::SeqCst) || shared.retiring.load(Ordering::SeqCst) > 0 {
+            return None;
+        }
+
+        if let Some(task) = self.next_task() {
+            return Some(task);
+        }
+
+        self.counters.parks.fetch_add(1, Ordering::Relaxed);
+        idle.sleeping += 1;
+
+        // 这是最后一个准备休眠的工作线程、运行时看起来已经停滞时，只休眠`Builder::deadlock_timeout`：
+        // 运行时之外的线程可能还会唤醒任务。醒来时还没有任何任务被轮询过才报告停滞。
+        let all_sleeping =
+            |sleeping: usize| sleeping >= shared.live_workers.load(Ordering::SeqCst).max(1);
+        let mut stall = None;
+        if all_sleeping(idle.sleeping) && shared.detect_deadlock().is_some() {
+            let polls = shared.total_polls.load(Ordering::Relaxed);
+            stall = Some((Instant::now() + shared.deadlock_timeout, polls));
+        }
+
+        // `idle_condvar`可能会虚假唤醒，只有`notified`被扣除了才说明这个线程确实被通知了。
+        loop {
+            idle = match stall {
+                Some((deadline, _)) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    shared.idle_condvar.wait_timeout(idle, timeout).unwrap().0
+                }
+                None => shared.idle_condvar.wait(idle).unwrap(),
+            };
+
+            if idle.notified > 0 {
+                idle.notified -= 1;
+                return None;
+            }
+
+            // 关闭之后计数不再重要。
+            if shared.is_shutdown.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            // 回到循环开始处认领退休的名额，或者执行固定在这里的任务，见`Handle::push_pinned`。
+            // 没有被通知就醒来，所以自己从`sleeping`中扣除。
+            if shared.retiring.load(Ordering::SeqCst) > 0 || !self.pinned.lock().unwrap().is_empty()
+            {
+                idle.sleeping -= 1;
+                return None;
+            }
+
+            // 回调可能会关闭运行时，关闭需要这把锁，所以先放开它，回到`run_worker`之后再重新休眠。
+            match stall {
+                Some((deadline, polls)) if Instant::now() >= deadline => {
+                    stall = None;
+                    if all_sleeping(idle.sleeping)
+                        && shared.total_polls.load(Ordering::Relaxed) == polls
+                    {
+                        if let Some(dump) = shared.detect_deadlock() {
+                            shared.deadlock_reported_at.store(polls, Ordering::Relaxed);
+                            idle.sleeping -= 1;
+                            drop(idle);
+                            shared.report_deadlock(&dump);
+                            return None;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 退休：把LIFO槽和本地队列中的任务交还给调度通道，然后从窃取的集合和统计数据中移除自己。
+    // 调用之前上下文已经从`WORKER`中取走，之后在这个线程上被唤醒的任务不会再进入本地队列。
+    fn retire(&self) {
+        let handle = Handle {
+            shared: self.shared.clone(),
+        };
+
+        if let Some(task) = self.lifo.take() {
+            handle.push_woken(task);
+        }
+        while let Some(task) = self.local.pop() {
+            handle.push_woken(task);
+        }
+
+        // 其他工作线程可能在上面的过程中从这里窃取了任务，那些任务已经在它们的队列中了。
+        self.shared.stealers.write().unwrap()[self.index] = None;
+        self.shared.worker_metrics.write().unwrap()[self.index] = None;
+
+        // 固定在这里的任务被重新固定到其他工作线程上。在持有写锁时移除队列并取出其中的任务：
+        // 唤醒者在持有读锁时推入任务，所以之后的唤醒都会看到队列已经不在了，自己重新固定任务。
+        let pinned = {
+            let mut queues = self.shared.pinned.write().unwrap();
+            queues[self.index] = None;
+            std::mem::take(&mut *self.pinned.lock().unwrap())
+        };
+        for task in pinned {
+            let _ = handle.push_pinned(task);
+        }
+    }
+
+    // 从其他工作线程的本地队列中窃取大约一半的任务放进自己的本地队列，并返回其中一个。
+    // 从下一个编号开始依次尝试，使窃取的压力分散到各个工作线程上。
+    fn steal(&self) -> Option<Arc<Task>> {
+        let stealers = self.shared.stealers.read().unwrap();
+        let counters = &self.counters;
+
+        for offset in 1..stealers.len() {
+            let stealer = match &stealers[(self.index + offset) % stealers.len()] {
+                Some(stealer) => stealer,
+                None => continue,
+            };
+
+            loop {
+                match stealer.steal_batch_and_pop(&self.local) {
+                    deque::Steal::Success(task) => {
+                        counters.steals.fetch_add(1, Ordering::Relaxed);
+                        return Some(task);
+                    }
+                    deque::Steal::Empty => break,
+                    // 与其他窃取者发生了竞争，再试一次。
+                    deque::Steal::Retry => {}
+                }
+            }
+        }
+
+        if stealers.len() > 1 {
+            counters.steal_failures.fetch_add(1, Ordering::Relaxed);
+        }
+
+        None
+    }
+}
+
+impl Handle {
+    /// 在这个句柄所属的运行时上产生一个未来。
+    ///
+    /// 运行时关闭之后返回`SpawnError::Shutdown`，调度通道已满时返回`SpawnError::QueueFull`，
+    /// 存活的任务数达到`Builder::max_tasks`时返回`SpawnError::AtCapacity`，
+    /// 这些情况下给定的未来都被丢弃而不会被执行。
+    fn spawn<F, T>(&self, future: F) -> Result<JoinHandle<T>, SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_with_priority(future, Priority::Normal)
+    }
+
+    /// 以给定的优先级在这个句柄所属的运行时上产生一个未来，见`spawn_with_priority`。
+    ///
+    /// 错误和`spawn`相同。高优先级和低优先级的调度通道没有容量限制，所以只有普通优先级会返回`SpawnError::QueueFull`。
+    fn spawn_with_priority<F, T>(
+        &self,
+        future: F,
+        priority: Priority,
+    ) -> Result<JoinHandle<T>, SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (task, join) = Task::new(future, self, priority, None)?;
+        self.inject(task)?;
+        Ok(join)
+    }
+
+    /// 进入运行时的上下文：在返回的守卫被丢弃之前，当前线程上的`spawn`等函数使用这个运行时。
+    ///
+    /// 守卫保存进入之前的上下文，并在被丢弃时恢复它，所以进入另一个运行时可以正确地嵌套。
+    /// 已经在同一个运行时中时，进入不会改变任何东西。
+    fn enter(&self) -> EnterGuard {
+        let prev = CURRENT.with(|cell| {
+            let mut current = cell.borrow_mut();
+
+            match &*current {
+                Some(handle) if Arc::ptr_eq(&handle.shared, &self.shared) => None,
+                _ => Some(current.replace(self.clone())),
+            }
+        });
+
+        EnterGuard {
+            prev,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// 在运行时上执行给定的未来，阻塞当前线程直到它完成，并返回它的输出。
+    ///
+    /// 这是给运行时之外的同步代码准备的，例如在另一个线程上驱动的运行时中等待一个异步函数，
+    /// 或者在`spawn_blocking`的闭包中调用异步代码。
+    /// 未来被催生为一个普通的任务，当前线程在等待期间被park，而不是忙等。
+    /// 运行时必须正在被驱动（多线程模式的工作线程，或者另一个线程上的`run`），否则这里会永远等待。
+    ///
+    /// # Panics
+    ///
+    /// 在负责轮询这个运行时的任务的线程上（工作线程、`run`或者`MiniTokio::block_on`的线程）调用时panic：
+    /// 阻塞执行器线程等待一个需要执行器才能完成的任务会造成死锁。阻塞线程池中的线程和
+    /// `block_in_place`中已经交出本地队列的工作线程不在此列。
+    /// 运行时已经关闭，或者任务在完成之前被取消时也会panic。
+    ///
+    /// 在另一个运行时的线程上调用是允许的，但那个线程在等待期间不能再轮询它自己的任务。
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        assert!(
+            !self.is_driven_here(),
+            "Cannot start a runtime from within a runtime: \
+             `Handle::block_on` was called from a thread that is driving the same runtime"
+        );
+
+        let join = match self.spawn(future) {
+            Ok(join) => join,
+            Err(err) => panic!("`Handle::block_on` failed to spawn the future: {}", err),
+        };
+
+        match join.join() {
+            Ok(output) => output,
+            Err(err) => panic!("`Handle::block_on` future did not complete: {}", err),
+        }
+    }
+
+    // 和`spawn`一样，但不返回错误，供签名里没有错误的`spawn`函数和`MiniTokio::spawn`使用。
+    // 任何错误都会panic，包括运行时已经关闭：这些都是调用者需要知道的错误，
+    // 不能藏在一个被取消的句柄后面，让未来悄无声息地消失。
+    // `name`是`task::Builder`给任务起的名字，其他调用者传入`None`。
+    fn spawn_or_panic<F, T>(
+        &self,
+        future: F,
+        priority: Priority,
+        name: Option<Arc<str>>,
+    ) -> JoinHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (task, join) = match Task::new(future, self, priority, name) {
+            Ok(spawned) => spawned,
+            Err(err) => panic!("failed to spawn a task: {}", err),
+        };
+
+        match self.inject(task) {
+            Ok(()) => join,
+            Err(err) => panic!("failed to spawn a task: {}", err),
+        }
+    }
+
+    /// 请求关闭运行时。
+    ///
+    /// 正在`run`的执行器循环会在当前任务执行完后停止，丢弃所有还在队列中的任务，然后返回。
+    /// 仍处于`Poll::Pending`状态的任务不会再被唤醒，它们的未来在这里就被丢弃，
+    /// 而不是一直留在某个唤醒者引用的任务中。此刻正在被轮询的任务的未来在那次轮询结束后被丢弃。
+    /// 之后催生任务会失败：`Handle::spawn`返回`SpawnError::Shutdown`，`spawn`panic。多次调用是无害的。
+    fn shutdown(&self) {
+        self.shared.is_shutdown.store(true, Ordering::SeqCst);
+        self.shared.shutdown_tx.lock().unwrap().take();
+        self.shared.unpark_all();
+        self.shared.blocking.shutdown();
+        self.shared.close_tasks();
+    }
+
+    /// 在阻塞线程池中执行给定的闭包，不占用执行器线程。
+    ///
+    /// 返回的`JoinHandle`在闭包返回时完成，可以在任何任务中等待。
+    fn spawn_blocking<F, R>(&self, f: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        // `UnhandledPanic`只适用于普通的任务，脱离的阻塞闭包的panic被忽略。
+        let (guard, join) = JoinGuard::new(None);
+        let job = Box::new(move || match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(output) => guard.complete(output),
+            Err(payload) => guard.panic(payload),
+        });
+
+        self.shared.blocking.spawn(job, self);
+
+        join
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shared.is_shutdown.load(Ordering::SeqCst)
+    }
+
+    // 新催生的任务是否会被拒绝：运行时已经关闭，或者`shutdown_timeout`已经开始。
+    fn is_closed(&self) -> bool {
+        self.is_shutdown() || self.shared.is_closed.load(Ordering::SeqCst)
+    }
+
+    /// 返回运行时的统计数据的快照。可以在任何线程上、在运行时执行任务期间调用。
+    ///
+    /// 计数器是各自独立读取的，运行时正忙时快照中的数字之间可能不完全一致。
+    fn metrics(&self) -> Metrics {
+        let shared = &self.shared;
+        let queued = shared.scheduled.len()
+            + shared.high_scheduled.len()
+            + shared.low_scheduled.len()
+            + shared.overflow.lock().unwrap().len()
+            + shared
+                .stealers
+                .read()
+                .unwrap()
+                .iter()
+                .flatten()
+                .map(|stealer| stealer.len())
+                .sum::<usize>()
+            + shared
+                .pinned
+                .read()
+                .unwrap()
+                .iter()
+                .flatten()
+                .map(|queue| queue.lock().unwrap().len())
+                .sum::<usize>();
+
+        Metrics {
+            tasks_spawned: shared.tasks_spawned.load(Ordering::Relaxed),
+            tasks_completed: shared.tasks_completed.load(Ordering::Relaxed),
+            current_queue_depth: queued,
+            total_polls: shared.total_polls.load(Ordering::Relaxed),
+            tasks_alive: shared.tasks_alive.load(Ordering::Relaxed),
+            wakeups_deduplicated: shared.wakeups_deduplicated.load(Ordering::Relaxed),
+            tasks_repinned: shared.tasks_repinned.load(Ordering::Relaxed),
+            workers: shared
+                .worker_metrics
+                .read()
+                .unwrap()
+                .iter()
+                .flatten()
+                .map(|counters| WorkerMetrics {
+                    polls: counters.polls.load(Ordering::Relaxed),
+                    steals: counters.steals.load(Ordering::Relaxed),
+                    steal_failures: counters.steal_failures.load(Ordering::Relaxed),
+                    parks: counters.parks.load(Ordering::Relaxed),
+                    busy: Duration::from_nanos(counters.busy_nanos.load(Ordering::Relaxed)),
+                })
+                .collect(),
+        }
+    }
+
+    /// 返回运行时中所有存活的任务的快照，按编号（也就是催生的先后）排列，例如用来查看程序卡住时每个任务在做什么。
+    ///
+    /// 和`metrics`一样可以在任何线程上、在运行时执行任务期间调用，每个任务的信息是各自独立读取的。
+    /// 任务从被催生起出现在快照中，直到最后一个引用它的唤醒者被丢弃：已经完成但还被某个唤醒者引用着的任务
+    /// 以`TaskState::Completed`出现。`spawn_blocking`和`spawn_local`催生的任务不在其中。
+    fn dump(&self) -> TaskDump {
+        let tasks: Vec<_> = self
+            .shared
+            .tasks
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        // 在注册表的锁之外读取任务的状态，释放最后的引用时`Task`的`drop`需要这个锁。
+        let mut tasks: Vec<_> = tasks
+            .iter()
+            .map(|task| TaskInfo {
+                id: task.id,
+                name: task.name.clone(),
+                state: match task.state.load(Ordering::SeqCst) {
+                    Task::IDLE => TaskState::Idle,
+                    Task::SCHEDULED => TaskState::Scheduled,
+                    Task::RUNNING | Task::NOTIFIED => TaskState::Running,
+                    _ => TaskState::Completed,
+                },
+                spawned_at: task.spawned_at,
+                polls: task.polls.load(Ordering::Relaxed),
+            })
+            .collect();
+        tasks.sort_by_key(|task| task.id);
+
+        TaskDump { tasks }
+    }
+
+    /// 增加一个工作线程。新的线程立即开始执行任务，并参与窃取。
+    ///
+    /// 单线程模式下返回`ErrorKind::InvalidInput`错误，运行时已经关闭时返回`ErrorKind::Other`错误，
+    /// 无法创建线程时返回创建线程的错误。
+    fn add_worker(&self) -> io::Result<()> {
+        let shared = &self.shared;
+
+        if !shared.multi_thread {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot add workers to a single-threaded runtime",
+            ));
+        }
+
+        if self.is_closed() {
+            return Err(io::Error::other("the runtime has been shut down"));
+        }
+
+        let local = deque::Worker::new_fifo();
+        let counters = Arc::new(WorkerCounters::default());
+        let pinned = PinnedQueue::default();
+
+        // 这几个列表总是一起增长，所以新的编号在它们中都相同。
+        let index = {
+            let mut stealers = shared.stealers.write().unwrap();
+            let mut worker_metrics = shared.worker_metrics.write().unwrap();
+            let mut pinned_queues = shared.pinned.write().unwrap();
+            stealers.push(Some(local.stealer()));
+            worker_metrics.push(Some(counters.clone()));
+            pinned_queues.push(Some(pinned.clone()));
+            stealers.len() - 1
+        };
+        shared.live_workers.fetch_add(1, Ordering::SeqCst);
+
+        let spawned = shared.spawn_worker(WorkerContext {
+            shared: shared.clone(),
+            index,
+            counters,
+            pinned,
+            local,
+            lifo: Cell::new(None),
+            lifo_polls: Cell::new(0),
+            tick: Cell::new(0),
+            normal_tick: Cell::new(0),
+            scheduled: shared.scheduled.clone(),
+        });
+
+        if spawned.is_err() {
+            shared.stealers.write().unwrap()[index] = None;
+            shared.worker_metrics.write().unwrap()[index] = None;
+            shared.pinned.write().unwrap()[index] = None;
+            shared.live_workers.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        spawned
+    }
+
+    /// 减少一个工作线程。
+    ///
+    /// 不会打断正在执行的任务：某个工作线程在执行完当前的任务之后（空闲的工作线程立即）退休，
+    /// 把它本地队列中的任务交还给调度通道，然后退出，所以没有任务会丢失。这个函数不等待它退出，
+    /// 退休的工作线程退出之后不再出现在`Metrics`中。
+    ///
+    /// 只剩一个工作线程（不计已经在等待退休的）或者在单线程模式下时返回`ErrorKind::InvalidInput`错误：
+    /// 运行时至少需要一个工作线程来执行任务。
+    fn remove_worker(&self) -> io::Result<()> {
+        let shared = &self.shared;
+
+        if !shared.multi_thread {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot remove workers from a single-threaded runtime",
+            ));
+        }
+
+        shared
+            .live_workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n > 1 {
+                    Some(n - 1)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "cannot remove the last worker")
+            })?;
+
+        shared.retiring.fetch_add(1, Ordering::SeqCst);
+        shared.unpark_all();
+        Ok(())
+    }
+
+    // 一个任务panic了，调用`on_task_panic`回调。`name`是任务的名字，见`task::Builder`。
+    fn task_panicked(&self, payload: &(dyn Any + Send), name: Option<&str>) {
+        if let Some(hook) = &self.shared.on_task_panic {
+            hook(&TaskPanicInfo { payload, name });
+        }
+    }
+
+    // 一个脱离的任务panic了。
+    fn unhandled_panic(&self, payload: Box<dyn Any + Send>) {
+        if self.shared.unhandled_panic == UnhandledPanic::Ignore {
+            return;
+        }
+
+        // 只保留第一次panic，它是运行时被关闭的原因。
+        self.shared
+            .panic_payload
+            .lock()
+            .unwrap()
+            .get_or_insert(payload);
+        self.shutdown();
+    }
+
+    // 运行时因为一个脱离的任务panic而被关闭时，在驱动运行时的线程上重新抛出那次panic。
+    fn resume_unhandled_panic(&self) {
+        let payload = self.shared.panic_payload.lock().unwrap().take();
+
+        if let Some(payload) = payload {
+            panic::resume_unwind(payload);
+        }
+    }
+
+    // 安排一个被唤醒的任务。
+    //
+    // 如果唤醒发生在这个运行时的某个工作线程上，任务被推入该工作线程的本地队列，
+    // 这样它很可能在同一个线程上继续执行。其他情况下（包括通过`defer`让出执行器的唤醒），
+    // 任务进入共享的调度通道。
+    // 关闭之后的推送被忽略：任务在这里被丢弃，而不是留在一个无人读取的队列里。
+    // 任务被标记为已取消：它的未来已经或者即将被`shutdown`丢弃，不会再被轮询。
+    fn schedule(&self, task: Arc<Task>) {
+        if self.is_shutdown() {
+            task.aborted.store(true, Ordering::SeqCst);
+            return;
+        }
+
+        let task = match self.push_pinned(task) {
+            Some(task) => task,
+            None => return,
+        };
+
+        // 本地队列和LIFO槽只用于普通优先级的任务。
+        if task.priority != Priority::Normal {
+            self.push_woken(task);
+            return;
+        }
+
+        let deferred = DEFER.with(|defer| defer.get());
+
+        let task = WORKER.with(|cell| match &*cell.borrow() {
+            Some(context) if !deferred && Arc::ptr_eq(&context.shared, &self.shared) => {
+                // 被唤醒的任务放进LIFO槽，槽中原来的任务移到本地队列的末尾。
+                if let Some(prev) = context.lifo.replace(Some(task)) {
+                    context.local.push(prev);
+
+                    // 本地队列中有任务在排队，叫醒一个空闲的工作线程来分担。
+                    self.shared.unpark_one();
+                }
+
+                None
+            }
+            _ => Some(task),
+        });
+
+        if let Some(task) = task {
+            self.push_woken(task);
+        }
+    }
+
+    // 把一个被唤醒的任务推入共享的调度通道。和`inject`不同，它从不失败：
+    // 调度通道已满时任务进入溢出列表，等调度通道腾出位置后再被移入。
+    fn push_woken(&self, task: Arc<Task>) {
+        if self.is_shutdown() {
+            task.aborted.store(true, Ordering::SeqCst);
+            return;
+        }
+
+        let task = match self.push_pinned(task) {
+            Some(task) => task,
+            None => return,
+        };
+
+        // 高优先级和低优先级的调度通道没有容量限制，只有普通优先级的任务会进入溢出列表。
+        let sender = match task.priority {
+            Priority::High => &self.shared.high_sender,
+            Priority::Low => &self.shared.low_sender,
+            Priority::Normal => &self.shared.sender,
+        };
+
+        let rejected = if sender.capacity().is_none() {
+            sender.send(task).err().map(|err| err.0)
+        } else {
+            let mut overflow = self.shared.overflow.lock().unwrap();
+
+            match self.shared.sender.try_send(task) {
+                Ok(()) => None,
+                Err(channel::TrySendError::Full(task)) => {
+                    overflow.push_back(task);
+                    None
+                }
+                Err(channel::TrySendError::Disconnected(task)) => Some(task),
+            }
+        };
+
+        // `Shared`自己持有调度通道的接收者，通道不会在运行时存活期间断开。万一断开了，
+        // 也要像运行时关闭时一样丢弃任务的未来，等待它的`JoinHandle`得到`JoinError::Cancelled`，而不是永远挂起。
+        if let Some(task) = rejected {
+            task.close();
+            return;
+        }
+
+        self.shared.unpark_one();
+    }
+
+    // 把任务推入共享的调度通道。新催生的任务总是从这里进入运行时，可以被任意一个工作线程取走。
+    //
+    // 运行时已经关闭，或者有容量限制的调度通道已满时，任务被丢弃并返回错误。
+    //
+    // 按照`SpawnBackpressure::YieldAndRetry`，在驱动运行时的线程上调度通道已满时，
+    // 新任务和被唤醒的任务一样进入溢出列表，催生它的任务在下一个资源操作处让出执行器。
+    fn inject(&self, task: Arc<Task>) -> Result<(), SpawnError> {
+        if task.pinned.load(Ordering::SeqCst) != Task::NOT_PINNED {
+            if self.is_closed() {
+                return Err(SpawnError::Shutdown);
+            }
+
+            self.push_pinned(task);
+            self.shared.tasks_spawned.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        if task.priority != Priority::Normal {
+            if self.is_closed() {
+                return Err(SpawnError::Shutdown);
+            }
+
+            self.push_woken(task);
+            self.shared.tasks_spawned.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        if self.is_closed() {
+            return Err(SpawnError::Shutdown);
+        }
+
+        match self.shared.sender.try_send(task) {
+            Ok(()) => {
+                self.shared.tasks_spawned.fetch_add(1, Ordering::Relaxed);
+                self.shared.unpark_one();
+                Ok(())
+            }
+            // 不在这里执行排在前面的任务：它们会在催生者的轮询中运行，看到催生者的`TaskLocal`，
+            // 算进催生者的轮询时间，嵌套的催生还会让栈无限加深。
+            // 用完催生者这次轮询的预算，它在下一个资源操作处让出执行器，调度通道得以被取空。
+            Err(channel::TrySendError::Full(task))
+                if self.shared.spawn_backpressure == SpawnBackpressure::YieldAndRetry
+                    && self.is_driven_here() =>
+            {
+                self.push_woken(task);
+                self.shared.tasks_spawned.fetch_add(1, Ordering::Relaxed);
+                BUDGET.with(|budget| {
+                    if budget.get().is_some() {
+                        budget.set(Some(0));
+                    }
+                });
+                Ok(())
+            }
+            Err(channel::TrySendError::Full(_)) => Err(SpawnError::QueueFull),
+            Err(channel::TrySendError::Disconnected(_)) => Err(SpawnError::Shutdown),
+        }
+    }
+
+    // 把一个固定在某个工作线程上的任务推入那个工作线程的固定队列，并叫醒所有空闲的工作线程：
+    // 只有那一个工作线程能执行它，`unpark_one`可能叫醒别的线程。
+    // 那个工作线程已经退休时，先把任务重新固定到另一个工作线程上。
+    //
+    // 任务没有被固定时原样返回它。所有工作线程都已经退出时（只在关闭之后）也返回它，由调用者放进调度通道。
+    fn push_pinned(&self, task: Arc<Task>) -> Option<Arc<Task>> {
+        let index = task.pinned.load(Ordering::SeqCst);
+        if index == Task::NOT_PINNED {
+            return Some(task);
+        }
+
+        {
+            let queues = self.shared.pinned.read().unwrap();
+            let queue = match queues.get(index).and_then(Option::as_ref) {
+                Some(queue) => queue,
+                None => match self.shared.pick_worker(&queues) {
+                    Some(index) => {
+                        task.pinned.store(index, Ordering::SeqCst);
+                        self.shared.tasks_repinned.fetch_add(1, Ordering::Relaxed);
+                        queues[index].as_ref().unwrap()
+                    }
+                    None => return Some(task),
+                },
+            };
+            queue.lock().unwrap().push_back(task);
+        }
+
+        self.shared.unpark_all();
+        None
+    }
+
+    /// 在编号为`worker`的工作线程上产生一个未来，任务之后总是在这个工作线程上被轮询。
+    ///
+    /// 依赖线程本地缓存的任务这样不会被工作窃取迁移到其他线程上。编号和`Metrics`中的编号不同：
+    /// 它是工作线程被创建的次序，从0开始，`Builder::worker_threads`创建的工作线程是前几个，
+    /// 之后`add_worker`增加的工作线程依次排在后面。唯一的例外是`task::block_in_place`：
+    /// 工作线程的队列被交给替代的线程，固定的任务跟着队列走。
+    ///
+    /// 任务固定的工作线程退休时（见`remove_worker`），任务被重新固定到另一个工作线程上，
+    /// 计入`Metrics::tasks_repinned`，而不是被丢弃。
+    ///
+    /// 单线程模式下，或者没有这个编号的工作线程（已经退休或者从未存在）时返回`SpawnError::NoSuchWorker`，
+    /// 其他错误和`spawn`相同。
+    fn spawn_pinned<F, T>(&self, worker: usize, future: F) -> Result<JoinHandle<T>, SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let alive = matches!(
+            self.shared.pinned.read().unwrap().get(worker),
+            Some(Some(_))
+        );
+        if !self.shared.multi_thread || !alive {
+            return Err(SpawnError::NoSuchWorker);
+        }
+
+        let (task, join) = Task::new(future, self, Priority::Normal, None)?;
+        task.pinned.store(worker, Ordering::SeqCst);
+        self.inject(task)?;
+        Ok(join)
+    }
+
+    /// 和`spawn_pinned`一样，但工作线程是轮流选择的，这样固定的任务均匀地分布在各个工作线程上。
+    fn spawn_pinned_auto<F, T>(&self, future: F) -> Result<JoinHandle<T>, SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let worker = self.shared.pick_worker(&self.shared.pinned.read().unwrap());
+        match worker {
+            Some(worker) => self.spawn_pinned(worker, future),
+            None => Err(SpawnError::NoSuchWorker),
+        }
+    }
+
+    // 当前线程是否在轮询这个运行时的任务：它是这个运行时的工作线程，或者正在`MiniTokio::block_on`中驱动它。
+    fn is_driven_here(&self) -> bool {
+        let on_worker = WORKER.with(|cell| match &*cell.borrow() {
+            Some(context) => Arc::ptr_eq(&context.shared, &self.shared),
+            None => false,
+        });
+        let in_block_on = BLOCK_ON.with(|block_on| block_on.get())
+            && CURRENT.with(|cell| match &*cell.borrow() {
+                Some(handle) => Arc::ptr_eq(&handle.shared, &self.shared),
+                None => false,
+            });
+
+        on_worker || in_block_on
+    }
+}
+
+//相当于`tokio::spawn`。
+// 当进入mini-tokio执行器时，`CURRENT`线程本地被设置为指向该执行器的句柄。
+// 然后，spwn需要为给定的`future`创建`Task`线束，并将其推入计划队列。
+//
+// 和`MiniTokio::spawn`一样，运行时已经关闭或者调度通道有容量限制并且已满时panic，
+// 需要处理这些情况时使用`Handle::spawn`。
+// 在运行时之外调用时同样panic，需要处理这种情况时使用`try_spawn`。
+pub fn spawn<F, T>(future: F) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with_priority(future, Priority::Normal)
+}
+
+/// 以给定的优先级产生一个未来。
+///
+/// 三种优先级的任务在不同的队列中排队。所有队列中都有任务时，工作线程执行的任务中
+/// 大约七分之四是高优先级的，七分之二是普通优先级的，七分之一是低优先级的：
+/// 延迟敏感的任务优先执行，低优先级的后台任务仍然能取得进展。优先级跟随任务，
+/// 任务之后每次被唤醒都回到它的优先级的队列中。
+///
+/// `spawn`等价于以`Priority::Normal`调用这个函数。`MiniTokio::block_on`的线程执行任务时不考虑优先级。
+///
+/// # Panics
+///
+/// 和`spawn`相同。
+pub fn spawn_with_priority<F, T>(future: F, priority: Priority) -> JoinHandle<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    match with_current(|handle| handle.spawn_or_panic(future, priority, None)) {
+        Ok(join) => join,
+        Err(err) => panic!("failed to spawn a task: {}", err),
+    }
+}
+
+/// 运行时的统计数据在某一时刻的快照，见`MiniTokio::metrics`和`Handle::metrics`。
+///
+/// 每个工作线程的计数器通过`worker_poll_count`等方法读取，`idx`小于`worker_count()`。
+/// 工作线程按照加入运行时的先后排列，已经退休的工作线程（见`Handle::remove_worker`）不在其中。
+/// 单线程模式下只有一个工作线程，也就是调用`run`的线程。`MiniTokio::block_on`的线程轮询的任务
+/// 计入`total_polls`，但不计入任何一个工作线程。
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metrics {
+    /// 成功催生的任务数，不包括`spawn_blocking`和`spawn_local`催生的任务。
+    pub tasks_spawned: u64,
+    /// 未来已经完成（包括panic）的任务数。被取消的任务不计入。
+    pub tasks_completed: u64,
+    /// 正在排队等待被轮询的任务数：调度通道、溢出列表和工作线程的本地队列中的任务。
+    /// 工作线程的LIFO槽中的任务不计入。
+    pub current_queue_depth: usize,
+    /// 任务的未来被轮询的总次数。
+    pub total_polls: u64,
+    /// 未来还没有被丢弃的任务数：还没有完成、也没有被取消的任务。
+    pub tasks_alive: u64,
+    /// 因为任务已经在队列中，或者在轮询期间已经被唤醒过而被合并的唤醒次数。
+    pub wakeups_deduplicated: u64,
+    /// 固定的任务因为它的工作线程退休而被固定到另一个工作线程上的次数，见`Handle::spawn_pinned`。
+    pub tasks_repinned: u64,
+    workers: Vec<WorkerMetrics>,
+}
+
+// `Metrics`中一个工作线程的计数器。
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct WorkerMetrics {
+    polls: u64,
+    steals: u64,
+    steal_failures: u64,
+    parks: u64,
+    busy: Duration,
+}
+
+impl Metrics {
+    /// 工作线程的数量。运行时中的工作线程可能在增加或减少，见`Handle::add_worker`。
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// 工作线程轮询任务的次数。
+    ///
+    /// # Panics
+    ///
+    /// `idx`不小于`worker_count()`时panic。下面的方法也一样。
+    pub fn worker_poll_count(&self, idx: usize) -> u64 {
+        self.workers[idx].polls
+    }
+
+    /// 工作线程成功地从其他工作线程的本地队列中窃取任务的次数。每次窃取大约一半的任务。
+    pub fn worker_steal_count(&self, idx: usize) -> u64 {
+        self.workers[idx].steals
+    }
+
+    /// 工作线程尝试窃取，但其他工作线程的本地队列都为空的次数。
+    pub fn worker_steal_failure_count(&self, idx: usize) -> u64 {
+        self.workers[idx].steal_failures
+    }
+
+    /// 工作线程找不到任务而阻塞等待的次数。
+    pub fn worker_park_count(&self, idx: usize) -> u64 {
+        self.workers[idx].parks
+    }
+
+    /// 工作线程轮询任务花费的总时间。
+    pub fn worker_busy_duration(&self, idx: usize) -> Duration {
+        self.workers[idx].busy
+    }
+}
+
+/// 运行时中所有存活的任务在某一时刻的快照，见`Handle::dump`。
+///
+/// 用`{}`格式化时每个任务占一行。
+#[derive(Debug, Clone)]
+pub struct TaskDump {
+    tasks: Vec<TaskInfo>,
+}
+
+impl TaskDump {
+    /// 快照中的任务，按编号排列。
+    pub fn tasks(&self) -> &[TaskInfo] {
+        &self.tasks
+    }
+}
+
+impl fmt::Display for TaskDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for task in &self.tasks {
+            write!(f, "task {}", task.id)?;
+            if let Some(name) = task.name() {
+                write!(f, " ({:?})", name)?;
+            }
+            writeln!(
+                f,
+                ": {:?}, polled {} times, spawned {:?} ago",
+                task.state,
+                task.polls,
+                task.spawned_at.elapsed()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `TaskDump`中一个任务的信息。
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    id: task::Id,
+    name: Option<Arc<str>>,
+    state: TaskState,
+    spawned_at: Instant,
+    polls: u64,
+}
+
+impl TaskInfo {
+    /// 任务的编号，和它的`JoinHandle::id`相同。
+    pub fn id(&self) -> task::Id {
+        self.id
+    }
+
+    /// 任务的名字，见`task::Builder::name`。没有名字的任务返回`None`。
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// 快照时任务的调度状态。
+    pub fn state(&self) -> TaskState {
+        self.state
+    }
+
+    /// 任务被催生的时刻。总是真实的时间，不受`Builder::clock`影响。
+    pub fn spawned_at(&self) -> Instant {
+        self.spawned_at
+    }
+
+    /// 任务的未来被轮询的次数。
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+}
+
+/// 任务的调度状态，见`TaskInfo::state`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// 在等待被唤醒：既不在队列中，也没有被轮询。
+    Idle,
+    /// 已经被唤醒，在某个队列中等待被轮询。
+    Scheduled,
+    /// 正在被某个线程轮询。
+    Running,
+    /// 未来已经完成或者被取消，只是任务还被某个唤醒者引用着。
+    Completed,
+}
+
+/// 任务的优先级，见`spawn_with_priority`。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// 延迟敏感的任务。
+    High,
+    /// `spawn`催生的任务的优先级。
+    #[default]
+    Normal,
+    /// 后台任务。
+    Low,
+}
+
+/// `spawn`的可失败版本。
+///
+/// 当前线程不在运行时之中时返回`SpawnError::NoRuntime`，其余的错误和`Handle::spawn`相同。
+pub fn try_spawn<F, T>(future: F) -> Result<JoinHandle<T>, SpawnError>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    with_current(|handle| handle.spawn(future))?
+}
+
+// 用`CURRENT`中的句柄调用`f`。线程本地为空时返回`SpawnError::NoRuntime`。
+fn with_current<R>(f: impl FnOnce(&Handle) -> R) -> Result<R, SpawnError> {
+    CURRENT.with(|cell| match cell.borrow().as_ref() {
+        Some(handle) => Ok(f(handle)),
+        None => Err(SpawnError::NoRuntime),
+    })
+}
+
+// 让出执行器，使其他被调度的任务有机会运行。
+//
+// 一个长时间运行的任务可以定期等待它，这样它不会霸占执行器线程。
+// 第一次轮询时任务立即唤醒自己并返回`Poll::Pending`，于是它被重新排入调度队列的末尾；
+// 排在它前面的任务执行完之后，第二次轮询返回`Poll::Ready`。
+pub async fn yield_now() {
+    struct YieldNow {
+        yielded: bool,
+    }
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.yielded {
+                return Poll::Ready(());
+            }
+
+            self.yielded = true;
+
+            // 必须在返回`Pending`之前唤醒自己。没有别的资源会唤醒这个任务，
+            // 忘记这一步的话任务会永远挂起。
+            defer(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    YieldNow { yielded: false }.await;
+}
+
+/// 用一个闭包构造一个未来：每次被轮询时调用`f(cx)`，返回它的结果。
+///
+/// 这样一个小的叶子未来可以就地写出来，而不必像`Sleep`那样定义一个有名字的结构体。
+/// 闭包负责在返回`Poll::Pending`时安排唤醒`cx`中的唤醒者，和手写的`Future::poll`一样。
+pub fn poll_fn<T, F>(f: F) -> impl Future<Output = T>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T>,
+{
+    struct PollFn<F> {
+        f: F,
+    }
+
+    // 闭包只被调用，从不被固定在原地，所以不管它是什么，`PollFn`都可以是`Unpin`的。
+    impl<F> Unpin for PollFn<F> {}
+
+    impl<T, F: FnMut(&mut Context<'_>) -> Poll<T>> Future for PollFn<F> {
+        type Output = T;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+            (self.f)(cx)
+        }
+    }
+
+    PollFn { f }
+}
+
+/// 与任务本身打交道的工具，对应`tokio::task`。
+pub mod task {
+    use super::*;
+
+    pub use super::yield_now;
+
+    /// 任务的编号，对应`tokio::task::Id`。
+    ///
+    /// 编号在催生任务时分配，在整个进程中单调递增，任务结束之后也不会被重新使用，
+    /// 所以可以用来把不同任务输出的日志对应起来。`JoinHandle::id`和任务中的`task::id()`返回同一个值。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct Id(u64);
+
+    impl Id {
+        // 分配一个新的编号。所有运行时共用同一个计数器，不同运行时中的任务的编号也不会重复。
+        pub(crate) fn next() -> Id {
+            static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+            Id(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+        }
+    }
+
+    impl fmt::Display for Id {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    // 当前线程正在轮询的任务的编号。
+    thread_local! {
+        static CURRENT_ID: Cell<Option<Id>> = const { Cell::new(None) };
+    }
+
+    // 在轮询一个任务期间设置`CURRENT_ID`，被丢弃时恢复之前的值：
+    // 一个任务的轮询中可能会轮询其他任务，例如它调用了另一个运行时的`MiniTokio::block_on`。
+    pub(crate) struct IdGuard {
+        prev: Option<Id>,
+    }
+
+    impl IdGuard {
+        pub(crate) fn enter(id: Id) -> IdGuard {
+            IdGuard {
+                prev: CURRENT_ID.with(|cell| cell.replace(Some(id))),
+            }
+        }
+    }
+
+    impl Drop for IdGuard {
+        fn drop(&mut self) {
+            CURRENT_ID.with(|cell| cell.set(self.prev));
+        }
+    }
+
+    /// 返回当前正在执行的任务的编号。
+    ///
+    /// # Panics
+    ///
+    /// 不在任务中调用时panic，例如在`block_on`的根未来或者`spawn_blocking`的闭包中。需要处理这种情况时使用`try_id`。
+    pub fn id() -> Id {
+        try_id().expect("`task::id` called from outside of a task")
+    }
+
+    /// 返回当前正在执行的任务的编号，不在任务中时返回`None`。
+    pub fn try_id() -> Option<Id> {
+        CURRENT_ID.with(|cell| cell.get())
+    }
+
+    /// 在当前任务中执行一段阻塞的同步代码，返回闭包的结果。
+    ///
+    /// 与`spawn_blocking`不同，闭包就在当前线程上执行，所以它可以借用任务中的局部状态。
+    ///
+    /// - 在多线程模式的工作线程上调用时，当前工作线程的本地队列会先被交给一个新创建的替代工作线程，
+    ///   其他任务在闭包执行期间继续取得进展。当前任务返回之后，这个线程不再作为工作线程。
+    /// - 在单线程模式下，或者在不是工作线程的线程上（比如`block_on`的调用者、阻塞线程池），
+    ///   闭包被直接执行：闭包执行期间，这个线程上的其他任务都无法取得进展。
+    ///
+    /// # Panics
+    ///
+    /// 不在mini-tokio运行时中调用时panic。
+    pub fn block_in_place<F, R>(f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        // 只读取CURRENT，不修改它，所以这里的panic不会留下被破坏的上下文。
+        let in_runtime = CURRENT.with(|cell| cell.borrow().is_some());
+        assert!(
+            in_runtime,
+            "`block_in_place` must be called from within a MiniTokio runtime"
+        );
+
+        // 只有多线程模式下的工作线程才有需要交出去的本地队列。
+        let context = WORKER.with(|cell| {
+            let mut cell = cell.borrow_mut();
+            match &*cell {
+                Some(context) if context.shared.multi_thread => cell.take(),
+                _ => None,
+            }
+        });
+
+        if let Some(context) = context {
+            // 执行者循环在轮询任务期间不持有上下文的引用，所以这里拿到的是唯一的一份。
+            let context = match Rc::try_unwrap(context) {
+                Ok(context) => context,
+                Err(_) => unreachable!("worker context is still referenced"),
+            };
+
+            let shared = context.shared.clone();
+            shared
+                .spawn_worker(context)
+                .expect("failed to spawn a replacement worker thread");
+        }
+
+        f()
+    }
+
+    /// 在当前的`LocalSet`上产生一个不需要是`Send`的未来。
+    ///
+    /// # Panics
+    ///
+    /// 不在`LocalSet::run_until`驱动的未来中调用时panic。
+    pub fn spawn_local<F, T>(future: F) -> JoinHandle<T>
+    where
+        F: Future<Output = T> + 'static,
+        T: 'static,
+    {
+        CURRENT_LOCAL.with(|cell| {
+            let borrow = cell.borrow();
+            let local = borrow
+                .as_ref()
+                .expect("`spawn_local` called from outside of a `LocalSet`");
+            local.spawn(future)
+        })
+    }
+
+    /// 一组被固定在同一个线程上执行的任务，这些任务的未来不需要是`Send`。
+    ///
+    /// `spawn`要求未来是`Send`，因为任务可能在任何一个工作线程上被轮询，
+    /// 这使得跨越等待点持有`Rc`或`RefCell`的未来无法被催生。`LocalSet`自己保存这些未来，
+    /// 并且只在驱动它的那个线程上轮询它们：
+    ///
+    /// ```ignore
+    /// let local = task::LocalSet::new();
+    /// mini_tokio.block_on(local.run_until(async {
+    ///     let shared = Rc::new(Cell::new(0));
+    ///     let task = task::spawn_local({
+    ///         let shared = shared.clone();
+    ///         async move { shared.set(1) }
+    ///     });
+    ///     task.await.unwrap();
+    /// }));
+    /// ```
+    ///
+    /// `run_until`返回的未来同样不是`Send`，所以它需要由`block_on`来驱动。
+    pub struct LocalSet {
+        inner: Rc<LocalInner>,
+    }
+
+    // 本地任务的未来。和`Task`中的一样，输出类型已经被擦除，但不要求`Send`。
+    type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+    // `LocalSet`在驱动它的线程上的状态。
+    struct LocalInner {
+        // 还没有完成的本地任务的未来。轮询一个任务时先把它的未来取出来，
+        // 这样任务在轮询期间调用`spawn_local`不会与这里的借用冲突。
+        // 按任务编号索引，见`JoinHandle::id`。
+        tasks: RefCell<HashMap<Id, LocalFuture>>,
+        shared: Arc<LocalShared>,
+    }
+
+    // 可以从其他线程访问的那部分状态。
+    //
+    // 唤醒可能来自任何线程（比如定时器线程），而本地任务的未来只能在驱动`LocalSet`的线程上被触碰，
+    // 所以本地任务的唤醒者只持有任务的编号：被唤醒时它把编号排进`queue`，
+    // 再唤醒驱动`run_until`的那个任务，真正的轮询总是发生在正确的线程上。
+    struct LocalShared {
+        queue: Mutex<VecDeque<Id>>,
+        // 正在驱动`run_until`的任务的唤醒者。
+        waker: Mutex<Option<Waker>>,
+    }
+
+    // 本地任务的唤醒者。
+    struct LocalWaker {
+        id: Id,
+        shared: Arc<LocalShared>,
+    }
+
+    impl LocalSet {
+        /// 创建一个空的`LocalSet`。
+        pub fn new() -> LocalSet {
+            LocalSet {
+                inner: Rc::new(LocalInner {
+                    tasks: RefCell::new(HashMap::new()),
+                    shared: Arc::new(LocalShared {
+                        queue: Mutex::new(VecDeque::new()),
+                        waker: Mutex::new(None),
+                    }),
+                }),
+            }
+        }
+
+        /// 在这个`LocalSet`上产生一个未来。未来在`run_until`被驱动时才开始执行。
+        pub fn spawn_local<F, T>(&self, future: F) -> JoinHandle<T>
+        where
+            F: Future<Output = T> + 'static,
+            T: 'static,
+        {
+            self.inner.spawn(future)
+        }
+
+        /// 驱动给定的未来直到完成，同时执行这个`LocalSet`上的本地任务。
+        ///
+        /// 给定的未来和本地任务中都可以调用`task::spawn_local`。
+        /// 未来完成时还没有完成的本地任务留在`LocalSet`中，下一次`run_until`时继续执行。
+        pub async fn run_until<F: Future>(&self, future: F) -> F::Output {
+            struct RunUntil<'a, F> {
+                local: &'a LocalSet,
+                future: Pin<Box<F>>,
+            }
+
+            impl<F: Future> Future for RunUntil<'_, F> {
+                type Output = F::Output;
+
+                fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+                    let inner = self.local.inner.clone();
+
+                    // 存储驱动者的唤醒者，本地任务被唤醒时通过它重新轮询这里。
+                    {
+                        let mut waker = inner.shared.waker.lock().unwrap();
+                        match &*waker {
+                            Some(waker) if waker.will_wake(cx.waker()) => {}
+                            _ => *waker = Some(cx.waker().clone()),
+                        }
+                    }
+
+                    let prev = CURRENT_LOCAL.with(|cell| cell.replace(Some(inner.clone())));
+
+                    let output = self.future.as_mut().poll(cx);
+                    if output.is_pending() {
+                        inner.run_queued();
+
+                        // 本轮执行期间又有本地任务被唤醒，让驱动者尽快再轮询一次，
+                        // 而不是在这里一直循环，使同一线程上的其他任务也有机会运行。
+                        if !inner.shared.queue.lock().unwrap().is_empty() {
+                            defer(cx.waker());
+                        }
+                    }
+
+                    CURRENT_LOCAL.with(|cell| cell.replace(prev));
+                    output
+                }
+            }
+
+            RunUntil {
+                local: self,
+                future: Box::pin(future),
+            }
+            .await
+        }
+    }
+
+    impl Default for LocalSet {
+        fn default() -> LocalSet {
+            LocalSet::new()
+        }
+    }
+
+    impl LocalInner {
+        fn spawn<F, T>(&self, future: F) -> JoinHandle<T>
+        where
+            F: Future<Output = T> + 'static,
+            T: 'static,
+        {
+            let (guard, join) = JoinGuard::new(None);
+            let future = async move {
+                match catch_unwind(future).await {
+                    Ok(output) => guard.complete(output),
+                    Err(payload) => guard.panic(payload),
+                }
+            };
+
+            let id = join.id;
+            self.tasks.borrow_mut().insert(id, Box::pin(future));
+
+            // 和被唤醒一样排进队列，使它在下一轮被第一次轮询。
+            LocalWaker {
+                id,
+                shared: self.shared.clone(),
+            }
+            .schedule();
+
+            join
+        }
+
+        // 轮询此刻已经排在队列中的本地任务。
+        fn run_queued(&self) {
+            let n = self.shared.queue.lock().unwrap().len();
+
+            for _ in 0..n {
+                let id = match self.shared.queue.lock().unwrap().pop_front() {
+                    Some(id) => id,
+                    None => break,
+                };
+
+                // 任务已经完成，或者同一个任务在队列中出现了不止一次、已经被轮询过。
+                let mut future = match self.tasks.borrow_mut().remove(&id) {
+                    Some(future) => future,
+                    None => continue,
+                };
+
+                let waker = waker::waker(Arc::new(LocalWaker {
+                    id,
+                    shared: self.shared.clone(),
+                }));
+                let mut cx = Context::from_waker(&waker);
+
+                let pending = {
+                    let _id = IdGuard::enter(id);
+                    future.as_mut().poll(&mut cx).is_pending()
+                };
+                if pending {
+                    self.tasks.borrow_mut().insert(id, future);
+                }
+            }
+        }
+    }
+
+    impl LocalWaker {
+        fn schedule(&self) {
+            self.shared.queue.lock().unwrap().push_back(self.id);
+
+            let waker = self.shared.waker.lock().unwrap().clone();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    impl ArcWake for LocalWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            // 只排队，不轮询：这里可能是任意一个线程。
+            arc_self.schedule();
+        }
+    }
+
+    // 当前正在被`run_until`驱动的`LocalSet`。
+    thread_local! {
+        static CURRENT_LOCAL: RefCell<Option<Rc<LocalInner>>> = const { RefCell::new(None) };
+    }
+
+    /// 一组在当前运行时中催生的任务，按完成的先后取得它们的结果，对应`tokio::task::JoinSet`。
+    ///
+    /// 句柄保存在一个`FuturesUnordered`中：每个句柄有自己的唤醒者，所以`join_next`只查看已经结束的任务，
+    /// 而不是每次被唤醒都轮询整个集合。集合被丢弃时，所有还没有结束的任务都被取消。
+    pub struct JoinSet<T> {
+        handles: FuturesUnordered<JoinHandle<T>>,
+    }
+
+    impl<T: Send + 'static> JoinSet<T> {
+        /// 创建一个空的集合。
+        pub fn new() -> JoinSet<T> {
+            JoinSet {
+                handles: FuturesUnordered::new(),
+            }
+        }
+
+        /// 在当前运行时中催生一个任务并把它加入集合，返回可以单独取消它的`AbortHandle`。
+        ///
+        /// # Panics
+        ///
+        /// 和`spawn`相同。
+        pub fn spawn<F>(&mut self, future: F) -> AbortHandle
+        where
+            F: Future<Output = T> + Send + 'static,
+        {
+            let join = spawn(future);
+            let abort = join.abort_handle();
+            self.handles.push(join);
+            abort
+        }
+
+        /// 等待集合中的下一个任务结束，返回它的结果。集合为空时返回`None`。
+        ///
+        /// 被取消的任务也会出现在这里，结果是`JoinError::Cancelled`。
+        pub async fn join_next(&mut self) -> Option<Result<T, JoinError>> {
+            self.handles.next().await
+        }
+
+        /// 取消集合中所有还没有结束的任务。它们仍然留在集合中，之后的`join_next`逐个返回`JoinError::Cancelled`。
+        pub fn abort_all(&mut self) {
+            for (join, _) in self.handles.futures.values() {
+                join.abort();
+            }
+        }
+    }
+
+    impl<T> JoinSet<T> {
+        /// 集合中还没有被`join_next`取走的任务数。
+        pub fn len(&self) -> usize {
+            self.handles.len()
+        }
+
+        /// 集合是否为空。
+        pub fn is_empty(&self) -> bool {
+            self.handles.is_empty()
+        }
+    }
+
+    impl<T: Send + 'static> Default for JoinSet<T> {
+        fn default() -> JoinSet<T> {
+            JoinSet::new()
+        }
+    }
+
+    // 集合是任务的所有者：没有人会再取得它们的结果，所以还在运行的任务被取消。
+    impl<T> Drop for JoinSet<T> {
+        fn drop(&mut self) {
+            for (join, _) in self.handles.futures.values() {
+                join.abort();
+            }
+        }
+    }
+
+    /// 用来在催生之前配置一个任务，对应`tokio::task::Builder`。
+    ///
+    /// ```ignore
+    /// let join = task::Builder::new().name("accept-loop").spawn(async { accept().await });
+    /// ```
+    ///
+    /// 名字出现在`Builder::on_task_panic`回调的`TaskPanicInfo::task_name`和`JoinHandle`的`Debug`输出中。
+    /// 不设置名字的任务和用`spawn`催生的任务完全一样。
+    #[derive(Debug, Default)]
+    pub struct Builder<'a> {
+        name: Option<&'a str>,
+    }
+
+    impl<'a> Builder<'a> {
+        /// 创建一个没有任何配置的构建器。
+        pub fn new() -> Builder<'a> {
+            Builder::default()
+        }
+
+        /// 给任务起一个名字。
+        pub fn name(self, name: &'a str) -> Builder<'a> {
+            Builder { name: Some(name) }
+        }
+
+        /// 在当前运行时中催生任务。
+        ///
+        /// # Panics
+        ///
+        /// 和`spawn`相同。
+        pub fn spawn<F, T>(self, future: F) -> JoinHandle<T>
+        where
+            F: Future<Output = T> + Send + 'static,
+            T: Send + 'static,
+        {
+            let name = self.name.map(Arc::from);
+            match with_current(|handle| handle.spawn_or_panic(future, Priority::Normal, name)) {
+                Ok(join) => join,
+                Err(err) => panic!("failed to spawn a task: {}", err),
+            }
+        }
+    }
+}
+
+/// 声明一个任务本地变量，对应`tokio::task_local!`。
+///
+/// ```ignore
+/// task_local! {
+///     static REQUEST_ID: u64;
+/// }
+///
+/// REQUEST_ID.scope(7, async {
+///     assert_eq!(REQUEST_ID.with(|id| *id), 7);
+/// }).await;
+/// ```
+macro_rules! task_local {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty;) => {
+        $(#[$attr])*
+        $vis static $name: TaskLocal<$t> = {
+            thread_local! {
+                static VALUE: TaskLocalSlot<$t> = const { Cell::new(None) };
+            }
+
+            TaskLocal { key: &VALUE }
+        };
+    };
+}
+
+// `task_local!`声明的线程本地变量：`scope`正在被轮询时，指向它保存的值，以及轮询它的任务的编号。
+type TaskLocalSlot<T> = Cell<Option<(*const T, Option<task::Id>)>>;
+
+/// 任务本地变量，由`task_local!`声明。
+///
+/// `scope`给一个未来设置变量的值，在这个未来（以及它等待的所有未来）被轮询期间，
+/// `with`可以取得这个值。值保存在未来中，只在每次轮询期间被放进线程本地变量，
+/// 轮询结束时再被取回来，所以同一个线程上交错执行的其他任务看不到它。
+/// 在这次轮询中被轮询的其他任务（例如在另一个运行时的`MiniTokio::block_on`中）也看不到它。
+pub struct TaskLocal<T: 'static> {
+    key: &'static thread::LocalKey<TaskLocalSlot<T>>,
+}
+
+impl<T: 'static> TaskLocal<T> {
+    /// 以`value`作为变量的值驱动`future`直到完成。
+    ///
+    /// 作用域可以嵌套：内层的`scope`在它的未来被轮询期间遮住外层的值。
+    pub async fn scope<F: Future>(&'static self, value: T, future: F) -> F::Output {
+        struct Scope<T: 'static, F> {
+            local: &'static TaskLocal<T>,
+            // 变量的值一直保存在这里，轮询期间线程本地变量指向它。
+            value: T,
+            future: Pin<Box<F>>,
+        }
+
+        // 值只被移动，从不被固定。
+        impl<T, F> Unpin for Scope<T, F> {}
+
+        impl<T, F: Future> Future for Scope<T, F> {
+            type Output = F::Output;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+                // 即使内层的未来panic，也要恢复外层的值。
+                struct Reset<T: 'static> {
+                    key: &'static thread::LocalKey<TaskLocalSlot<T>>,
+                    prev: Option<(*const T, Option<task::Id>)>,
+                }
+
+                impl<T> Drop for Reset<T> {
+                    fn drop(&mut self) {
+                        self.key.with(|cell| cell.set(self.prev));
+                    }
+                }
+
+                let this = &mut *self;
+                let key = this.local.key;
+                let value: *const T = &this.value;
+                let _reset = Reset {
+                    key,
+                    prev: key.with(|cell| cell.replace(Some((value, task::try_id())))),
+                };
+
+                this.future.as_mut().poll(cx)
+            }
+        }
+
+        Scope {
+            local: self,
+            value,
+            future: Box::pin(future),
+        }
+        .await
+    }
+
+    /// 用变量当前的值调用`f`。
+    ///
+    /// # Panics
+    ///
+    /// 不在这个变量的`scope`中时panic。
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.try_with(f)
+            .expect("cannot access a task-local value outside of its `scope`")
+    }
+
+    /// 和`with`一样，但不在`scope`中时返回`None`而不是panic。
+    ///
+    /// 调用`f`时不借用线程本地变量，所以`f`中可以再次进入同一个变量的`scope`。
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let (value, id) = self.key.with(Cell::get)?;
+
+        // 值属于另一个任务，当前任务是在那个任务的轮询中被轮询的。
+        if id != task::try_id() {
+            return None;
+        }
+
+        // 指针只在`Scope::poll`期间留在线程本地变量中。那时`Scope`被可变地借用着，
+        // 它保存的值不会被移动或者丢弃，也没有人持有它的可变引用；返回之前`Reset`恢复之前的值。
+        Some(f(unsafe { &*value }))
+    }
+}
+
+/// 只传递一个值的通道，对应`tokio::sync::oneshot`。
+///
+/// 发送者和接收者可以在不同的任务甚至不同的线程中。接收者是一个未来，
+/// 在值被发送时完成；如果发送者在发送之前被丢弃，接收者得到`RecvError`。
+pub mod oneshot {
+    use super::*;
+
+    /// 创建一个新的一次性通道，返回它的发送端和接收端。
+    pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+        let inner = Arc::new(Inner {
+            value: Mutex::new(None),
+            tx_dropped: AtomicBool::new(false),
+            rx_dropped: AtomicBool::new(false),
+            waker: Mutex::new(None),
+        });
+
+        (
+            Sender {
+                inner: inner.clone(),
+            },
+            Receiver { inner },
+        )
+    }
+
+    /// 一次性通道的发送端。
+    pub struct Sender<T> {
+        inner: Arc<Inner<T>>,
+    }
+
+    /// 一次性通道的接收端。它是一个未来，解析为被发送的值。
+    pub struct Receiver<T> {
+        inner: Arc<Inner<T>>,
+    }
+
+    /// 发送者在发送值之前被丢弃时，接收者得到的错误。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RecvError(());
+
+    // 发送端和接收端共享的状态。
+    //
+    // 两个标志都只在持有`value`的锁时被修改，接收者也在持有这把锁时检查它们并存储唤醒者，
+    // 所以发送和丢弃不会和接收者的检查交错。发送者总是在释放`value`的锁之后才取出唤醒者，
+    // 这样被唤醒的接收者不会在锁上等待。
+    struct Inner<T> {
+        // 被发送的值，接收者取走之前一直保存在这里。
+        value: Mutex<Option<T>>,
+        // 发送者已经被丢弃。发送值也会消耗发送者，所以此后不会再有值到来。
+        tx_dropped: AtomicBool,
+        // 接收者已经被丢弃，之后的发送会失败。
+        rx_dropped: AtomicBool,
+        // 正在等待值的接收者的唤醒者。
+        waker: Mutex<Option<Waker>>,
+    }
+
+    impl<T> Sender<T> {
+        /// 发送一个值，消耗发送者。
+        ///
+        /// 接收者已经被丢弃时返回`Err(value)`，把值还给调用者。
+        pub fn send(self, value: T) -> Result<(), T> {
+            let mut slot = self.inner.value.lock().unwrap();
+
+            if self.inner.rx_dropped.load(Ordering::SeqCst) {
+                return Err(value);
+            }
+
+            *slot = Some(value);
+
+            // 接收者在发送者被丢弃时被唤醒，见`Drop`。
+            Ok(())
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            {
+                let _slot = self.inner.value.lock().unwrap();
+                self.inner.tx_dropped.store(true, Ordering::SeqCst);
+            }
+
+            // 无论值有没有被发送，接收者都可以完成了。
+            if let Some(waker) = self.inner.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T> Future for Receiver<T> {
+        type Output = Result<T, RecvError>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if poll_budget(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            let mut slot = self.inner.value.lock().unwrap();
+
+            if let Some(value) = slot.take() {
+                return Poll::Ready(Ok(value));
+            }
+
+            if self.inner.tx_dropped.load(Ordering::SeqCst) {
+                return Poll::Ready(Err(RecvError(())));
+            }
+
+            // 仍然持有`value`的锁：发送者只能在这之后丢弃自己，那时它会看到这里存储的唤醒者。
+            let mut waker = self.inner.waker.lock().unwrap();
+            match &*waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+
+            Poll::Pending
+        }
+    }
+
+    impl<T> Drop for Receiver<T> {
+        fn drop(&mut self) {
+            // 已经发送但没有被接收的值在释放锁之后才被丢弃。
+            let _value = {
+                let mut slot = self.inner.value.lock().unwrap();
+                self.inner.rx_dropped.store(true, Ordering::SeqCst);
+                slot.take()
+            };
+        }
+    }
+
+    impl fmt::Display for RecvError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("channel closed")
+        }
+    }
+
+    impl std::error::Error for RecvError {}
+}
+
+/// 多个生产者、单个消费者的异步通道，对应`tokio::sync::mpsc`。
+///
+/// 通道的缓冲区有固定的容量。缓冲区为空时`recv`挂起当前任务，缓冲区已满时`send`挂起当前任务，
+/// 直到接收者腾出位置，这样快速的生产者不会让内存无限增长。
+pub mod mpsc {
+    use super::*;
+
+    /// 创建一个缓冲区容量为`capacity`的通道，返回它的发送端和接收端。
+    ///
+    /// # Panics
+    ///
+    /// `capacity`为零时panic。
+    pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        assert!(capacity > 0, "mpsc channel capacity must be greater than 0");
+
+        let chan = Arc::new(Mutex::new(Chan {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            senders: 1,
+            rx_closed: false,
+            rx_waker: None,
+            send_waiters: VecDeque::new(),
+            next_waiter: 0,
+        }));
+
+        (Sender { chan: chan.clone() }, Receiver { chan })
+    }
+
+    /// 通道的发送端，可以被克隆以得到多个生产者。
+    pub struct Sender<T> {
+        chan: Arc<Mutex<Chan<T>>>,
+    }
+
+    /// 通道的接收端。
+    pub struct Receiver<T> {
+        chan: Arc<Mutex<Chan<T>>>,
+    }
+
+    /// 接收者已经被丢弃时`send`返回的错误，里面是没能发送的值。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SendError<T>(pub T);
+
+    // 发送端和接收端共享的状态。
+    struct Chan<T> {
+        // 已经发送、还没有被接收的值。
+        buffer: VecDeque<T>,
+        capacity: usize,
+        // 还活着的发送者的数量。降到零时接收者在缓冲区取空之后得到`None`。
+        senders: usize,
+        // 接收者已经被丢弃。
+        rx_closed: bool,
+        // 正在等待值的接收者的唤醒者。
+        rx_waker: Option<Waker>,
+        // 等待缓冲区空位的发送者，按开始等待的先后排列。编号用来在发送被取消时找到自己。
+        send_waiters: VecDeque<(u64, Waker)>,
+        next_waiter: u64,
+    }
+
+    impl<T> Chan<T> {
+        // 缓冲区腾出了一个位置，唤醒最早开始等待的发送者。
+        fn wake_sender(&mut self) {
+            if let Some((_, waker)) = self.send_waiters.pop_front() {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T> Sender<T> {
+        /// 发送一个值。缓冲区已满时等待，直到接收者腾出位置。
+        ///
+        /// 接收者已经被丢弃时返回`Err(SendError(value))`，把值还给调用者。
+        pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+            // 和`delay`一样，手动定义一个私有的未来。它需要在被丢弃时从等待队列中移除自己。
+            struct Send<'a, T> {
+                chan: &'a Mutex<Chan<T>>,
+                value: Option<T>,
+                // 在等待队列中的编号。值被发送之后为`None`。
+                waiter: Option<u64>,
+            }
+
+            // 值只被移进缓冲区，从不被固定在原地轮询，所以不管`T`是什么，`Send`都可以是`Unpin`的。
+            impl<T> Unpin for Send<'_, T> {}
+
+            impl<T> Future for Send<'_, T> {
+                type Output = Result<(), SendError<T>>;
+
+                fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                    if poll_budget(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+
+                    let this = &mut *self;
+                    let mut chan = this.chan.lock().unwrap();
+
+                    if chan.rx_closed {
+                        this.waiter = None;
+                        let value = this.value.take().unwrap();
+                        return Poll::Ready(Err(SendError(value)));
+                    }
+
+                    if chan.buffer.len() < chan.capacity {
+                        this.waiter = None;
+                        chan.buffer.push_back(this.value.take().unwrap());
+
+                        if let Some(waker) = chan.rx_waker.take() {
+                            waker.wake();
+                        }
+
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    // 缓冲区已满。如果还在等待队列中，就更新唤醒者；
+                    // 如果已经被唤醒过但位置又被别人抢先占用了，就重新排到队尾。
+                    let queued = this.waiter.and_then(|id| {
+                        chan.send_waiters
+                            .iter_mut()
+                            .find(|(waiter, _)| *waiter == id)
+                    });
+
+                    match queued {
+                        Some((_, waker)) => {
+                            if !waker.will_wake(cx.waker()) {
+                                *waker = cx.waker().clone();
+                            }
+                        }
+                        None => {
+                            let id = chan.next_waiter;
+                            chan.next_waiter += 1;
+                            chan.send_waiters.push_back((id, cx.waker().clone()));
+                            this.waiter = Some(id);
+                        }
+                    }
+
+                    Poll::Pending
+                }
+            }
+
+            impl<T> Drop for Send<'_, T> {
+                fn drop(&mut self) {
+                    let id = match self.waiter {
+                        Some(id) => id,
+                        None => return,
+                    };
+
+                    let mut chan = self.chan.lock().unwrap();
+
+                    // 发送在完成之前被取消。还在队列中就移除自己；已经被唤醒的话，
+                    // 那次唤醒是为一个空位准备的，把它转交给下一个等待的发送者，避免其他发送者永远等待。
+                    match chan
+                        .send_waiters
+                        .iter()
+                        .position(|(waiter, _)| *waiter == id)
+                    {
+                        Some(index) => {
+                            chan.send_waiters.remove(index);
+                        }
+                        None => chan.wake_sender(),
+                    }
+                }
+            }
+
+            Send {
+                chan: &self.chan,
+                value: Some(value),
+                waiter: None,
+            }
+            .await
+        }
+    }
+
+    impl<T> Clone for Sender<T> {
+        fn clone(&self) -> Sender<T> {
+            self.chan.lock().unwrap().senders += 1;
+
+            Sender {
+                chan: self.chan.clone(),
+            }
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let mut chan = self.chan.lock().unwrap();
+            chan.senders -= 1;
+
+            // 最后一个发送者被丢弃，正在等待的接收者需要醒来并发现通道已经关闭。
+            if chan.senders == 0 {
+                if let Some(waker) = chan.rx_waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    impl<T> Receiver<T> {
+        /// 接收下一个值。缓冲区为空时等待。
+        ///
+        /// 所有发送者都被丢弃、并且缓冲区中的值都已被接收之后返回`None`。
+        pub async fn recv(&mut self) -> Option<T> {
+            struct Recv<'a, T> {
+                chan: &'a Mutex<Chan<T>>,
+            }
+
+            impl<T> Future for Recv<'_, T> {
+                type Output = Option<T>;
+
+                fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+                    if poll_budget(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+
+                    let mut chan = self.chan.lock().unwrap();
+
+                    if let Some(value) = chan.buffer.pop_front() {
+                        chan.wake_sender();
+                        return Poll::Ready(Some(value));
+                    }
+
+                    if chan.senders == 0 {
+                        return Poll::Ready(None);
+                    }
+
+                    match &chan.rx_waker {
+                        Some(waker) if waker.will_wake(cx.waker()) => {}
+                        _ => chan.rx_waker = Some(cx.waker().clone()),
+                    }
+
+                    Poll::Pending
+                }
+            }
+
+            Recv { chan: &self.chan }.await
+        }
+    }
+
+    impl<T> Drop for Receiver<T> {
+        fn drop(&mut self) {
+            // 缓冲区中的值和被唤醒者在释放锁之后才被丢弃和唤醒。
+            let (buffer, waiters) = {
+                let mut chan = self.chan.lock().unwrap();
+                chan.rx_closed = true;
+                (
+                    std::mem::take(&mut chan.buffer),
+                    std::mem::take(&mut chan.send_waiters),
+                )
+            };
+
+            drop(buffer);
+
+            // 正在等待空位的发送者醒来后会发现接收者已经不在了。
+            for (_, waker) in waiters {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T> fmt::Display for SendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("channel closed")
+        }
+    }
+
+    impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+}
+
+/// 只保存最新值的广播通道，对应`tokio::sync::watch`。
+///
+/// 发送者每次`send`都替换通道中的值并通知所有接收者。接收者用`changed`等待值发生变化，
+/// 用`borrow`读取最新的值。只有最新的值是重要的：接收者来不及观察的中间值会被跳过，
+/// 所以它适合传递配置的重新加载或者关闭信号这样的状态。
+pub mod watch {
+    use super::*;
+
+    /// 创建一个以`initial`为初始值的通道，返回它的发送端和接收端。
+    ///
+    /// 初始值被视为接收者已经看到的值：第一次`changed`等待的是之后的第一次`send`。
+    pub fn channel<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+        let shared = Arc::new(Shared {
+            value: RwLock::new(initial),
+            state: Mutex::new(State {
+                version: 0,
+                tx_dropped: false,
+                receivers: 1,
+                wakers: HashMap::new(),
+                next_id: 1,
+            }),
+        });
+
+        (
+            Sender {
+                shared: shared.clone(),
+            },
+            Receiver {
+                shared,
+                id: 0,
+                version: 0,
+            },
+        )
+    }
+
+    /// 通道的发送端。
+    pub struct Sender<T> {
+        shared: Arc<Shared<T>>,
+    }
+
+    /// 通道的接收端，可以被克隆以得到多个接收者。克隆的接收者从原来的接收者已经看到的值开始。
+    pub struct Receiver<T> {
+        shared: Arc<Shared<T>>,
+        // 在`wakers`中的编号。
+        id: u64,
+        // 这个接收者上一次看到的值的版本。
+        version: u64,
+    }
+
+    /// `borrow`返回的对最新值的引用。持有它期间`send`会等待，所以不要跨越等待点持有它。
+    pub struct Ref<'a, T> {
+        guard: RwLockReadGuard<'a, T>,
+    }
+
+    /// 所有接收者都已经被丢弃时`send`返回的错误，里面是没能发送的值。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SendError<T>(pub T);
+
+    /// 发送者已经被丢弃、不会再有新的值时`changed`返回的错误。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RecvError(());
+
+    // 发送端和接收端共享的状态。
+    //
+    // 值和版本号分开加锁，这样`borrow`不需要和等待的接收者争用同一把锁。
+    // 发送者先写入值，再增加版本号，所以看到新版本号的接收者读到的一定是新的值（或者更新的值）。
+    struct Shared<T> {
+        value: RwLock<T>,
+        state: Mutex<State>,
+    }
+
+    struct State {
+        // 每次`send`加一。
+        version: u64,
+        // 发送者已经被丢弃，之后版本号不会再变。
+        tx_dropped: bool,
+        // 还活着的接收者的数量。降到零时`send`失败。
+        receivers: usize,
+        // 正在等待`changed`的接收者的唤醒者，按接收者的编号索引，
+        // 所以同一个接收者被轮询多次也只占一个位置。
+        wakers: HashMap<u64, Waker>,
+        next_id: u64,
+    }
+
+    impl<T> Sender<T> {
+        /// 用`value`替换通道中的值，并唤醒所有正在等待`changed`的接收者。
+        ///
+        /// 所有接收者都已经被丢弃时返回`Err(SendError(value))`，把值还给调用者。
+        pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+            if self.shared.state.lock().unwrap().receivers == 0 {
+                return Err(SendError(value));
+            }
+
+            // 旧的值在释放写锁之后才被丢弃。
+            let old = std::mem::replace(&mut *self.shared.value.write().unwrap(), value);
+            drop(old);
+
+            // 唤醒者在释放锁之后再调用，被唤醒的接收者不会在锁上等待。
+            let wakers = {
+                let mut state = self.shared.state.lock().unwrap();
+                state.version += 1;
+                std::mem::take(&mut state.wakers)
+            };
+
+            for (_, waker) in wakers {
+                waker.wake();
+            }
+
+            Ok(())
+        }
+
+        /// 读取通道中当前的值。
+        pub fn borrow(&self) -> Ref<'_, T> {
+            Ref {
+                guard: self.shared.value.read().unwrap(),
+            }
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let wakers = {
+                let mut state = self.shared.state.lock().unwrap();
+                state.tx_dropped = true;
+                std::mem::take(&mut state.wakers)
+            };
+
+            // 正在等待的接收者醒来后会发现不会再有新的值了。
+            for (_, waker) in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T> Receiver<T> {
+        /// 读取最新的值。不会把它标记为已经看到：之后的`changed`仍然会因为它而完成。
+        pub fn borrow(&self) -> Ref<'_, T> {
+            Ref {
+                guard: self.shared.value.read().unwrap(),
+            }
+        }
+
+        /// 等待值在这个接收者上一次看到之后发生变化，并把最新的值标记为已经看到。
+        ///
+        /// 上一次看到之后已经有过`send`时立即完成。发送者已经被丢弃、并且没有还没看到的值时返回`Err(RecvError)`。
+        pub async fn changed(&mut self) -> Result<(), RecvError> {
+            // 和`mpsc::Receiver::recv`一样，手动定义一个私有的未来。
+            struct Changed<'a, T> {
+                rx: &'a mut Receiver<T>,
+            }
+
+            impl<T> Future for Changed<'_, T> {
+                type Output = Result<(), RecvError>;
+
+                fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                    if poll_budget(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+
+                    let rx = &mut *self.rx;
+                    let mut state = rx.shared.state.lock().unwrap();
+
+                    if state.version != rx.version {
+                        rx.version = state.version;
+                        return Poll::Ready(Ok(()));
+                    }
+
+                    if state.tx_dropped {
+                        return Poll::Ready(Err(RecvError(())));
+                    }
+
+                    // 仍然持有锁：之后的`send`一定会看到这里存储的唤醒者。
+                    match state.wakers.get_mut(&rx.id) {
+                        Some(waker) if waker.will_wake(cx.waker()) => {}
+                        Some(waker) => *waker = cx.waker().clone(),
+                        None => {
+                            state.wakers.insert(rx.id, cx.waker().clone());
+                        }
+                    }
+
+                    Poll::Pending
+                }
+            }
+
+            Changed { rx: self }.await
+        }
+    }
+
+    impl<T> Clone for Receiver<T> {
+        fn clone(&self) -> Receiver<T> {
+            let mut state = self.shared.state.lock().unwrap();
+            state.receivers += 1;
+            let id = state.next_id;
+            state.next_id += 1;
+
+            Receiver {
+                shared: self.shared.clone(),
+                id,
+                version: self.version,
+            }
+        }
+    }
+
+    impl<T> Drop for Receiver<T> {
+        fn drop(&mut self) {
+            let mut state = self.shared.state.lock().unwrap();
+            state.receivers -= 1;
+            state.wakers.remove(&self.id);
+        }
+    }
+
+    impl<T> std::ops::Deref for Ref<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.guard
+        }
+    }
+
+    impl<T> fmt::Display for SendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("channel closed")
+        }
+    }
+
+    impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+    impl fmt::Display for RecvError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("channel closed")
+        }
+    }
+
+    impl std::error::Error for RecvError {}
+}
+
+/// 多个生产者、多个消费者的广播通道，对应`tokio::sync::broadcast`。
+///
+/// 每个接收者都能收到每一条消息的副本。消息保存在一个容量固定的环形缓冲区中，
+/// 每个接收者有自己的读取位置：落后超过`capacity`条消息的接收者会丢失最旧的那些消息，
+/// 下一次`recv`返回`RecvError::Lagged`告诉它错过了多少条，然后从还在缓冲区中的最旧的消息继续。
+/// 发送从不等待，所以慢的接收者不会拖慢发送者。
+pub mod broadcast {
+    use super::*;
+
+    /// 创建一个缓冲区容量为`capacity`的通道，返回它的发送端和第一个接收端。
+    /// 更多的接收端通过`Sender::subscribe`得到。
+    ///
+    /// # Panics
+    ///
+    /// `capacity`为零时panic。
+    pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        assert!(
+            capacity > 0,
+            "broadcast channel capacity must be greater than 0"
+        );
+
+        let shared = Arc::new(Mutex::new(Shared {
+            buffer: (0..capacity).map(|_| None).collect(),
+            tail: 0,
+            senders: 1,
+            receivers: 1,
+            wakers: HashMap::new(),
+            next_id: 1,
+        }));
+
+        (
+            Sender {
+                shared: shared.clone(),
+            },
+            Receiver {
+                shared,
+                id: 0,
+                next: 0,
+            },
+        )
+    }
+
+    /// 通道的发送端，可以被克隆以得到多个生产者。
+    pub struct Sender<T> {
+        shared: Arc<Mutex<Shared<T>>>,
+    }
+
+    /// 通道的接收端。
+    pub struct Receiver<T> {
+        shared: Arc<Mutex<Shared<T>>>,
+        // 在`wakers`中的编号。
+        id: u64,
+        // 这个接收者要读取的下一条消息的序号。
+        next: u64,
+    }
+
+    /// 所有接收者都已经被丢弃时`send`返回的错误，里面是没能发送的值。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SendError<T>(pub T);
+
+    /// `recv`返回的错误。
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum RecvError {
+        /// 所有发送者都已经被丢弃，并且缓冲区中的消息都已被这个接收者收到。
+        Closed,
+        /// 接收者落后太多，错过了这么多条已经被新消息覆盖的消息。下一次`recv`从最旧的还在缓冲区中的消息继续。
+        Lagged(u64),
+    }
+
+    // 发送端和接收端共享的状态。
+    struct Shared<T> {
+        // 环形缓冲区。序号为`n`的消息保存在`buffer[n % capacity]`，直到被序号为`n + capacity`的消息覆盖。
+        // 消息被包在`Arc`中，这样接收者在锁中只需要增加引用计数，克隆值的工作在释放锁之后进行。
+        buffer: Vec<Option<Arc<T>>>,
+        // 已经发送的消息数，也就是下一条消息的序号。
+        tail: u64,
+        // 还活着的发送者和接收者的数量。
+        senders: usize,
+        receivers: usize,
+        // 正在等待消息的接收者的唤醒者，按接收者的编号索引。
+        wakers: HashMap<u64, Waker>,
+        next_id: u64,
+    }
+
+    impl<T> Shared<T> {
+        // 还在缓冲区中的最旧的消息的序号。
+        fn head(&self) -> u64 {
+            self.tail.saturating_sub(self.buffer.len() as u64)
+        }
+    }
+
+    impl<T> Sender<T> {
+        /// 向所有接收者发送一条消息，返回接收者的数量。从不等待：缓冲区已满时最旧的消息被覆盖。
+        ///
+        /// 没有接收者时返回`Err(SendError(value))`，把值还给调用者。
+        pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+            let (old, wakers, receivers) = {
+                let mut shared = self.shared.lock().unwrap();
+
+                if shared.receivers == 0 {
+                    return Err(SendError(value));
+                }
+
+                let index = (shared.tail % shared.buffer.len() as u64) as usize;
+                let old = shared.buffer[index].replace(Arc::new(value));
+                shared.tail += 1;
+
+                (old, std::mem::take(&mut shared.wakers), shared.receivers)
+            };
+
+            // 被覆盖的消息和唤醒者在释放锁之后才被丢弃和调用。
+            drop(old);
+            for (_, waker) in wakers {
+                waker.wake();
+            }
+
+            Ok(receivers)
+        }
+
+        /// 创建一个新的接收者，它收到从现在开始发送的消息。
+        pub fn subscribe(&self) -> Receiver<T> {
+            let mut shared = self.shared.lock().unwrap();
+            shared.receivers += 1;
+            let id = shared.next_id;
+            shared.next_id += 1;
+
+            Receiver {
+                shared: self.shared.clone(),
+                id,
+                next: shared.tail,
+            }
+        }
+    }
+
+    impl<T> Clone for Sender<T> {
+        fn clone(&self) -> Sender<T> {
+            self.shared.lock().unwrap().senders += 1;
+
+            Sender {
+                shared: self.shared.clone(),
+            }
+        }
+    }
+
+    impl<T> Drop for Sender<T> {
+        fn drop(&mut self) {
+            let wakers = {
+                let mut shared = self.shared.lock().unwrap();
+                shared.senders -= 1;
+
+                if shared.senders > 0 {
+                    return;
+                }
+                std::mem::take(&mut shared.wakers)
+            };
+
+            // 最后一个发送者被丢弃，正在等待的接收者需要醒来并发现通道已经关闭。
+            for (_, waker) in wakers {
+                waker.wake();
+            }
+        }
+    }
+
+    impl<T: Clone> Receiver<T> {
+        /// 接收下一条消息的副本。没有新的消息时等待。
+        pub async fn recv(&mut self) -> Result<T, RecvError> {
+            // 和`mpsc::Receiver::recv`一样，手动定义一个私有的未来。
+            struct Recv<'a, T> {
+                rx: &'a mut Receiver<T>,
+            }
+
+            impl<T: Clone> Future for Recv<'_, T> {
+                type Output = Result<T, RecvError>;
+
+                fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                    if poll_budget(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+
+                    let rx = &mut *self.rx;
+                    let message = {
+                        let mut shared = rx.shared.lock().unwrap();
+
+                        // 要读取的消息已经被覆盖了，跳到最旧的还在缓冲区中的消息。
+                        let head = shared.head();
+                        if rx.next < head {
+                            let missed = head - rx.next;
+                            rx.next = head;
+                            return Poll::Ready(Err(RecvError::Lagged(missed)));
+                        }
+
+                        if rx.next == shared.tail {
+                            if shared.senders == 0 {
+                                return Poll::Ready(Err(RecvError::Closed));
+                            }
+
+                            // 仍然持有锁：之后的`send`一定会看到这里存储的唤醒者。
+                            match shared.wakers.get_mut(&rx.id) {
+                                Some(waker) if waker.will_wake(cx.waker()) => {}
+                                Some(waker) => *waker = cx.waker().clone(),
+                                None => {
+                                    shared.wakers.insert(rx.id, cx.waker().clone());
+                                }
+                            }
+
+                            return Poll::Pending;
+                        }
+
+                        let index = (rx.next % shared.buffer.len() as u64) as usize;
+                        rx.next += 1;
+                        shared.buffer[index].clone().unwrap()
+                    };
+
+                    Poll::Ready(Ok(T::clone(&message)))
+                }
+            }
+
+            Recv { rx: self }.await
+        }
+    }
+
+    impl<T> Drop for Receiver<T> {
+        fn drop(&mut self) {
+            let mut shared = self.shared.lock().unwrap();
+            shared.receivers -= 1;
+            shared.wakers.remove(&self.id);
+        }
+    }
+
+    impl<T> fmt::Display for SendError<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("channel closed")
+        }
+    }
+
+    impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+    impl fmt::Display for RecvError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                RecvError::Closed => f.write_str("channel closed"),
+                RecvError::Lagged(missed) => write!(f, "receiver lagged by {} messages", missed),
+            }
+        }
+    }
+
+    impl std::error::Error for RecvError {}
+}
+
+/// 异步的迭代，对应`futures::stream`和`tokio_stream`。
+///
+/// `Stream`之于`Iterator`，就像`Future`之于一个普通的值：每个元素都可能需要等待。
+/// `StreamExt`中的适配器和迭代器的适配器一样是惰性的：它们只是包住内层的流，
+/// 在被轮询时才去轮询内层的流，不被轮询就什么都不做。
+pub mod stream {
+    use super::*;
+
+    /// 一个异步产生一系列值的序列。
+    pub trait Stream {
+        /// 产生的值的类型。
+        type Item;
+
+        /// 尝试取出下一个值。
+        ///
+        /// 下一个值还没有准备好时返回`Poll::Pending`，并安排在它准备好时唤醒`cx`中的唤醒者，
+        /// 和`Future::poll`一样。序列结束时返回`Poll::Ready(None)`。
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>;
+    }
+
+    /// `Stream`的扩展方法，为所有的流实现。
+    pub trait StreamExt: Stream {
+        /// 等待下一个值，序列结束时返回`None`。
+        fn next(&mut self) -> Next<'_, Self>
+        where
+            Self: Unpin,
+        {
+            Next { stream: self }
+        }
+
+        /// 用`f`变换每一个值。
+        fn map<T, F>(self, f: F) -> Map<Self, F>
+        where
+            F: FnMut(Self::Item) -> T,
+            Self: Sized,
+        {
+            Map {
+                stream: Box::pin(self),
+                f,
+            }
+        }
+
+        /// 只保留`predicate`返回`true`的值。
+        fn filter<F>(self, predicate: F) -> Filter<Self, F>
+        where
+            F: FnMut(&Self::Item) -> bool,
+            Self: Sized,
+        {
+            Filter {
+                stream: Box::pin(self),
+                predicate,
+            }
+        }
+
+        /// 最多产生`n`个值。取到`n`个之后内层的流不会再被轮询。
+        fn take(self, n: usize) -> Take<Self>
+        where
+            Self: Sized,
+        {
+            Take {
+                stream: Box::pin(self),
+                remaining: n,
+            }
+        }
+    }
+
+    impl<S: Stream + ?Sized> StreamExt for S {}
+
+    /// 把一个迭代器变成一个流，每个值都立即就绪。
+    pub fn iter<I: IntoIterator>(iter: I) -> Iter<I::IntoIter> {
+        Iter {
+            iter: iter.into_iter(),
+        }
+    }
+
+    /// `iter`返回的流。
+    #[derive(Debug, Clone)]
+    pub struct Iter<I> {
+        iter: I,
+    }
+
+    /// `StreamExt::next`返回的未来。
+    pub struct Next<'a, S: ?Sized> {
+        stream: &'a mut S,
+    }
+
+    /// `StreamExt::map`返回的流。
+    ///
+    /// 和`Timeout`一样，内层的流被装箱固定，这样适配器本身是`Unpin`的，不需要不安全的固定投影。
+    pub struct Map<S, F> {
+        stream: Pin<Box<S>>,
+        f: F,
+    }
+
+    /// `StreamExt::filter`返回的流。
+    pub struct Filter<S, F> {
+        stream: Pin<Box<S>>,
+        predicate: F,
+    }
+
+    /// `StreamExt::take`返回的流。
+    pub struct Take<S> {
+        stream: Pin<Box<S>>,
+        remaining: usize,
+    }
+
+    // 闭包只被调用，从不被固定在原地，所以不管它是什么，适配器都可以是`Unpin`的。
+    impl<S, F> Unpin for Map<S, F> {}
+    impl<S, F> Unpin for Filter<S, F> {}
+
+    impl<I: Iterator + Unpin> Stream for Iter<I> {
+        type Item = I::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            // 每个值都立即就绪，一个很长的迭代器也不能独占执行器线程。
+            if poll_budget(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            Poll::Ready(self.iter.next())
+        }
+    }
+
+    impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S> {
+        type Output = Option<S::Item>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            Pin::new(&mut *self.stream).poll_next(cx)
+        }
+    }
+
+    impl<S: Stream, T, F: FnMut(S::Item) -> T> Stream for Map<S, F> {
+        type Item = T;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+            let this = &mut *self;
+
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(item) => Poll::Ready(item.map(&mut this.f)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    impl<S: Stream, F: FnMut(&S::Item) -> bool> Stream for Filter<S, F> {
+        type Item = S::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+            let this = &mut *self;
+
+            // 跳过被过滤掉的值，直到找到一个被保留的值，或者内层的流需要等待。
+            loop {
+                match this.stream.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(item)) if !(this.predicate)(&item) => {}
+                    other => return other,
+                }
+            }
+        }
+    }
+
+    impl<S: Stream> Stream for Take<S> {
+        type Item = S::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+            if self.remaining == 0 {
+                return Poll::Ready(None);
+            }
+
+            let item = match self.stream.as_mut().poll_next(cx) {
+                Poll::Ready(item) => item,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            // 内层的流提前结束时不再轮询它。
+            self.remaining = match item {
+                Some(_) => self.remaining - 1,
+                None => 0,
+            };
+
+            Poll::Ready(item)
+        }
+    }
+}
+
+/// 异步的互斥锁。
+///
+/// 锁被其他任务持有时，`lock`挂起当前任务而不是阻塞执行器线程，锁被释放时任务按照开始等待的先后被唤醒。
+/// 和`std::sync::Mutex`不同，它的守卫可以跨越等待点持有：
+///
+/// ```ignore
+/// let counter = Arc::new(AsyncMutex::new(0));
+/// let mut guard = counter.lock().await;
+/// delay(Duration::from_millis(10)).await;
+/// *guard += 1;
+/// ```
+pub struct AsyncMutex<T> {
+    state: Mutex<LockState>,
+    // 被保护的值。持有锁的守卫把值取出来，被丢弃时再放回去，所以这里的锁只在取出和放回时被短暂持有，
+    // 从不跨越等待点。守卫不能直接持有`std::sync::MutexGuard`，它不是`Send`。
+    value: Mutex<Option<T>>,
+}
+
+/// `AsyncMutex::lock`返回的守卫。守卫被丢弃时锁被释放，最早开始等待的任务被唤醒。
+pub struct MutexGuard<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+    // 从互斥锁中取出的值。丢弃守卫时先把它放回去，再把锁交给下一个等待者。
+    value: Option<T>,
+}
+
+// 异步锁的状态。
+struct LockState {
+    // 锁被某个守卫持有，或者正在被交给`handoff`中的等待者。
+    locked: bool,
+    // 等待锁的任务，按开始等待的先后排列。编号用来在等待被取消时找到自己。
+    waiters: VecDeque<(u64, Waker)>,
+    next_waiter: u64,
+    // 释放锁的守卫直接把锁交给了这个等待者，它被唤醒之后不需要再去竞争。
+    // 这样刚开始等待的任务不能抢在已经被唤醒的任务前面取得锁。
+    handoff: Option<u64>,
+}
+
+impl LockState {
+    // 锁被释放，或者得到锁的等待者在取得它之前被取消了：交给下一个等待者，没有等待者时解锁。
+    fn release(&mut self) {
+        match self.waiters.pop_front() {
+            Some((id, waker)) => {
+                self.handoff = Some(id);
+                waker.wake();
+            }
+            None => {
+                self.handoff = None;
+                self.locked = false;
+            }
+        }
+    }
+}
+
+impl<T> AsyncMutex<T> {
+    /// 创建一个没有被锁住的互斥锁。
+    pub fn new(value: T) -> AsyncMutex<T> {
+        AsyncMutex {
+            state: Mutex::new(LockState {
+                locked: false,
+                waiters: VecDeque::new(),
+                next_waiter: 0,
+                handoff: None,
+            }),
+            value: Mutex::new(Some(value)),
+        }
+    }
+
+    /// 取得锁。锁被其他任务持有时等待，直到它被释放并轮到当前任务。
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        // 和`mpsc::Sender::send`一样，私有的未来需要在被丢弃时从等待队列中移除自己。
+        struct Lock<'a, T> {
+            mutex: &'a AsyncMutex<T>,
+            // 在等待队列中的编号。取得锁之后为`None`。
+            waiter: Option<u64>,
+        }
+
+        impl<'a, T> Future for Lock<'a, T> {
+            type Output = MutexGuard<'a, T>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<MutexGuard<'a, T>> {
+                if poll_budget(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                let mutex = self.mutex;
+                let mut state = mutex.state.lock().unwrap();
+
+                let acquired = match self.waiter {
+                    // 锁已经被交给了这个任务。
+                    Some(id) => state.handoff == Some(id),
+                    // 第一次轮询。有其他任务在等待时排到它们后面。
+                    None => !state.locked,
+                };
+
+                if acquired {
+                    state.locked = true;
+                    state.handoff = None;
+                    drop(state);
+                    self.waiter = None;
+                    return Poll::Ready(mutex.guard());
+                }
+
+                // 还没有轮到这个任务。可能是第一次轮询，也可能是被虚假唤醒：
+                // 还在等待队列中就更新唤醒者，否则排到队尾。
+                let queued = self
+                    .waiter
+                    .and_then(|id| state.waiters.iter_mut().find(|(waiter, _)| *waiter == id));
+
+                match queued {
+                    Some((_, waker)) => {
+                        if !waker.will_wake(cx.waker()) {
+                            *waker = cx.waker().clone();
+                        }
+                    }
+                    None => {
+                        let id = state.next_waiter;
+                        state.next_waiter += 1;
+                        state.waiters.push_back((id, cx.waker().clone()));
+                        self.waiter = Some(id);
+                    }
+                }
+
+                Poll::Pending
+            }
+        }
+
+        impl<T> Drop for Lock<'_, T> {
+            fn drop(&mut self) {
+                let id = match self.waiter {
+                    Some(id) => id,
+                    None => return,
+                };
+
+                let mut state = self.mutex.state.lock().unwrap();
+
+                // 等待在取得锁之前被取消。锁已经交给了这个任务的话，把它转交给下一个等待者，
+                // 否则只是从等待队列中移除自己。
+                if state.handoff == Some(id) {
+                    state.release();
+                } else if let Some(index) =
+                    state.waiters.iter().position(|(waiter, _)| *waiter == id)
+                {
+                    state.waiters.remove(index);
+                }
+            }
+        }
+
+        Lock {
+            mutex: self,
+            waiter: None,
+        }
+        .await
+    }
+
+    /// 消耗互斥锁，返回被保护的值。
+    pub fn into_inner(self) -> T {
+        self.value.into_inner().unwrap().unwrap()
+    }
+
+    // 已经取得了锁，取出被保护的值。
+    fn guard(&self) -> MutexGuard<'_, T> {
+        let value = self.value.lock().unwrap().take();
+
+        MutexGuard {
+            mutex: self,
+            value: Some(value.expect("the value is taken without holding the lock")),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().unwrap()
+    }
+}
+
+impl<T> std::ops::DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // 先放回被保护的值，下一个得到锁的任务才能取出它。持有守卫的任务panic时也是如此。
+        *self.mutex.value.lock().unwrap() = self.value.take();
+        self.mutex.state.lock().unwrap().release();
+    }
+}
+
+/// 用来限制并发数量的信号量。
+///
+/// 信号量持有一定数量的许可。`acquire`取走一个许可，没有可用的许可时挂起当前任务，
+/// 直到某个`Permit`被丢弃；等待的任务按照开始等待的先后得到许可。
+///
+/// ```ignore
+/// let semaphore = Arc::new(Semaphore::new(2));
+/// let _permit = semaphore.acquire().await;
+/// // 同时最多两个任务执行到这里。
+/// ```
+pub struct Semaphore {
+    state: Mutex<SemaphoreState>,
+}
+
+/// `Semaphore::acquire`返回的许可。许可被丢弃时归还给信号量。
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+// 信号量的状态。
+struct SemaphoreState {
+    // 可用的许可数。有任务在等待时，被归还的许可直接交给它们，所以这时它总是零。
+    permits: usize,
+    // 等待许可的任务，按开始等待的先后排列。
+    waiters: VecDeque<(u64, Waker)>,
+    next_waiter: u64,
+    // 已经得到了被归还的许可、但还没有被重新轮询的等待者。
+    granted: Vec<u64>,
+}
+
+impl SemaphoreState {
+    // 归还一个许可：交给最早开始等待的任务，没有等待者时放回可用的许可中。
+    fn release(&mut self) {
+        match self.waiters.pop_front() {
+            Some((id, waker)) => {
+                self.granted.push(id);
+                waker.wake();
+            }
+            None => self.permits += 1,
+        }
+    }
+}
+
+impl Semaphore {
+    /// 创建一个持有`permits`个许可的信号量。
+    pub fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            state: Mutex::new(SemaphoreState {
+                permits,
+                waiters: VecDeque::new(),
+                next_waiter: 0,
+                granted: Vec::new(),
+            }),
+        }
+    }
+
+    /// 当前可用的许可数。
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    /// 取走一个许可。没有可用的许可时等待，直到有许可被归还并轮到当前任务。
+    pub async fn acquire(&self) -> Permit<'_> {
+        // 和`AsyncMutex::lock`一样，等待被取消时需要从等待队列中移除自己。
+        struct Acquire<'a> {
+            semaphore: &'a Semaphore,
+            // 在等待队列中的编号。得到许可之后为`None`。
+            waiter: Option<u64>,
+        }
+
+        impl<'a> Future for Acquire<'a> {
+            type Output = Permit<'a>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Permit<'a>> {
+                if poll_budget(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                let semaphore = self.semaphore;
+                let mut state = semaphore.state.lock().unwrap();
+
+                let acquired = match self.waiter {
+                    Some(id) => match state.granted.iter().position(|granted| *granted == id) {
+                        Some(index) => {
+                            state.granted.swap_remove(index);
+                            true
+                        }
+                        None => false,
+                    },
+                    // 第一次轮询。有任务在等待时可用的许可总是零，所以不会抢在它们前面。
+                    None if state.permits > 0 => {
+                        state.permits -= 1;
+                        true
+                    }
+                    None => false,
+                };
+
+                if acquired {
+                    self.waiter = None;
+                    return Poll::Ready(Permit { semaphore });
+                }
+
+                let queued = self
+                    .waiter
+                    .and_then(|id| state.waiters.iter_mut().find(|(waiter, _)| *waiter == id));
+
+                match queued {
+                    Some((_, waker)) => {
+                        if !waker.will_wake(cx.waker()) {
+                            *waker = cx.waker().clone();
+                        }
+                    }
+                    None => {
+                        let id = state.next_waiter;
+                        state.next_waiter += 1;
+                        state.waiters.push_back((id, cx.waker().clone()));
+                        self.waiter = Some(id);
+                    }
+                }
+
+                Poll::Pending
+            }
+        }
+
+        impl Drop for Acquire<'_> {
+            fn drop(&mut self) {
+                let id = match self.waiter {
+                    Some(id) => id,
+                    None => return,
+                };
+
+                let mut state = self.semaphore.state.lock().unwrap();
+
+                // 已经得到的许可转交给下一个等待者，否则只是从等待队列中移除自己。
+                if let Some(index) = state.granted.iter().position(|granted| *granted == id) {
+                    state.granted.swap_remove(index);
+                    state.release();
+                } else if let Some(index) =
+                    state.waiters.iter().position(|(waiter, _)| *waiter == id)
+                {
+                    state.waiters.remove(index);
+                }
+            }
+        }
+
+        Acquire {
+            semaphore: self,
+            waiter: None,
+        }
+        .await
+    }
+
+    /// 不等待地取走一个许可。没有可用的许可时返回`None`。
+    pub fn try_acquire(&self) -> Option<Permit<'_>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.permits == 0 {
+            return None;
+        }
+
+        state.permits -= 1;
+        Some(Permit { semaphore: self })
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.state.lock().unwrap().release();
+    }
+}
+
+/// 在任务之间传递事件的通知。
+///
+/// `notified`挂起当前任务，直到收到通知。两种通知的区别在于没有任务在等待时：
+///
+/// - `notify_one`唤醒最早开始等待的一个任务；没有任务在等待时存储一个许可，
+///   下一次`notified`立即完成。许可最多只有一个，多次通知不会累积。
+/// - `notify_waiters`唤醒此刻正在等待的所有任务，不存储许可：之后才开始等待的任务不受影响。
+///
+/// 一个`notified`的未来在第一次被轮询时才开始等待。
+pub struct Notify {
+    state: Mutex<NotifyState>,
+}
+
+// 通知的状态。
+struct NotifyState {
+    // `notify_one`在没有等待者时存储的许可。
+    permit: bool,
+    // 正在等待通知的任务，按开始等待的先后排列。
+    waiters: VecDeque<(u64, Waker)>,
+    next_waiter: u64,
+    // 已经收到通知、但还没有被重新轮询的等待者。第二项表示通知是否来自`notify_one`：
+    // 这样的等待者在完成之前被取消时，通知要转交给下一个等待者，不能丢失。
+    notified: Vec<(u64, bool)>,
+}
+
+impl NotifyState {
+    // 通知最早开始等待的任务，没有等待者时存储许可。
+    fn notify_one(&mut self) {
+        match self.waiters.pop_front() {
+            Some((id, waker)) => {
+                self.notified.push((id, true));
+                waker.wake();
+            }
+            None => self.permit = true,
+        }
+    }
+}
+
+impl Notify {
+    /// 创建一个没有许可的通知。
+    pub fn new() -> Notify {
+        Notify {
+            state: Mutex::new(NotifyState {
+                permit: false,
+                waiters: VecDeque::new(),
+                next_waiter: 0,
+                notified: Vec::new(),
+            }),
+        }
+    }
+
+    /// 等待通知。存储着许可时消耗它并立即完成。
+    pub async fn notified(&self) {
+        // 和`Semaphore::acquire`一样，等待被取消时需要从等待队列中移除自己。
+        struct Notified<'a> {
+            notify: &'a Notify,
+            // 在等待队列中的编号。完成之后为`None`。
+            waiter: Option<u64>,
+        }
+
+        impl Future for Notified<'_> {
+            type Output = ();
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if poll_budget(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                let mut state = self.notify.state.lock().unwrap();
+
+                let done = match self.waiter {
+                    Some(id) => match state.notified.iter().position(|(waiter, _)| *waiter == id) {
+                        Some(index) => {
+                            state.notified.swap_remove(index);
+                            true
+                        }
+                        None => false,
+                    },
+                    None => std::mem::take(&mut state.permit),
+                };
+
+                if done {
+                    self.waiter = None;
+                    return Poll::Ready(());
+                }
+
+                let queued = self
+                    .waiter
+                    .and_then(|id| state.waiters.iter_mut().find(|(waiter, _)| *waiter == id));
+
+                match queued {
+                    Some((_, waker)) => {
+                        if !waker.will_wake(cx.waker()) {
+                            *waker = cx.waker().clone();
+                        }
+                    }
+                    None => {
+                        let id = state.next_waiter;
+                        state.next_waiter += 1;
+                        state.waiters.push_back((id, cx.waker().clone()));
+                        self.waiter = Some(id);
+                    }
+                }
+
+                Poll::Pending
+            }
+        }
+
+        impl Drop for Notified<'_> {
+            fn drop(&mut self) {
+                let id = match self.waiter {
+                    Some(id) => id,
+                    None => return,
+                };
+
+                let mut state = self.notify.state.lock().unwrap();
+
+                match state.notified.iter().position(|(waiter, _)| *waiter == id) {
+                    Some(index) => {
+                        let (_, one) = state.notified.swap_remove(index);
+                        if one {
+                            state.notify_one();
+                        }
+                    }
+                    None => {
+                        if let Some(index) =
+                            state.waiters.iter().position(|(waiter, _)| *waiter == id)
+                        {
+                            state.waiters.remove(index);
+                        }
+                    }
+                }
+            }
+        }
+
+        Notified {
+            notify: self,
+            waiter: None,
+        }
+        .await
+    }
+
+    /// 唤醒一个正在等待的任务。没有任务在等待时存储一个许可。
+    pub fn notify_one(&self) {
+        self.state.lock().unwrap().notify_one();
+    }
+
+    /// 唤醒所有正在等待的任务。不存储许可。
+    pub fn notify_waiters(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        let waiters = std::mem::take(&mut state.waiters);
+        for (id, waker) in waiters {
+            state.notified.push((id, false));
+            waker.wake();
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Notify {
+        Notify::new()
+    }
+}
+
+/// 协作式取消的令牌，对应`tokio_util::sync::CancellationToken`。
+///
+/// 任务通过`is_cancelled`检查，或者等待`cancelled`，在令牌被取消时自己结束。
+/// `child_token`创建的子令牌在父令牌被取消时也被取消，取消子令牌不影响父令牌，
+/// 所以一次`cancel`可以扇出到一整棵子任务的树，实现优雅的关闭。克隆的令牌和原来的是同一个令牌。
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
+
+struct TokenInner {
+    cancelled: AtomicBool,
+    // 唤醒正在等待`cancelled`的任务。
+    notify: Notify,
+    // 子令牌。只持有弱引用，被丢弃的子令牌不会一直留在父令牌中。
+    // 检查和推入在同一次持有锁时完成，这样和`cancel`同时创建的子令牌不会错过取消。
+    children: Mutex<Vec<Weak<TokenInner>>>,
+}
+
+impl CancellationToken {
+    /// 创建一个没有被取消的令牌。
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            inner: Arc::new(TokenInner {
+                cancelled: AtomicBool::new(false),
+                notify: Notify::new(),
+                children: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// 创建一个子令牌。这个令牌已经被取消时，子令牌一开始就是被取消的。
+    pub fn child_token(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+
+        let mut children = self.inner.children.lock().unwrap();
+        if self.is_cancelled() {
+            child.inner.cancelled.store(true, Ordering::SeqCst);
+        } else {
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Arc::downgrade(&child.inner));
+        }
+
+        child
+    }
+
+    /// 取消这个令牌和它所有的子令牌，唤醒所有正在等待`cancelled`的任务。多次调用是无害的。
+    pub fn cancel(&self) {
+        TokenInner::cancel(&self.inner);
+    }
+
+    /// 令牌是否已经被取消。
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// 等待令牌被取消。已经被取消时立即完成。
+    pub async fn cancelled(&self) {
+        let mut notified = Box::pin(self.inner.notify.notified());
+
+        // 先开始等待通知再检查标志：`cancel`先设置标志再唤醒等待者，
+        // 所以在两者之间被取消时，这里要么看到标志，要么被唤醒。
+        poll_fn(|cx| {
+            if notified.as_mut().poll(cx).is_ready() || self.is_cancelled() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl TokenInner {
+    fn cancel(inner: &TokenInner) {
+        if inner.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        inner.notify.notify_waiters();
+
+        let children = std::mem::take(&mut *inner.children.lock().unwrap());
+        for child in children {
+            if let Some(child) = child.upgrade() {
+                TokenInner::cancel(&child);
+            }
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("is_cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+// 驱动给定的未来，捕获轮询它时发生的panic。
+//
+// 任务的未来panic时，展开不能穿过`Task::poll`：那会带走执行器线程，队列中的其他任务再也不会被轮询。
+// 包裹用户未来的适配器通过这里把panic变成一个结果，交给`JoinHandle`。
+// panic之后未来不会再被轮询，所以不需要它是`UnwindSafe`的。
+async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, Box<dyn Any + Send>> {
+    struct CatchUnwind<F> {
+        future: Pin<Box<F>>,
+    }
+
+    impl<F: Future> Future for CatchUnwind<F> {
+        type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let future = self.future.as_mut();
+
+            match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+                Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+                Ok(Poll::Pending) => Poll::Pending,
+                Err(payload) => Poll::Ready(Err(payload)),
+            }
+        }
+    }
+
+    CatchUnwind {
+        future: Box::pin(future),
+    }
+    .await
+}
+
+// 唤醒一个任务，并确保它排在调度通道的末尾。
+//
+// 在工作线程上发生的普通唤醒会进入本地队列，而本地队列总是先于调度通道被查看，
+// 所以一个不断唤醒自己的任务会一直在本地队列中打转，调度通道中的任务永远轮不到。
+// 让出执行器的唤醒通过这里绕过本地队列。
+fn defer(waker: &Waker) {
+    DEFER.with(|defer| defer.set(true));
+    waker.wake_by_ref();
+    DEFER.with(|defer| defer.set(false));
+}
+
+// 在当前运行时的阻塞线程池中执行一个闭包，相当于`tokio::task::spawn_blocking`。
+//
+// 执行器线程同时负责轮询所有的任务，在任务中直接执行阻塞的代码（文件IO、繁重的计算、同步的库）
+// 会冻结整个执行器。闭包被交给一个专门的线程池执行，等待返回的`JoinHandle`则只会挂起当前任务。
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    CURRENT.with(|cell| {
+        let borrow = cell.borrow();
+        let handle = borrow.as_ref().unwrap();
+        handle.spawn_blocking(f)
+    })
+}
+
+// 交给阻塞线程池执行的闭包。
+type BlockingJob = Box<dyn FnOnce() + Send>;
+
+// 执行`spawn_blocking`闭包的线程池。
+//
+// 线程在第一次需要时才被创建。执行完一个闭包的线程不会立即退出，而是等待下一个闭包，
+// 所以只有在所有线程都忙碌时才会创建新的线程。线程数达到上限后，新的闭包按顺序排队。
+// 空闲超过`keep_alive`的线程会退出，使线程池在负载下降后收缩回去。
+struct BlockingPool {
+    state: Mutex<BlockingState>,
+    // 空闲的线程在这里等待新的闭包或者关闭。
+    condvar: Condvar,
+    max_threads: usize,
+    keep_alive: Duration,
+}
+
+struct BlockingState {
+    // 还没有线程开始执行的闭包。
+    queue: VecDeque<BlockingJob>,
+    // 正在`condvar`上等待的线程数，其中`notified`个已经被分配了一个闭包、即将醒来。
+    //
+    // 分配时就从`idle`中扣除被通知的线程，这样两个紧挨着的`spawn`不会把同一个空闲线程算两次，
+    // 否则第二个闭包可能要等到某个忙碌的线程结束才能开始执行。
+    idle: usize,
+    notified: usize,
+    // 当前存在的线程数，包括忙碌的和空闲的。
+    threads: usize,
+    shutdown: bool,
+}
+
+impl BlockingPool {
+    fn new(max_threads: usize, keep_alive: Duration) -> BlockingPool {
+        BlockingPool {
+            state: Mutex::new(BlockingState {
+                queue: VecDeque::new(),
+                idle: 0,
+                notified: 0,
+                threads: 0,
+                shutdown: false,
+            }),
+            condvar: Condvar::new(),
+            max_threads,
+            keep_alive,
+        }
+    }
+
+    // 提交一个闭包。有空闲线程时叫醒其中一个；否则在没有达到上限时创建一个新线程，
+    // 达到上限时闭包留在队列中，由第一个忙完的线程取走。
+    // 新线程在CURRENT中持有`handle`，使闭包中也可以调用`spawn`。
+    fn spawn(&self, job: BlockingJob, handle: &Handle) {
+        let mut state = self.state.lock().unwrap();
+
+        // 运行时已经关闭，或者`shutdown_timeout`已经开始，闭包被丢弃。
+        if state.shutdown || handle.is_closed() {
+            return;
+        }
+
+        state.queue.push_back(job);
+
+        if state.idle > 0 {
+            state.idle -= 1;
+            state.notified += 1;
+            self.condvar.notify_one();
+            return;
+        }
+
+        if state.threads == self.max_threads {
+            return;
+        }
+
+        state.threads += 1;
+
+        let handle = handle.clone();
+        handle
+            .shared
+            .thread_builder()
+            .spawn(move || {
+                let _enter = handle.enter();
+                let _hooks = ThreadHooks::enter(&handle.shared);
+                handle.shared.blocking.run();
+            })
+            .expect("failed to spawn a blocking thread");
+    }
+
+    // 已经提交但还没有返回的闭包数：正在执行的和还在排队的。
+    // 被分配了闭包但还没有醒来的线程不计入忙碌的线程，它要执行的闭包还在队列中。
+    fn pending(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.threads - state.idle - state.notified + state.queue.len()
+    }
+
+    // 关闭线程池。空闲的线程会退出，忙碌的线程在执行完当前的闭包后退出，还没开始的闭包被丢弃。
+    fn shutdown(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.shutdown = true;
+        state.queue.clear();
+        self.condvar.notify_all();
+    }
+
+    // 阻塞线程的循环。
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            // 先执行队列中的闭包。闭包运行期间不持有锁。
+            // 闭包panic时线程不能跟着退出，否则它在`threads`中的名额永远不会被释放。
+            // `spawn_blocking`包裹的闭包自己捕获panic并把它交给`JoinHandle`，这里只是最后一道保护。
+            if let Some(job) = state.queue.pop_front() {
+                drop(state);
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            if state.shutdown {
+                state.threads -= 1;
+                return;
+            }
+
+            // 没有闭包可以执行，等待被分配一个。`condvar`可能会虚假唤醒，
+            // 只有`notified`被扣除了才说明这个线程确实被分配了闭包（`spawn`已经把它从`idle`中扣除）。
+            state.idle += 1;
+            let deadline = Instant::now() + self.keep_alive;
+
+            loop {
+                let now = Instant::now();
+                let timed_out = now >= deadline;
+
+                if !timed_out {
+                    state = self.condvar.wait_timeout(state, deadline - now).unwrap().0;
+                }
+
+                if state.shutdown {
+                    state.threads -= 1;
+                    return;
+                }
+
+                if state.notified > 0 {
+                    state.notified -= 1;
+                    break;
+                }
+
+                // 空闲时间超过了`keep_alive`，线程退出。它仍被计在`idle`中，所以两个计数都要扣除。
+                if timed_out {
+                    state.idle -= 1;
+                    state.threads -= 1;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// 与`thread::sleep`异步等效。在这个函数上的等待会在给定的时间内暂停。
+//
+// mini-tokio通过一个共享的定时器驱动来实现延迟：所有`delay`调用都把自己的截止时间和唤醒者
+// 登记到同一个最小堆中，由一个后台线程在最近的截止时间之前休眠，并唤醒所有到期的条目。
+// 这样成千上万个并发的`delay`也只需要一个线程。
+//
+// `delay`是一个`叶子`的未来。有时，这被称为 "资源"。
+// 其他资源包括`套接字`和`通道`。
+// `资源`可能无法用`async/await`来实现，因为它们必须与一些操作系统的细节相结合。
+// 正因为如此，我们必须手动实现"未来"，也就是下面的`Sleep`。
+//
+// 然而，将API暴露为`async fn'是很好的。一个有用的习惯是手动定义一个未来，然后从一个`async fn`API中使用它。
+async fn delay(dur: Duration) {
+    sleep(dur).await;
+}
+
+/// 返回一个在给定的时间之后完成的`Sleep`。
+///
+/// 时间从当前运行时的时钟读取，见`Builder::clock`。
+pub fn sleep(dur: Duration) -> Sleep {
+    let clock = current_clock();
+    let when = clock.now() + dur;
+
+    Sleep {
+        when,
+        waker: None,
+        clock,
+    }
+}
+
+/// 返回一个在给定的截止时间完成的`Sleep`。截止时间已经过去时它立即完成。
+///
+/// 和`sleep`不同，截止时间是一个绝对的`Instant`：按照一个固定的时间线安排许多事件时，
+/// 每个截止时间都从同一个起点算出，不会因为前面的等待被延迟而累积漂移。
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        when: deadline,
+        waker: None,
+        clock: current_clock(),
+    }
+}
+
+/// `sleep`和`sleep_until`返回的未来，相当于`tokio::time::Sleep`。
+///
+/// 和`async fn`返回的匿名未来不同，它可以在被等待期间用`reset`移动截止时间。
+pub struct Sleep {
+    // 截止时间。
+    when: Instant,
+    // 延迟完成后通知的唤醒者。
+    // 唤醒者必须能被定时器线程和未来线程访问，所以它被`Arc<Mutex<_>'包裹起来。
+    // 定时器线程在截止时间到达时取走它，`Sleep`被提前丢弃或者被重置时也把它取走，
+    // 所以槽为空表示定时器驱动中的条目已经不需要唤醒任何人了。
+    waker: Option<Arc<Mutex<Option<Waker>>>>,
+    // 创建时所在的运行时的时钟。截止时间按照它来判断，也登记到它的定时器驱动中。
+    clock: Arc<dyn Clock>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if poll_budget(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        // 截止时间已经过了，不需要登记到定时器驱动中。
+        if self.clock.now() >= self.when {
+            return Poll::Ready(());
+        }
+
+        // 首先，如果这是第一次调用future，则把截止时间登记到定时器驱动中。
+        // 如果已经登记过，确保存储的`Waker'与当前任务的Waker相匹配。
+        if let Some(waker) = &self.waker {
+            let mut waker = waker.lock().unwrap();
+
+            // 检查存储的waker是否与当前任务的waker一致。
+            // 这是必要的，因为在调用`poll'之间，`Sleep'的未来实例可能会转移到不同的任务。
+            // 如果发生这种情况，给定的`Context'所包含的waker就会不同，我们必须更新我们存储的waker以反映这种变化。
+            match &*waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        } else {
+            let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+            self.waker = Some(waker.clone());
+
+            // 这是第一次调用`poll`，登记截止时间。截止时间到达时，定时器线程会调用唤醒器通知调用者。
+            self.clock.timer().register(self.when, waker);
+        }
+
+        // 一旦唤醒者被存储起来，截止时间被登记，就是检查延迟是否已经完成的时候了。
+        // 这是通过检查当前的瞬间完成的。
+        // 如果持续时间已经过了，那么未来就已经完成了，`Poll::Ready`将被返回。
+        if self.clock.now() >= self.when {
+            Poll::Ready(())
+        } else {
+            // 持续时间没有过去，未来没有完成，所以返回`Poll::Pending`。
+            //
+            // `Future`特质契约要求，当返回`Pending`时，未来确保一旦未来应该再次轮询，就会向给定的唤醒者发出信号。
+            // 在我们的例子中，通过在这里返回`Pending'，我们承诺一旦请求的持续时间结束，我们将调用包括在`Context'参数中的指定唤醒者。我们通过把截止时间登记到上面的定时器驱动中来确保这一点。
+            //
+            // 如果我们忘记调用唤醒器，任务将无限期地挂起。
+            Poll::Pending
+        }
+    }
+}
+
+// `Sleep`在截止时间之前被丢弃时（例如输掉了`timeout`中的竞争），取消它在定时器驱动中的登记，
+// 以免定时器线程在截止时间到达后去唤醒一个不再关心它的任务，任务本身也不会因为唤醒者而继续存活。
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(waker) = &self.waker {
+            self.clock.timer().deregister(waker);
+        }
+    }
+}
+
+impl Sleep {
+    /// 截止时间。
+    pub fn deadline(&self) -> Instant {
+        self.when
+    }
+
+    /// 截止时间是否已经过去。
+    pub fn is_elapsed(&self) -> bool {
+        self.clock.now() >= self.when
+    }
+
+    /// 把截止时间改为`deadline`。
+    ///
+    /// 旧的截止时间在定时器驱动中的登记被取消。`Sleep`已经完成时，新的截止时间还没有到达的话它重新变为未完成；
+    /// 正在被等待时，等待它的任务在新的截止时间被唤醒，而不是旧的。
+    pub fn reset(&mut self, deadline: Instant) {
+        self.when = deadline;
+
+        // 取消旧的登记并取走其中的唤醒者：定时器线程到达旧的截止时间时不会再唤醒任何人。
+        let waker = match self.waker.take() {
+            Some(waker) => self.clock.timer().deregister(&waker),
+            None => None,
+        };
+
+        // 有任务正在等待时立即用它的唤醒者登记新的截止时间：它可能不会再轮询这个未来，直到被唤醒。
+        // 旧的登记已经到期（唤醒者已被取走）时，下一次轮询会登记新的截止时间。
+        if let Some(waker) = waker {
+            let waker = Arc::new(Mutex::new(Some(waker)));
+            self.waker = Some(waker.clone());
+            self.clock.timer().register(deadline, waker);
+        }
+    }
+}
+
+// 所有`Sleep`共享的定时器驱动。
+//
+// 登记的截止时间保存在一个最小堆中。一个后台线程在`condvar`上休眠到最近的截止时间，
+// 唤醒所有到期的条目，然后重新计算下一个截止时间。登记新的条目时会通知`condvar`，
+// 因为新的截止时间可能比线程正在等待的那个更早。
+//
+// 被取消的条目（唤醒者已被取走）不会立即从堆中删除：从堆中间删除一个条目需要扫描整个堆。
+// 它们到达堆顶时被丢弃，而且被取消的条目超过一半时整个堆被清理一遍，
+// 所以大量被取消的延迟（例如输掉竞争的`timeout`）不会让堆无限增长。
+struct TimerDriver {
+    entries: Mutex<BinaryHeap<TimerEntry>>,
+    condvar: Condvar,
+    // 上次清理之后被取消的条目数。已经到期的条目也可能被计入，这只会让清理提前发生。
+    cancelled: AtomicUsize,
+}
+
+// 定时器驱动中的一个条目。
+struct TimerEntry {
+    when: Instant,
+    // 与`Sleep`共享的唤醒者，`Sleep`在被转移到其他任务时会更新它。为空时条目已经被取消。
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl TimerDriver {
+    // 返回全局的定时器驱动，第一次调用时启动定时器线程。
+    fn get() -> &'static TimerDriver {
+        static DRIVER: OnceLock<TimerDriver> = OnceLock::new();
+        static START: Once = Once::new();
+
+        let driver = DRIVER.get_or_init(TimerDriver::new);
+
+        START.call_once(|| {
+            thread::Builder::new()
+                .name("mini-tokio-timer".to_string())
+                .spawn(move || {
+                    affinity::timer_thread_started();
+                    driver.run()
+                })
+                .expect("failed to spawn the timer thread");
+        });
+
+        driver
+    }
+
+    fn new() -> TimerDriver {
+        TimerDriver {
+            entries: Mutex::new(BinaryHeap::new()),
+            condvar: Condvar::new(),
+            cancelled: AtomicUsize::new(0),
+        }
+    }
+
+    // 取消一个登记，返回其中的唤醒者。登记已经到期或者已经被取消时返回`None`。
+    fn deregister(&self, waker: &Arc<Mutex<Option<Waker>>>) -> Option<Waker> {
+        let waker = waker.lock().unwrap().take()?;
+
+        let mut entries = self.entries.lock().unwrap();
+        let cancelled = self.cancelled.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if cancelled * 2 > entries.len() {
+            entries.retain(|entry| entry.waker.lock().unwrap().is_some());
+            self.cancelled.store(0, Ordering::Relaxed);
+
+            // 最近的截止时间可能已经被删除了，让定时器线程重新计算休眠的时间。
+            self.condvar.notify_one();
+        }
+
+        Some(waker)
+    }
+
+    // 是否还有没有到期、也没有被取消的条目，见`Builder::on_deadlock`。
+    fn has_pending(&self) -> bool {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .any(|entry| entry.waker.lock().unwrap().is_some())
+    }
+
+    // 登记一个截止时间。截止时间到达时`waker`会被调用。
+    fn register(&self, when: Instant, waker: Arc<Mutex<Option<Waker>>>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .push(TimerEntry { when, waker });
+        self.condvar.notify_one();
+    }
+
+    // 取出所有在`now`之前到期的条目，以及位于堆顶的已经被取消的条目，
+    // 这样定时器线程不会为了一个已经被取消的截止时间醒来。
+    fn take_expired(entries: &mut BinaryHeap<TimerEntry>, now: Instant) -> Vec<TimerEntry> {
+        let mut expired = Vec::new();
+        while entries
+            .peek()
+            .is_some_and(|entry| entry.when <= now || entry.waker.lock().unwrap().is_none())
+        {
+            expired.push(entries.pop().unwrap());
+        }
+        expired
+    }
+
+    // 调用取出的条目的唤醒者。已经被取消的条目没有唤醒者，直接被丢弃。
+    fn fire(expired: Vec<TimerEntry>) {
+        for entry in expired {
+            let waker = entry.waker.lock().unwrap().take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    // 定时器线程的循环。
+    fn run(&self) {
+        let mut entries = self.entries.lock().unwrap();
+
+        loop {
+            let now = Instant::now();
+            let expired = TimerDriver::take_expired(&mut entries, now);
+
+            // 在释放锁之后再调用唤醒者，这样被唤醒的任务登记新的截止时间时不会与这里竞争。
+            if !expired.is_empty() {
+                drop(entries);
+                TimerDriver::fire(expired);
+                entries = self.entries.lock().unwrap();
+                continue;
+            }
+
+            // 休眠到最近的截止时间，或者在没有条目时一直休眠，直到有新的条目被登记。
+            entries = match entries.peek() {
+                Some(entry) => {
+                    let timeout = entry.when - now;
+                    self.condvar.wait_timeout(entries, timeout).unwrap().0
+                }
+                None => self.condvar.wait(entries).unwrap(),
+            };
+        }
+    }
+}
+
+// `BinaryHeap`是一个最大堆，所以把比较反过来，使截止时间最早的条目位于堆顶。
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.when.cmp(&self.when)
+    }
+}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.when == other.when
+    }
+}
+
+impl Eq for TimerEntry {}
+
+// 运行时读取当前时刻、安排定时唤醒的时钟，见`Builder::clock`。
+//
+// `Sleep`、`Interval`和`DelayQueue`都通过创建它们的运行时的时钟读取时间，而不是直接调用`Instant::now`，
+// 这样测试可以用`MockClock`代替真实的时间。
+trait Clock: Send + Sync {
+    // 当前时刻。
+    fn now(&self) -> Instant;
+
+    // 登记截止时间的定时器驱动。时钟负责在它的截止时间按照`now`到达时唤醒其中的条目。
+    fn timer(&self) -> &TimerDriver;
+}
+
+// 真实的时钟：读取`Instant::now`，截止时间由全局的定时器线程唤醒。不在运行时之中时也使用它。
+struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn timer(&self) -> &TimerDriver {
+        TimerDriver::get()
+    }
+}
+
+// 当前运行时的时钟，不在运行时之中时是`SystemClock`。
+fn current_clock() -> Arc<dyn Clock> {
+    CURRENT.with(|cell| match &*cell.borrow() {
+        Some(handle) => handle.shared.clock.clone(),
+        None => Arc::new(SystemClock),
+    })
+}
+
+/// 只有在测试调用`advance`时才前进的时钟，用来确定性地测试依赖定时器的代码。
+///
+/// ```ignore
+/// let clock = MockClock::new();
+/// let mini_tokio = MiniTokio::with_clock(clock.clone());
+/// // 一个等待`delay(Duration::from_secs(10))`的任务……
+/// clock.advance(Duration::from_secs(10));
+/// // ……立即被唤醒，而不用真的等待10秒。
+/// ```
+///
+/// 克隆的`MockClock`共享同一个时间。它有自己的定时器驱动，不需要后台线程：
+/// 截止时间到达的条目在`advance`中被唤醒。
+#[derive(Clone)]
+pub struct MockClock {
+    inner: Arc<MockClockInner>,
+}
+
+struct MockClockInner {
+    now: Mutex<Instant>,
+    timer: TimerDriver,
+}
+
+impl MockClock {
+    /// 创建一个停在当前时刻的时钟。
+    pub fn new() -> MockClock {
+        MockClock {
+            inner: Arc::new(MockClockInner {
+                now: Mutex::new(Instant::now()),
+                timer: TimerDriver::new(),
+            }),
+        }
+    }
+
+    /// 让时间前进`dur`，并唤醒所有截止时间已经到达的`Sleep`。
+    pub fn advance(&self, dur: Duration) {
+        let now = {
+            let mut now = self.inner.now.lock().unwrap();
+            *now += dur;
+            *now
+        };
+
+        // 先更新时间再取出到期的条目：在两者之间登记的`Sleep`在登记之后会读到新的时间，自己完成。
+        let expired = TimerDriver::take_expired(&mut self.inner.timer.entries.lock().unwrap(), now);
+        TimerDriver::fire(expired);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.inner.now.lock().unwrap()
+    }
+
+    fn timer(&self) -> &TimerDriver {
+        &self.inner.timer
+    }
+}
+
+// I/O驱动（reactor）。和定时器驱动一样，整个进程共用一个：一个后台线程在`mio::Poll`上等待就绪事件，
+// 然后唤醒登记在对应`Registration`中的唤醒者。
+//
+// 套接字和定时器一样是`叶子`的未来等待的资源：它们在`WouldBlock`时把唤醒者交给这里，
+// 而不是阻塞执行器线程。mio使用边沿触发，所以一次事件之后就绪状态一直保留，
+// 直到对套接字的操作再次返回`WouldBlock`时才被清除，见`Registration::poll_read_io`。
+struct Reactor {
+    // 只被I/O线程锁住，它在上面一直等待事件。
+    poll: Mutex<mio::Poll>,
+    // 登记和取消登记不需要`poll`的锁。
+    registry: mio::Registry,
+    // 所有登记的就绪状态，按`mio::Token`索引。
+    ios: Mutex<HashMap<usize, Arc<ScheduledIo>>>,
+    next_token: AtomicUsize,
+}
+
+// 一个登记的就绪状态，在`Reactor`和`Registration`之间共享。
+struct ScheduledIo {
+    state: Mutex<IoState>,
+}
+
+#[derive(Default)]
+struct IoState {
+    readable: bool,
+    writable: bool,
+    // 每收到一个事件加一。清除就绪状态时比较它，这样在操作返回`WouldBlock`之后、
+    // 清除之前到达的事件不会被一起清除掉。
+    tick: u64,
+    // 正在等待可读和可写的任务的唤醒者。
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+// 等待的方向。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+// 全局的I/O驱动。放在`Reactor::get`之外，使`Reactor::registrations`不必启动I/O线程。
+static REACTOR: OnceLock<Reactor> = OnceLock::new();
+
+impl Reactor {
+    // 返回全局的I/O驱动，第一次调用时启动I/O线程。
+    fn get() -> &'static Reactor {
+        static START: Once = Once::new();
+
+        let reactor = REACTOR.get_or_init(Reactor::new);
+
+        START.call_once(|| {
+            thread::Builder::new()
+                .name("mini-tokio-io".to_string())
+                .spawn(move || reactor.run())
+                .expect("failed to spawn the I/O thread");
+        });
+
+        reactor
+    }
+
+    // 在I/O驱动中登记的套接字数，见`Builder::on_deadlock`。不会启动I/O线程。
+    fn registrations() -> usize {
+        REACTOR
+            .get()
+            .map_or(0, |reactor| reactor.ios.lock().unwrap().len())
+    }
+
+    fn new() -> Reactor {
+        let poll = mio::Poll::new().expect("failed to create the I/O reactor");
+        let registry = poll
+            .registry()
+            .try_clone()
+            .expect("failed to create the I/O reactor");
+
+        Reactor {
+            poll: Mutex::new(poll),
+            registry,
+            ios: Mutex::new(HashMap::new()),
+            next_token: AtomicUsize::new(0),
+        }
+    }
+
+    // I/O线程的循环。
+    fn run(&self) {
+        let mut poll = self.poll.lock().unwrap();
+        let mut events = mio::Events::with_capacity(1024);
+
+        loop {
+            if let Err(err) = poll.poll(&mut events, None) {
+                // 被信号打断时重新等待。其他错误说明`Poll`本身已经不能用了。
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                panic!("the I/O reactor failed: {}", err);
+            }
+
+            for event in events.iter() {
+                let io = self.ios.lock().unwrap().get(&event.token().0).cloned();
+                // 登记已经被丢弃了。
+                let io = match io {
+                    Some(io) => io,
+                    None => continue,
+                };
+
+                // 对端关闭和错误也算作就绪：接下来的读写会立即返回结果，而不是永远等待。
+                let readable = event.is_readable() || event.is_read_closed() || event.is_error();
+                let writable = event.is_writable() || event.is_write_closed() || event.is_error();
+
+                let wakers = {
+                    let mut state = io.state.lock().unwrap();
+                    state.tick += 1;
+                    let mut wakers = Vec::new();
+                    if readable {
+                        state.readable = true;
+                        wakers.extend(state.read_waker.take());
+                    }
+                    if writable {
+                        state.writable = true;
+                        wakers.extend(state.write_waker.take());
+                    }
+                    wakers
+                };
+
+                // 和定时器一样，在释放锁之后再唤醒。
+                for waker in wakers {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+/// 一个在I/O驱动中登记的I/O资源，叶子I/O未来通过它等待可读或者可写。
+///
+/// ```ignore
+/// let registration = Registration::new(&mut socket, mio::Interest::READABLE)?;
+/// let n = poll_fn(|cx| registration.poll_read_io(cx, || socket.read(&mut buf))).await?;
+/// ```
+///
+/// 登记不拥有资源本身：资源被关闭时操作系统自动取消它在`mio::Poll`中的登记，
+/// 需要在关闭之前取消时使用`deregister`。登记被丢弃之后，资源上的事件不再唤醒任何任务。
+pub struct Registration {
+    token: usize,
+    io: Arc<ScheduledIo>,
+}
+
+impl Registration {
+    /// 在I/O驱动中登记给定的资源，关心`interest`中的事件。
+    ///
+    /// 资源必须处于非阻塞模式。登记失败时返回`mio::Registry::register`的错误。
+    pub fn new<S>(source: &mut S, interest: mio::Interest) -> io::Result<Registration>
+    where
+        S: mio::event::Source + ?Sized,
+    {
+        let reactor = Reactor::get();
+        let token = reactor.next_token.fetch_add(1, Ordering::Relaxed);
+        let io = Arc::new(ScheduledIo {
+            state: Mutex::new(IoState::default()),
+        });
+
+        // 先放进表中再登记：登记之后的第一个事件可能马上就会到达。
+        reactor.ios.lock().unwrap().insert(token, io.clone());
+
+        if let Err(err) = reactor
+            .registry
+            .register(source, mio::Token(token), interest)
+        {
+            reactor.ios.lock().unwrap().remove(&token);
+            return Err(err);
+        }
+
+        Ok(Registration { token, io })
+    }
+
+    /// 取消资源在`mio::Poll`中的登记。给定的资源必须是创建这个登记时的那一个。
+    pub fn deregister<S>(&self, source: &mut S) -> io::Result<()>
+    where
+        S: mio::event::Source + ?Sized,
+    {
+        Reactor::get().registry.deregister(source)
+    }
+
+    /// 资源已经可读时返回`Poll::Ready`，否则存储当前任务的唤醒者，在资源变得可读时唤醒它。
+    ///
+    /// 就绪可能是虚假的：读取仍然可能返回`WouldBlock`。需要在那之后继续等待时使用`poll_read_io`。
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.poll_ready(Direction::Read, cx).map(|_| ())
+    }
+
+    /// 和`poll_read_ready`一样，等待的是可写。
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.poll_ready(Direction::Write, cx).map(|_| ())
+    }
+
+    /// 等待资源变得可读。
+    pub async fn readable(&self) {
+        poll_fn(|cx| self.poll_read_ready(cx)).await
+    }
+
+    /// 等待资源变得可写。
+    pub async fn writable(&self) {
+        poll_fn(|cx| self.poll_write_ready(cx)).await
+    }
+
+    /// 在资源可读时执行一次非阻塞的读操作`f`。
+    ///
+    /// `f`返回`WouldBlock`时清除可读的就绪状态并继续等待，返回其他结果时返回那个结果。
+    pub fn poll_read_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        f: impl FnMut() -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        self.poll_io(Direction::Read, cx, f)
+    }
+
+    /// 和`poll_read_io`一样，在资源可写时执行一次非阻塞的写操作`f`。
+    pub fn poll_write_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        f: impl FnMut() -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        self.poll_io(Direction::Write, cx, f)
+    }
+
+    fn poll_io<R>(
+        &self,
+        direction: Direction,
+        cx: &mut Context<'_>,
+        mut f: impl FnMut() -> io::Result<R>,
+    ) -> Poll<io::Result<R>> {
+        loop {
+            let tick = match self.poll_ready(direction, cx) {
+                Poll::Ready(tick) => tick,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match f() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    self.clear_ready(direction, tick);
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+
+    // 就绪时返回读到的事件计数，用来在之后清除就绪状态。
+    fn poll_ready(&self, direction: Direction, cx: &mut Context<'_>) -> Poll<u64> {
+        if poll_budget(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let mut state = self.io.state.lock().unwrap();
+        let ready = match direction {
+            Direction::Read => state.readable,
+            Direction::Write => state.writable,
+        };
+
+        if ready {
+            return Poll::Ready(state.tick);
+        }
+
+        // 和`Sleep`一样，登记可能在两次轮询之间被转移到不同的任务，所以检查唤醒者是否一致。
+        let slot = match direction {
+            Direction::Read => &mut state.read_waker,
+            Direction::Write => &mut state.write_waker,
+        };
+        match slot {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *slot = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+
+    // 操作返回了`WouldBlock`，清除就绪状态。从读到就绪之后又有新的事件到达时不清除。
+    fn clear_ready(&self, direction: Direction, tick: u64) {
+        let mut state = self.io.state.lock().unwrap();
+
+        if state.tick == tick {
+            match direction {
+                Direction::Read => state.readable = false,
+                Direction::Write => state.writable = false,
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        Reactor::get().ios.lock().unwrap().remove(&self.token);
+    }
+}
+
+impl fmt::Debug for Registration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("token", &self.token)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 异步的字节流，对应`tokio::io`。
+///
+/// `AsyncRead`和`AsyncWrite`是`std::io::Read`和`std::io::Write`的异步版本：数据还没有就绪时，
+/// 它们返回`Poll::Pending`并在之后唤醒任务，而不是阻塞线程。这样`copy`之类的工具可以作用于任何字节流，
+/// 无论它是`net::TcpStream`还是内存中的缓冲区。
+///
+/// 这个模块同时重新导出了`std::io`中的全部内容，所以`io::Result`和`io::ErrorKind`照常可用。
+pub mod io {
+    use super::*;
+
+    pub use std::io::*;
+
+    /// 异步地读取字节。
+    pub trait AsyncRead {
+        /// 尝试读取一些数据到`buf`中，返回读到的字节数。
+        ///
+        /// 没有数据可读时返回`Poll::Pending`，并安排在数据到达时唤醒`cx`中的任务。
+        /// `Ok(0)`表示已经读到了末尾（或者`buf`为空）。
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<Result<usize>>;
+    }
+
+    /// 异步地写入字节。
+    pub trait AsyncWrite {
+        /// 尝试写入`buf`中的一些数据，返回写入的字节数。可能只写入一部分。
+        ///
+        /// 暂时不能写入时返回`Poll::Pending`，并安排在可以写入时唤醒`cx`中的任务。
+        /// `buf`不为空时返回`Ok(0)`表示不再接受任何数据。
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize>>;
+
+        /// 把缓冲的数据全部写到底层的目标。
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>>;
+
+        /// 关闭写方向：先刷新缓冲的数据，之后对端读到末尾。
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>>;
+    }
+
+    impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for &mut T {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<Result<usize>> {
+            Pin::new(&mut **self).poll_read(cx, buf)
+        }
+    }
+
+    impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for Box<T> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<Result<usize>> {
+            Pin::new(&mut **self).poll_read(cx, buf)
+        }
+    }
+
+    // 内存中的数据总是就绪的：读取消耗切片的开头。
+    impl AsyncRead for &[u8] {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<Result<usize>> {
+            Poll::Ready(Read::read(&mut *self, buf))
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for &mut T {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize>> {
+            Pin::new(&mut **self).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut **self).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut **self).poll_shutdown(cx)
+        }
+    }
+
+    impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for Box<T> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize>> {
+            Pin::new(&mut **self).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut **self).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Pin::new(&mut **self).poll_shutdown(cx)
+        }
+    }
+
+    // 写入内存的缓冲区总是立即完成，而且全部写入。
+    impl AsyncWrite for Vec<u8> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<Result<usize>> {
+            self.get_mut().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// 从`reader`中读取数据写入`writer`，直到`reader`读到末尾，返回复制的字节数。
+    ///
+    /// 每次读到的数据都被全部写入之后才读取下一块：`writer`只写入一部分时继续写入剩下的部分。
+    /// 读到末尾之后刷新`writer`，但不关闭它。`writer`不再接受数据时返回`ErrorKind::WriteZero`错误。
+    pub async fn copy<R, W>(reader: &mut R, writer: &mut W) -> Result<u64>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let mut buf = vec![0; 8 * 1024];
+        let mut copied = 0;
+
+        loop {
+            let n = poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf)).await?;
+            if n == 0 {
+                poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await?;
+                return Ok(copied);
+            }
+
+            let mut pending = &buf[..n];
+            while !pending.is_empty() {
+                let written = poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, pending)).await?;
+                if written == 0 {
+                    return Err(Error::new(
+                        ErrorKind::WriteZero,
+                        "write zero bytes into the writer",
+                    ));
+                }
+                pending = &pending[written..];
+            }
+            copied += n as u64;
+        }
+    }
+}
+
+/// 建立在I/O驱动之上的异步网络类型，对应`tokio::net`。
+///
+/// 套接字都处于非阻塞模式：操作返回`WouldBlock`时，任务通过`Registration`等待套接字就绪，
+/// 而不是阻塞执行器线程。
+pub mod net {
+    use super::*;
+
+    use io::{AsyncRead, AsyncWrite, Read, Write};
+    use std::net::{Shutdown, SocketAddr, ToSocketAddrs};
+
+    /// 一个TCP连接，对应`tokio::net::TcpStream`。
+    ///
+    /// ```ignore
+    /// let mut stream = net::TcpStream::connect("127.0.0.1:8080").await?;
+    /// stream.write_all(b"ping").await?;
+    /// let n = stream.read(&mut buf).await?;
+    /// ```
+    pub struct TcpStream {
+        io: mio::net::TcpStream,
+        registration: Registration,
+    }
+
+    impl TcpStream {
+        /// 连接到给定的地址。地址解析出多个地址时依次尝试，返回最后一个错误。
+        ///
+        /// 地址的解析是同步的（`ToSocketAddrs`），传入一个`SocketAddr`时不会阻塞。
+        /// 连接本身是异步完成的：等待期间任务让出执行器。
+        pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+            let mut last_err = None;
+
+            for addr in addr.to_socket_addrs()? {
+                match TcpStream::connect_addr(addr).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => last_err = Some(err),
+                }
+            }
+
+            Err(last_err.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "could not resolve to any address",
+                )
+            }))
+        }
+
+        async fn connect_addr(addr: SocketAddr) -> io::Result<TcpStream> {
+            let stream = TcpStream::new(mio::net::TcpStream::connect(addr)?)?;
+
+            // 非阻塞的连接在套接字变得可写时结束，之后从套接字上取得它是成功了还是失败了。
+            // 可写的就绪可能是虚假的，这时对端地址还不可用，清除就绪状态继续等待。
+            poll_fn(|cx| {
+                stream.registration.poll_write_io(cx, || {
+                    if let Some(err) = stream.io.take_error()? {
+                        return Err(err);
+                    }
+
+                    match stream.io.peer_addr() {
+                        Ok(_) => Ok(()),
+                        Err(err) if err.kind() == io::ErrorKind::NotConnected => {
+                            Err(io::ErrorKind::WouldBlock.into())
+                        }
+                        Err(err) => Err(err),
+                    }
+                })
+            })
+            .await?;
+
+            Ok(stream)
+        }
+
+        // 在I/O驱动中登记一个已经处于非阻塞模式的套接字。
+        pub(crate) fn new(mut io: mio::net::TcpStream) -> io::Result<TcpStream> {
+            let registration =
+                Registration::new(&mut io, mio::Interest::READABLE | mio::Interest::WRITABLE)?;
+
+            Ok(TcpStream { io, registration })
+        }
+
+        /// 把一个标准库的连接转换成异步的连接。连接被设置为非阻塞模式。
+        pub fn from_std(stream: std::net::TcpStream) -> io::Result<TcpStream> {
+            stream.set_nonblocking(true)?;
+            TcpStream::new(mio::net::TcpStream::from_std(stream))
+        }
+
+        /// 读取一些数据到`buf`中，返回读到的字节数。
+        ///
+        /// 可能只填满`buf`的一部分。对端关闭了连接的写方向之后返回`Ok(0)`。
+        pub async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            poll_fn(|cx| Pin::new(&mut *self).poll_read(cx, buf)).await
+        }
+
+        /// 写入`buf`中的一些数据，返回写入的字节数。
+        ///
+        /// 套接字的发送缓冲区快满时可能只写入一部分，需要全部写入时使用`write_all`。
+        pub async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            poll_fn(|cx| Pin::new(&mut *self).poll_write(cx, buf)).await
+        }
+
+        /// 写入`buf`中的全部数据。连接不再接受任何数据时返回`ErrorKind::WriteZero`错误。
+        pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf).await? {
+                    0 => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::WriteZero,
+                            "failed to write the whole buffer",
+                        ));
+                    }
+                    n => buf = &buf[n..],
+                }
+            }
+            Ok(())
+        }
+
+        /// 关闭连接的读方向、写方向或者两者。关闭写方向之后对端读到`Ok(0)`。
+        pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+            self.io.shutdown(how)
+        }
+
+        /// 连接的本地地址。
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.io.local_addr()
+        }
+
+        /// 连接的对端地址。
+        pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+            self.io.peer_addr()
+        }
+
+        /// 设置`TCP_NODELAY`。
+        pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+            self.io.set_nodelay(nodelay)
+        }
+    }
+
+    impl io::AsyncRead for TcpStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let TcpStream { io, registration } = self.get_mut();
+            registration.poll_read_io(cx, || (&*io).read(buf))
+        }
+    }
+
+    impl io::AsyncWrite for TcpStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let TcpStream { io, registration } = self.get_mut();
+            registration.poll_write_io(cx, || (&*io).write(buf))
+        }
+
+        // 写入的数据直接交给了内核，没有需要刷新的缓冲。
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(self.io.shutdown(Shutdown::Write))
+        }
+    }
+
+    // 套接字在被关闭时自动离开`mio::Poll`，这里先取消登记，使它的编号不会再收到事件。
+    impl Drop for TcpStream {
+        fn drop(&mut self) {
+            let _ = self.registration.deregister(&mut self.io);
+        }
+    }
+
+    impl fmt::Debug for TcpStream {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TcpStream")
+                .field("local_addr", &self.io.local_addr().ok())
+                .field("peer_addr", &self.io.peer_addr().ok())
+                .finish()
+        }
+    }
+
+    /// 一个监听TCP连接的套接字，对应`tokio::net::TcpListener`。
+    ///
+    /// ```ignore
+    /// let listener = net::TcpListener::bind("127.0.0.1:8080").await?;
+    /// loop {
+    ///     let (stream, peer) = listener.accept().await?;
+    ///     spawn(handle_connection(stream, peer));
+    /// }
+    /// ```
+    pub struct TcpListener {
+        io: mio::net::TcpListener,
+        registration: Registration,
+    }
+
+    impl TcpListener {
+        /// 在给定的地址上监听。地址解析出多个地址时依次尝试，返回第一个绑定成功的监听器，
+        /// 全部失败时返回最后一个错误。端口为0时由系统选择一个端口，用`local_addr`取得它。
+        pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
+            let mut last_err = None;
+
+            for addr in addr.to_socket_addrs()? {
+                match mio::net::TcpListener::bind(addr) {
+                    Ok(io) => return TcpListener::new(io),
+                    Err(err) => last_err = Some(err),
+                }
+            }
+
+            Err(last_err.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "could not resolve to any address",
+                )
+            }))
+        }
+
+        // 在I/O驱动中登记一个已经处于非阻塞模式的监听套接字。新连接到达时它变得可读。
+        fn new(mut io: mio::net::TcpListener) -> io::Result<TcpListener> {
+            let registration = Registration::new(&mut io, mio::Interest::READABLE)?;
+            Ok(TcpListener { io, registration })
+        }
+
+        /// 把一个标准库的监听器转换成异步的监听器。监听器被设置为非阻塞模式。
+        pub fn from_std(listener: std::net::TcpListener) -> io::Result<TcpListener> {
+            listener.set_nonblocking(true)?;
+            TcpListener::new(mio::net::TcpListener::from_std(listener))
+        }
+
+        /// 接受一个新连接，返回连接和对端的地址。
+        ///
+        /// 没有等待中的连接时任务让出执行器，直到监听套接字变得可读，而不是反复尝试。
+        /// 接受的连接已经处于非阻塞模式并登记在I/O驱动中。
+        pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+            let (io, addr) =
+                poll_fn(|cx| self.registration.poll_read_io(cx, || self.io.accept())).await?;
+            Ok((TcpStream::new(io)?, addr))
+        }
+
+        /// 监听器的本地地址。
+        pub fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.io.local_addr()
+        }
+    }
+
+    // 和`TcpStream`一样，先取消登记。
+    impl Drop for TcpListener {
+        fn drop(&mut self) {
+            let _ = self.registration.deregister(&mut self.io);
+        }
+    }
+
+    impl fmt::Debug for TcpListener {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TcpListener")
+                .field("local_addr", &self.io.local_addr().ok())
+                .finish()
+        }
+    }
+}
+
+/// 让两个未来赛跑，返回先完成的那个的输出。
+///
+/// 每次被唤醒时先轮询`a`再轮询`b`，所以两者同时就绪时`a`获胜。
+/// 另一个未来在`select`返回时被丢弃，例如它登记的延迟随之被取消。
+/// 两者都在同一个任务中被轮询，不会催生额外的任务。
+pub async fn select<A: Future, B: Future>(a: A, b: B) -> Either<A::Output, B::Output> {
+    // 和`Timeout`一样，两个子未来都被装箱固定。
+    struct Select<A, B> {
+        a: Pin<Box<A>>,
+        b: Pin<Box<B>>,
+    }
+
+    impl<A: Future, B: Future> Future for Select<A, B> {
+        type Output = Either<A::Output, B::Output>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if let Poll::Ready(output) = self.a.as_mut().poll(cx) {
+                return Poll::Ready(Either::Left(output));
+            }
+
+            match self.b.as_mut().poll(cx) {
+                Poll::Ready(output) => Poll::Ready(Either::Right(output)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    Select {
+        a: Box::pin(a),
+        b: Box::pin(b),
+    }
+    .await
+}
+
+/// `select`的结果，表示哪一个未来先完成。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<L, R> {
+    /// 第一个未来先完成。
+    Left(L),
+    /// 第二个未来先完成。
+    Right(R),
+}
+
+/// 同时等待两个未来，两者都完成之后返回它们的输出。
+///
+/// 每次被唤醒时两个还没有完成的未来都会被轮询，所以它们交错地取得进展：
+/// 等待两个`delay`所需的时间是两者中较长的那个，而不是两者之和。
+/// 先完成的输出被保存起来，直到另一个也完成。两者都在同一个任务中被轮询。
+pub async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+    // 和`Select`一样，两个子未来都被装箱固定。完成的子未来被丢弃，它的输出保存在旁边。
+    struct Join<A: Future, B: Future> {
+        a: Option<Pin<Box<A>>>,
+        a_output: Option<A::Output>,
+        b: Option<Pin<Box<B>>>,
+        b_output: Option<B::Output>,
+    }
+
+    // 输出只被移动，从不被固定，所以`Join`总是`Unpin`的。
+    impl<A: Future, B: Future> Unpin for Join<A, B> {}
+
+    impl<A: Future, B: Future> Future for Join<A, B> {
+        type Output = (A::Output, B::Output);
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+
+            if let Some(a) = this.a.as_mut() {
+                if let Poll::Ready(output) = a.as_mut().poll(cx) {
+                    this.a_output = Some(output);
+                    this.a = None;
+                }
+            }
+
+            if let Some(b) = this.b.as_mut() {
+                if let Poll::Ready(output) = b.as_mut().poll(cx) {
+                    this.b_output = Some(output);
+                    this.b = None;
+                }
+            }
+
+            if this.a.is_some() || this.b.is_some() {
+                return Poll::Pending;
+            }
+
+            Poll::Ready((this.a_output.take().unwrap(), this.b_output.take().unwrap()))
+        }
+    }
+
+    Join {
+        a: Some(Box::pin(a)),
+        a_output: None,
+        b: Some(Box::pin(b)),
+        b_output: None,
+    }
+    .await
+}
+
+/// 和`join`一样同时等待两个返回`Result`的未来，但任何一个返回`Err`时立即返回这个错误。
+///
+/// 两者都返回`Ok`时得到两个值。提前返回时另一个还没有完成的未来随之被丢弃，
+/// 所以一个操作失败会取消其余的操作，例如它登记的延迟。
+pub async fn try_join<A, B, T1, T2, E>(a: A, b: B) -> Result<(T1, T2), E>
+where
+    A: Future<Output = Result<T1, E>>,
+    B: Future<Output = Result<T2, E>>,
+{
+    // 和`Join`相同，只是子未来的输出在保存之前先检查是不是错误。
+    struct TryJoin<A, B, T1, T2> {
+        a: Option<Pin<Box<A>>>,
+        a_output: Option<T1>,
+        b: Option<Pin<Box<B>>>,
+        b_output: Option<T2>,
+    }
+
+    impl<A, B, T1, T2> Unpin for TryJoin<A, B, T1, T2> {}
+
+    impl<A, B, T1, T2, E> Future for TryJoin<A, B, T1, T2>
+    where
+        A: Future<Output = Result<T1, E>>,
+        B: Future<Output = Result<T2, E>>,
+    {
+        type Output = Result<(T1, T2), E>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+
+            if let Some(a) = this.a.as_mut() {
+                if let Poll::Ready(output) = a.as_mut().poll(cx) {
+                    this.a_output = Some(output?);
+                    this.a = None;
+                }
+            }
+
+            if let Some(b) = this.b.as_mut() {
+                if let Poll::Ready(output) = b.as_mut().poll(cx) {
+                    this.b_output = Some(output?);
+                    this.b = None;
+                }
+            }
+
+            if this.a.is_some() || this.b.is_some() {
+                return Poll::Pending;
+            }
+
+            Poll::Ready(Ok((
+                this.a_output.take().unwrap(),
+                this.b_output.take().unwrap(),
+            )))
+        }
+    }
+
+    TryJoin {
+        a: Some(Box::pin(a)),
+        a_output: None,
+        b: Some(Box::pin(b)),
+        b_output: None,
+    }
+    .await
+}
+
+/// 一组被同时驱动的未来，按完成的先后取得它们的输出，对应`futures::stream::FuturesUnordered`。
+///
+/// 每个未来都有自己的唤醒者：被唤醒时它只把自己的编号排进就绪队列，再唤醒正在等待`next`的任务，
+/// 所以`next`只轮询被唤醒过的未来，而不是每次都轮询整个集合。
+/// 所有的未来都在调用`next`的那个任务中被轮询，不会催生额外的任务。
+pub struct FuturesUnordered<F: Future> {
+    // 还没有完成的未来和它们的唤醒者，按编号索引。
+    futures: HashMap<u64, (Pin<Box<F>>, Waker)>,
+    next_id: u64,
+    ready: Arc<ReadyQueue>,
+}
+
+// 被唤醒的未来的编号，以及正在等待`next`的任务的唤醒者。唤醒可能来自任何线程。
+struct ReadyQueue {
+    queue: Mutex<VecDeque<u64>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+// `FuturesUnordered`中一个未来的唤醒者。
+struct ReadyWaker {
+    id: u64,
+    ready: Arc<ReadyQueue>,
+}
+
+impl ReadyWaker {
+    fn schedule(&self) {
+        self.ready.queue.lock().unwrap().push_back(self.id);
+
+        let waker = self.ready.waker.lock().unwrap().clone();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl ArcWake for ReadyWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.schedule();
+    }
+}
+
+impl<F: Future> FuturesUnordered<F> {
+    /// 创建一个空的集合。
+    pub fn new() -> FuturesUnordered<F> {
+        FuturesUnordered {
+            futures: HashMap::new(),
+            next_id: 0,
+            ready: Arc::new(ReadyQueue {
+                queue: Mutex::new(VecDeque::new()),
+                waker: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// 加入一个未来。它在下一次`next`时被第一次轮询。
+    pub fn push(&mut self, future: F) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let waker = Arc::new(ReadyWaker {
+            id,
+            ready: self.ready.clone(),
+        });
+        // 和被唤醒一样排进就绪队列。
+        waker.schedule();
+
+        self.futures
+            .insert(id, (Box::pin(future), waker::waker(waker)));
+    }
+
+    /// 还没有完成的未来的数量。
+    pub fn len(&self) -> usize {
+        self.futures.len()
+    }
+
+    /// 集合中是否没有未来。
+    pub fn is_empty(&self) -> bool {
+        self.futures.is_empty()
+    }
+
+    /// 等待下一个完成的未来，返回它的输出。集合为空时返回`None`。
+    pub async fn next(&mut self) -> Option<F::Output> {
+        struct Next<'a, F: Future> {
+            set: &'a mut FuturesUnordered<F>,
+        }
+
+        impl<F: Future> Future for Next<'_, F> {
+            type Output = Option<F::Output>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+                let set = &mut *self.set;
+
+                if set.futures.is_empty() {
+                    return Poll::Ready(None);
+                }
+
+                // 先存储唤醒者，再查看就绪队列，这样在两者之间发生的唤醒不会丢失。
+                {
+                    let mut waker = set.ready.waker.lock().unwrap();
+                    match &*waker {
+                        Some(waker) if waker.will_wake(cx.waker()) => {}
+                        _ => *waker = Some(cx.waker().clone()),
+                    }
+                }
+
+                // 和`LocalSet`一样，只轮询此刻已经在队列中的未来，轮询期间又被唤醒的留到下一次。
+                let n = set.ready.queue.lock().unwrap().len();
+
+                for _ in 0..n {
+                    let id = match set.ready.queue.lock().unwrap().pop_front() {
+                        Some(id) => id,
+                        None => break,
+                    };
+
+                    // 未来已经完成，或者同一个未来在队列中出现了不止一次。
+                    let (future, waker) = match set.futures.get_mut(&id) {
+                        Some(entry) => entry,
+                        None => continue,
+                    };
+
+                    let mut cx = Context::from_waker(waker);
+                    if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                        set.futures.remove(&id);
+                        return Poll::Ready(Some(output));
+                    }
+                }
+
+                if !set.ready.queue.lock().unwrap().is_empty() {
+                    defer(cx.waker());
+                }
+
+                Poll::Pending
+            }
+        }
+
+        Next { set: self }.await
+    }
+}
+
+impl<F: Future> Default for FuturesUnordered<F> {
+    fn default() -> FuturesUnordered<F> {
+        FuturesUnordered::new()
+    }
+}
+
+/// 给未来设置一个时间限制。
+///
+/// 给定的未来与一个`delay(dur)`赛跑：未来先完成时返回`Ok`和它的输出，
+/// 截止时间先到达时返回`Err(Elapsed)`，未来被丢弃。
+/// 两者都在同一个任务中被轮询，不会催生额外的任务。
+pub async fn timeout<F: Future>(dur: Duration, future: F) -> Result<F::Output, Elapsed> {
+    Timeout {
+        future: Box::pin(future),
+        delay: Box::pin(delay(dur)),
+    }
+    .await
+}
+
+/// 和`timeout`一样，但时间限制是一个绝对的截止时间，给定的未来与`sleep_until(deadline)`赛跑。
+///
+/// 几个操作共享同一个截止时间时，每个操作不必重新计算剩下的时间。
+/// 截止时间已经过去时，未来仍然会被轮询一次：它已经就绪的话返回`Ok`，否则立即返回`Err(Elapsed)`。
+pub async fn timeout_at<F: Future>(deadline: Instant, future: F) -> Result<F::Output, Elapsed> {
+    Timeout {
+        future: Box::pin(future),
+        delay: Box::pin(sleep_until(deadline)),
+    }
+    .await
+}
+
+// `timeout`和`timeout_at`返回的未来。和`delay`一样，手动定义一个私有的未来。两个子未来都被装箱固定，
+// 这样`Timeout`本身是`Unpin`的，不需要不安全的固定投影。
+//
+// `Timeout`在返回后被丢弃，里面的`Sleep`随之取消它在定时器驱动中的条目，
+// 所以未来先完成时，定时器线程不会在截止时间到达后再去唤醒这个任务。
+struct Timeout<F, D> {
+    future: Pin<Box<F>>,
+    delay: Pin<Box<D>>,
+}
+
+impl<F: Future, D: Future<Output = ()>> Future for Timeout<F, D> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // 先轮询未来：即使截止时间同时到达，已经完成的输出也不应该被丢弃。
+        if let Poll::Ready(output) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        match self.delay.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed(()))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// `timeout`和`timeout_at`的截止时间在未来完成之前到达时返回的错误。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+/// 创建一个以固定周期产生滴答的`Interval`。
+///
+/// 第一次`tick`立即完成，之后每隔`period`完成一次。
+///
+/// # Panics
+///
+/// `period`为零时panic。
+pub fn interval(period: Duration) -> Interval {
+    assert!(period > Duration::ZERO, "`period` must be non-zero");
+
+    Interval {
+        next: current_clock().now(),
+        period,
+        missed_tick_behavior: MissedTickBehavior::default(),
+        sleep: None,
+    }
+}
+
+/// 由`interval`创建的周期定时器。
+///
+/// 每个滴答的截止时间都是从上一个截止时间推算出来的，而不是从上一次`tick`返回的时刻，
+/// 所以即使每次处理滴答需要一些时间，滴答也不会漂移。
+///
+/// 它也是一个无穷的`Stream`，每个滴答产生一个`()`，可以和`StreamExt::take`等适配器组合。
+pub struct Interval {
+    // 下一个滴答的截止时间。
+    next: Instant,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+    // 等待下一个滴答的`Sleep`，每个滴答之后被重置到新的截止时间。第一次被轮询时才创建，
+    // 这样它使用的是轮询它的运行时的时钟。
+    sleep: Option<Sleep>,
+}
+
+/// 当消费者太慢、两次`tick`之间错过了一个或多个滴答时`Interval`的行为。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedTickBehavior {
+    /// 尽快补上所有错过的滴答，之后回到原来的节奏。默认行为。
+    #[default]
+    Burst,
+    /// 从晚到的这次滴答开始重新计算周期，之后的滴答都相应推迟。
+    Delay,
+    /// 跳过错过的滴答，下一个滴答落在原来节奏中的下一个截止时间上。
+    Skip,
+}
+
+impl Interval {
+    /// 等待下一个滴答，返回这个滴答的截止时间。
+    pub async fn tick(&mut self) -> Instant {
+        poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    /// 轮询下一个滴答，到达时返回这个滴答的截止时间。`tick`和`Stream`的实现都建立在它之上。
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        let deadline = self.next;
+
+        let sleep = match &mut self.sleep {
+            Some(sleep) => {
+                if sleep.deadline() != deadline {
+                    sleep.reset(deadline);
+                }
+                sleep
+            }
+            None => self.sleep.insert(sleep_until(deadline)),
+        };
+
+        if Pin::new(sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let now = current_clock().now();
+        self.next = if now < deadline + self.period {
+            // 没有错过下一个滴答，保持原来的节奏。
+            deadline + self.period
+        } else {
+            self.missed_tick_behavior
+                .next_deadline(deadline, now, self.period)
+        };
+
+        Poll::Ready(deadline)
+    }
+
+    /// 返回错过滴答时的行为。
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// 设置错过滴答时的行为。
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+
+    /// 返回滴答的周期。
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl fmt::Debug for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interval")
+            .field("next", &self.next)
+            .field("period", &self.period)
+            .field("missed_tick_behavior", &self.missed_tick_behavior)
+            .finish_non_exhaustive()
+    }
+}
+
+// 永远不会结束：每个滴答产生一个`Some(())`。
+impl stream::Stream for Interval {
+    type Item = ();
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+        match self.poll_tick(cx) {
+            Poll::Ready(_) => Poll::Ready(Some(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl MissedTickBehavior {
+    // 计算错过了滴答之后的下一个截止时间。`deadline`是刚刚完成的滴答原本的截止时间，
+    // `now`已经越过了它之后的那一个截止时间。
+    fn next_deadline(self, deadline: Instant, now: Instant, period: Duration) -> Instant {
+        match self {
+            MissedTickBehavior::Burst => deadline + period,
+            MissedTickBehavior::Delay => now + period,
+            MissedTickBehavior::Skip => {
+                let missed = (now - deadline).as_nanos() / period.as_nanos();
+                deadline + period * (missed + 1).min(u32::MAX as u128) as u32
+            }
+        }
+    }
+}
+
+/// 按照时间排列的队列，相当于`tokio_util::time::DelayQueue`。
+///
+/// `insert`放入一个元素和它的延迟，`poll_expired`按照截止时间的顺序取出到期的元素。
+/// 适用于让缓存条目过期，或者安排重试。元素保存在一个按下标索引的slab中，`insert`返回的`Key`
+/// 可以用来在到期之前`remove`它。所有元素共用一个`Sleep`，它总是等待最早的截止时间，
+/// 所以定时器驱动中每个队列同时只有一个有效的条目。
+pub struct DelayQueue<T> {
+    // 元素的slab。被移除的元素留下的空位记在`free`中，之后插入的元素复用它们。
+    slots: Vec<DelaySlot<T>>,
+    free: Vec<usize>,
+    // 按截止时间排列的`(截止时间, 下标, 代数)`。被移除的元素的条目留在堆中，
+    // 到达堆顶时因为代数不再匹配而被丢弃。
+    expirations: BinaryHeap<std::cmp::Reverse<(Instant, usize, u64)>>,
+    // 等待最早的截止时间。
+    sleep: Option<Sleep>,
+    len: usize,
+}
+
+// slab中的一个位置。每次被复用时代数加一，旧的`Key`和堆中旧的条目因此不会指向新的元素。
+struct DelaySlot<T> {
+    generation: u64,
+    item: Option<T>,
+}
+
+/// `DelayQueue::insert`返回的键，用来在元素到期之前移除它。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    index: usize,
+    generation: u64,
+}
+
+impl<T> DelayQueue<T> {
+    /// 创建一个空的队列。
+    pub fn new() -> DelayQueue<T> {
+        DelayQueue {
+            slots: Vec::new(),
+            free: Vec::new(),
+            expirations: BinaryHeap::new(),
+            sleep: None,
+            len: 0,
+        }
+    }
+
+    /// 放入一个在`dur`之后到期的元素。
+    pub fn insert(&mut self, item: T, dur: Duration) -> Key {
+        let when = current_clock().now() + dur;
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(DelaySlot {
+                    generation: 0,
+                    item: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        slot.generation += 1;
+        slot.item = Some(item);
+
+        self.expirations
+            .push(std::cmp::Reverse((when, index, slot.generation)));
+        self.len += 1;
+
+        Key {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    /// 在元素到期之前移除它。元素已经被取出或者已经被移除时返回`None`。
+    pub fn remove(&mut self, key: Key) -> Option<T> {
+        let slot = self.slots.get_mut(key.index)?;
+        if slot.generation != key.generation {
+            return None;
+        }
+
+        let item = slot.item.take()?;
+        self.free.push(key.index);
+        self.len -= 1;
+        Some(item)
+    }
+
+    /// 等待下一个元素到期并取出它。队列为空时返回`None`。
+    ///
+    /// 等待期间插入的元素如果比正在等待的那个更早到期，只有在下一次调用时才会被考虑：
+    /// 返回的未来借用了队列，等待期间不能插入元素。
+    pub async fn poll_expired(&mut self) -> Option<T> {
+        // 和`Timeout`一样，手动定义一个私有的未来。
+        struct PollExpired<'a, T> {
+            queue: &'a mut DelayQueue<T>,
+        }
+
+        impl<T> Future for PollExpired<'_, T> {
+            type Output = Option<T>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+                let queue = &mut *self.queue;
+
+                loop {
+                    let std::cmp::Reverse((when, index, generation)) =
+                        match queue.expirations.peek() {
+                            Some(&entry) => entry,
+                            None => {
+                                queue.sleep = None;
+                                return Poll::Ready(None);
+                            }
+                        };
+
+                    // 元素已经被移除，它在堆中的条目失效了。
+                    if queue.slots[index].generation != generation
+                        || queue.slots[index].item.is_none()
+                    {
+                        queue.expirations.pop();
+                        continue;
+                    }
+
+                    if current_clock().now() >= when {
+                        queue.expirations.pop();
+                        queue.free.push(index);
+                        queue.len -= 1;
+                        return Poll::Ready(queue.slots[index].item.take());
+                    }
+
+                    // 最早的截止时间还没有到达。共用的`Sleep`被重置到这个截止时间，旧的登记随之取消。
+                    let sleep = match &mut queue.sleep {
+                        Some(sleep) => {
+                            if sleep.deadline() != when {
+                                sleep.reset(when);
+                            }
+                            sleep
+                        }
+                        None => queue.sleep.insert(sleep_until(when)),
+                    };
+
+                    if Pin::new(sleep).poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                }
+            }
+        }
+
+        PollExpired { queue: self }.await
+    }
+
+    /// 队列中还没有被取出的元素数。
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 队列是否为空。
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> DelayQueue<T> {
+        DelayQueue::new()
+    }
+}
+
+// 用于跟踪当前的mini-tokio实例，以便`spawn'函数能够安排催生的任务。
+// 只通过`Handle::enter`修改，这样离开运行时的时候，之前的值总会被恢复。
+thread_local! {
+    static CURRENT: RefCell<Option<Handle>> = const { RefCell::new(None) };
+}
+
+/// `Handle::enter`返回的守卫。被丢弃时恢复进入之前的运行时上下文。
+///
+/// 守卫修改的是线程本地变量，所以它不能被发送到其他线程，必须在进入它的线程上被丢弃。
+struct EnterGuard {
+    // 进入之前的CURRENT。为`None`时进入的是同一个运行时，丢弃时不需要恢复任何东西。
+    prev: Option<Option<Handle>>,
+    _not_send: PhantomData<Rc<()>>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        if let Some(prev) = self.prev.take() {
+            CURRENT.with(|cell| {
+                *cell.borrow_mut() = prev;
+            });
+        }
+    }
+}
+
+// 当前线程作为工作线程时的上下文。不在工作线程上时为`None`。
+thread_local! {
+    static WORKER: RefCell<Option<Rc<WorkerContext>>> = const { RefCell::new(None) };
+}
+
+// 当前线程正在`MiniTokio::block_on`中轮询任务。
+thread_local! {
+    static BLOCK_ON: Cell<bool> = const { Cell::new(false) };
+}
+
+// 在`defer`调用唤醒者期间被设置，使唤醒路径把任务送进调度通道而不是本地队列。
+thread_local! {
+    static DEFER: Cell<bool> = const { Cell::new(false) };
+}
+
+// 当前这次轮询还能完成的资源操作数，见`poll_budget`。为`None`时不在任务中，没有限制。
+thread_local! {
+    static BUDGET: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// 任务一次轮询中最多完成的资源操作数。
+const BUDGET_PER_POLL: usize = 128;
+
+// 在一个新的预算下执行`f`，用在轮询任务和根未来的地方。返回时恢复之前的预算。
+fn with_budget<R>(f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<usize>);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            BUDGET.with(|budget| budget.set(self.0));
+        }
+    }
+
+    let _reset = Reset(BUDGET.with(|budget| budget.replace(Some(BUDGET_PER_POLL))));
+    f()
+}
+
+// 资源在完成一次操作之前调用，相当于tokio的协作式调度（coop）。
+//
+// 一个总是就绪的资源（比如一个一直有数据的通道）会让等待它的任务永远不返回`Poll::Pending`，
+// 同一个线程上的其他任务因此永远轮不到。每次轮询任务时都有一份预算，资源每完成一次操作消耗一点；
+// 预算用完时这里让出执行器并返回`Poll::Pending`，资源应该原样返回它，而不是完成操作。
+// 新的资源只需要在`poll`的开头调用它。
+fn poll_budget(cx: &mut Context<'_>) -> Poll<()> {
+    let exhausted = BUDGET.with(|budget| match budget.get() {
+        Some(0) => true,
+        Some(n) => {
+            budget.set(Some(n - 1));
+            false
+        }
+        None => false,
+    });
+
+    if exhausted {
+        defer(cx.waker());
+        return Poll::Pending;
+    }
+
+    Poll::Ready(())
+}
+
+// 任务。包含未来以及未来被唤醒后安排的必要数据。
+struct Task {
+    // 未来被一个 "Mutex "包裹着，使 "任务 "结构 "同步"。
+    // 只有一个线程试图使用`future`。
+    // Tokio运行时通过使用 "不安全 "代码来避免mutex。盒子也被避免了。
+    //
+    // 这里的未来总是`Output = ()`：用户未来的输出类型在`Task::new`中被擦除，
+    // 输出本身通过包裹它的适配器写入`JoinHandle`的共享槽中。
+    // 这样`Task`不需要泛型参数，所有任务都能通过同一个通道调度。
+    //
+    // 未来完成或者任务被取消后，这里变成`None`：未来被立即丢弃，之后迟到的唤醒不会再轮询它。
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+
+    // 由`AbortHandle::abort`设置。被设置之后任务不会再被轮询。
+    aborted: AtomicBool,
+
+    // 任务的调度状态，取值是下面`impl Task`中的常量。
+    // 它保证一个任务同时最多只在一个队列中，并且同时只有一个线程在轮询它。
+    state: AtomicUsize,
+
+    // 任务在被轮询期间通过`defer`被唤醒（也就是它在让出执行器）。
+    // 轮询结束后重新排队时，它应该进入共享的调度通道而不是本地队列。
+    deferred: AtomicBool,
+
+    // 任务的编号，也是它在`Shared::tasks`中的键。和`JoinHandle::id`相同。
+    id: task::Id,
+
+    // 任务的优先级，决定它被唤醒时进入哪个队列。
+    priority: Priority,
+
+    // 任务固定在哪个工作线程上，见`Handle::spawn_pinned`。没有被固定时是`Task::NOT_PINNED`。
+    pinned: AtomicUsize,
+
+    // `task::Builder::name`给任务起的名字。和`JoinState`共享同一个字符串，所以没有名字的任务只多一个`None`。
+    name: Option<Arc<str>>,
+
+    // 任务被催生的时刻和未来被轮询的次数，见`Handle::dump`。
+    spawned_at: Instant,
+    polls: AtomicU64,
+
+    // 当一个任务被通知时，它通过这个句柄被排入调度通道。
+    // 执行者会弹出被通知的任务并执行它们。
+    executor: Handle,
+}
+
+impl Task {
+    // 空闲：既不在队列中，也没有被轮询。唤醒把它推入队列。
+    const IDLE: usize = 0;
+    // 在某个队列中等待被轮询。这时的唤醒什么也不用做。
+    const SCHEDULED: usize = 1;
+    // 正在被某个线程轮询。
+    const RUNNING: usize = 2;
+    // 正在被轮询，并且在轮询期间又被唤醒了。轮询结束后由轮询它的线程把它重新排队一次。
+    const NOTIFIED: usize = 3;
+    // 未来已经完成或者被取消，已经被丢弃。之后的唤醒都被忽略。
+    const COMPLETE: usize = 4;
+
+    // `pinned`的值：任务没有被固定在任何工作线程上。
+    const NOT_PINNED: usize = usize::MAX;
+
+    // Spawns a new taks with the given future.
+
+    // 初始化一个新的包含给定未来的任务束。调用者负责把它推送给`handle`的调度通道，通道的接收方将获得该任务并执行它。
+    //
+    // 执行器只认识`Output = ()`的未来，所以给定的未来被包裹在一个适配器中：
+    // 它完成时把输出写入与`JoinHandle`共享的槽中，并唤醒正在等待句柄的任务。
+    //
+    // 存活的任务数已经达到`Builder::max_tasks`时返回`SpawnError::AtCapacity`。
+    fn new<F, T>(
+        future: F,
+        handle: &Handle,
+        priority: Priority,
+        name: Option<Arc<str>>,
+    ) -> Result<(Arc<Task>, JoinHandle<T>), SpawnError>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let shared = &handle.shared;
+
+        // 先占用一个存活任务的名额，见`Builder::max_tasks`。任务的未来被丢弃时归还它。
+        shared
+            .tasks_alive
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |alive| {
+                match shared.max_tasks {
+                    Some(max) if alive >= max => None,
+                    _ => Some(alive + 1),
+                }
+            })
+            .map_err(|_| SpawnError::AtCapacity)?;
+
+        let (guard, mut join) = JoinGuard::new(Some(handle.clone()));
+        join.state.lock().unwrap().name = name.clone();
+        let future = async move {
+            match catch_unwind(future).await {
+                Ok(output) => guard.complete(output),
+                Err(payload) => guard.panic(payload),
+            }
+        };
+
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            aborted: AtomicBool::new(false),
+            // 新任务马上就会被推入调度通道。
+            state: AtomicUsize::new(Task::SCHEDULED),
+            deferred: AtomicBool::new(false),
+            id: join.id,
+            priority,
+            pinned: AtomicUsize::new(Task::NOT_PINNED),
+            name,
+            spawned_at: Instant::now(),
+            polls: AtomicU64::new(0),
+            executor: handle.clone(),
+        });
+        join.task = Arc::downgrade(&task);
+
+        shared
+            .tasks
+            .lock()
+            .unwrap()
+            .insert(task.id, Arc::downgrade(&task));
+
+        Ok((task, join))
+    }
+
+    // 执行一个计划任务。这将创建必要的`task::Context`，包含任务的waker。
+    // 这个waker将任务推送到mini-redis计划通道上。然后用waker轮询未来。
+    fn poll(self: Arc<Self>) {
+        // Get a waker referencing the task.
+        let waker = waker::waker(self.clone());
+        // Initialize the task context with the waker.
+        let mut cx = Context::from_waker(&waker);
+
+        // 任务从队列中被取出时处于`SCHEDULED`状态。它同时最多只在一个队列中，
+        // 所以此刻没有其他线程在轮询它，下面的锁不会发生竞争。
+        // 上一次被唤醒时留下的让出标记与这次轮询无关，先清除它。
+        self.deferred.store(false, Ordering::SeqCst);
+        self.state.store(Task::RUNNING, Ordering::SeqCst);
+
+        let mut slot = self.future.lock().unwrap();
+        let _id = task::IdGuard::enter(self.id);
+
+        let done = match slot.as_mut() {
+            // 任务已经完成或者已经被取消。
+            None => true,
+            // 任务被取消了：丢弃未来而不轮询它。包裹未来的适配器在被丢弃时以`JoinError::Cancelled`完成`JoinHandle`。
+            Some(_) if self.aborted.load(Ordering::SeqCst) => true,
+            // Poll the future
+            // 轮询期间任务被取消（或者运行时被丢弃）时，未来在这次轮询之后立即被丢弃：
+            // 关闭之后任务不会再被调度，等到下一次轮询的话未来永远不会被丢弃。
+            Some(future) => {
+                let shared = &self.executor.shared;
+                shared.total_polls.fetch_add(1, Ordering::Relaxed);
+                self.polls.fetch_add(1, Ordering::Relaxed);
+
+                // 检测被关闭时不读取时间。
+                let start = shared.slow_poll_threshold.map(|_| Instant::now());
+                let ready = with_budget(|| future.as_mut().poll(&mut cx)).is_ready();
+                if let (Some(start), Some(threshold)) = (start, shared.slow_poll_threshold) {
+                    let elapsed = start.elapsed();
+                    if elapsed > threshold {
+                        let meta = TaskMeta {
+                            id: self.id,
+                            name: self.name.as_deref(),
+                        };
+                        shared.slow_poll(&meta, elapsed);
+                    }
+                }
+                if ready {
+                    shared.tasks_completed.fetch_add(1, Ordering::Relaxed);
+                }
+
+                ready || self.aborted.load(Ordering::SeqCst)
+            }
+        };
+
+        if done {
+            // 先标记为完成再丢弃未来，丢弃未来时触发的唤醒会被忽略。
+            self.state.store(Task::COMPLETE, Ordering::SeqCst);
+            self.drop_future(&mut slot);
+            return;
+        }
+
+        drop(slot);
+
+        // 轮询期间发生的唤醒只把状态改成了`NOTIFIED`，没有把任务排进队列。
+        // 不能丢弃这次唤醒（它可能发生在未来已经检查过资源之后），所以由这里把任务重新排队一次。
+        if self
+            .state
+            .compare_exchange(
+                Task::RUNNING,
+                Task::IDLE,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_err()
+        {
+            self.state.store(Task::SCHEDULED, Ordering::SeqCst);
+
+            if self.deferred.swap(false, Ordering::SeqCst) {
+                self.executor.push_woken(self.clone());
+            } else {
+                self.executor.schedule(self.clone());
+            }
+        }
+    }
+}
+
+impl Task {
+    // 丢弃任务的未来，任务不再计入`Metrics::tasks_alive`。
+    fn drop_future(&self, slot: &mut Option<Pin<Box<dyn Future<Output = ()> + Send>>>) {
+        if slot.take().is_some() {
+            self.executor
+                .shared
+                .tasks_alive
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    // 运行时被丢弃时关闭任务：标记它被取消，并丢弃它的未来。
+    //
+    // 任务此刻正在被另一个线程（或者就是当前线程）轮询时不等待它：
+    // 轮询它的线程在那次轮询结束后看到取消标记，由它丢弃未来。
+    fn close(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+
+        if let Ok(mut slot) = self.future.try_lock() {
+            self.state.store(Task::COMPLETE, Ordering::SeqCst);
+            self.drop_future(&mut slot);
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        let shared = &self.executor.shared;
+        shared.tasks.lock().unwrap().remove(&self.id);
+
+        // 任务在完成之前被释放了，例如运行时关闭时它还在队列中。
+        if let Ok(Some(_)) = self.future.get_mut() {
+            shared.tasks_alive.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
 
 // 标准库提供了低级别的、不安全的API来定义wakers。
-// 我们不用写不安全的代码，而是使用由`futures`板块提供的助手来定义一个能够安排我们的`Task`结构的waker。
+// 不安全的部分集中在`waker`模块中，这里只需要说明唤醒一个任务意味着什么。
 impl ArcWake for Task {
     fn wake_by_ref(arc_self: &Arc<Self>) {
-        // 安排任务的执行。执行者从通道接收并轮询任务。
-        let _ = arc_self.executor.send(arc_self.clone());
+        // 在交换状态之前记录下让出执行器的唤醒，否则轮询它的线程可能在看到`NOTIFIED`时还看不到这个标记。
+        if DEFER.with(|defer| defer.get()) {
+            arc_self.deferred.store(true, Ordering::SeqCst);
+        }
+
+        let mut state = arc_self.state.load(Ordering::SeqCst);
+
+        loop {
+            // 只有空闲的任务需要被排进队列：已经在队列中的任务不会被重复排队，
+            // 正在被轮询的任务由轮询它的线程在轮询结束后重新排队。
+            let next = match state {
+                Task::IDLE => Task::SCHEDULED,
+                Task::RUNNING => Task::NOTIFIED,
+                Task::SCHEDULED | Task::NOTIFIED => {
+                    let shared = &arc_self.executor.shared;
+                    shared.wakeups_deduplicated.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                _ => return,
+            };
+
+            match arc_self
+                .state
+                .compare_exchange(state, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) if next == Task::SCHEDULED => break,
+                Ok(_) => return,
+                Err(actual) => state = actual,
+            }
+        }
+
+        // 安排任务的执行。执行者从通道接收并轮询任务。
+        // 运行时关闭之后唤醒会被忽略，所以定时器线程在关闭后调用唤醒者是安全的。
+        arc_self.executor.schedule(arc_self.clone());
+    }
+}
+
+/// `spawn`返回的句柄，相当于`tokio::task::JoinHandle`。
+///
+/// 句柄本身是一个未来，在任务完成时解析为`Ok(输出)`；如果任务在完成之前被取消，
+/// 或者它的未来在完成之前被丢弃（例如运行时关闭时任务还在队列中），则解析为`Err(JoinError::Cancelled)`。
+/// 任务的未来panic时解析为`Err(JoinError::Panic)`，执行器线程和其他任务不受影响。
+/// 丢弃句柄会使任务脱离（detach）而不是取消它：任务会继续运行，只是它的输出无人接收。
+/// 需要取消任务时使用`abort_handle`。
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    // 用来取消任务。这里只持有弱引用，句柄不会让已经结束的任务继续存活。
+    // 不能被取消的任务（`spawn_blocking`和`spawn_local`）这里是一个空的`Weak`。
+    task: Weak<Task>,
+    // 任务已经完成或者被取消。和`task`不同，它对所有种类的任务都有效。
+    finished: Arc<AtomicBool>,
+    id: task::Id,
+}
+
+/// 用来取消一个任务的句柄，由`JoinHandle::abort_handle`返回。
+///
+/// 它不能用来取得任务的输出，可以被克隆并发送到其他线程。
+#[derive(Clone)]
+pub struct AbortHandle {
+    task: Weak<Task>,
+    // 与`JoinHandle`共享的完成标志。它不持有任务的输出，所以句柄不会让输出继续存活。
+    finished: Arc<AtomicBool>,
+}
+
+/// 任务没有正常完成时，等待它的`JoinHandle`得到的错误。
+#[derive(Debug)]
+pub enum JoinError {
+    /// 任务在完成之前被取消了。
+    Cancelled,
+    /// 任务的未来panic了，里面是panic的载荷。
+    Panic(Box<dyn Any + Send>),
+}
+
+// 任务和它的`JoinHandle`之间共享的状态。
+// 它由两边共同持有，所以即使任务在任何人等待句柄之前就已完成，输出也会被保留下来。
+struct JoinState<T> {
+    // 任务的结果。任务完成或被取消时写入，被句柄取走。
+    output: Option<Result<T, JoinError>>,
+    // 正在等待句柄的任务的唤醒者。
+    waker: Option<Waker>,
+    // 句柄已经被丢弃，任务脱离了。
+    detached: bool,
+    // 脱离的任务panic时按照它的`UnhandledPanic`处理。`spawn_blocking`和`spawn_local`的任务为`None`，
+    // 它们的panic总是被忽略。
+    executor: Option<Handle>,
+    // 任务的名字和编号，见`task::Builder::name`和`JoinHandle::id`。
+    name: Option<Arc<str>>,
+    id: task::Id,
+}
+
+impl<T> JoinState<T> {
+    // 存储任务的结果，并唤醒正在等待句柄的任务（如果有的话）。
+    fn complete(state: &Mutex<JoinState<T>>, finished: &AtomicBool, output: Result<T, JoinError>) {
+        let waker = {
+            let mut state = state.lock().unwrap();
+            state.output = Some(output);
+            // 在唤醒之前设置，看到任务已经完成的调用者一定能取得输出。
+            finished.store(true, Ordering::SeqCst);
+            state.waker.take()
+        };
+
+        // 在释放锁之后再唤醒，被唤醒的任务可能马上就会在别处尝试获取这把锁。
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// 任务一侧持有的共享状态。它被移进包裹用户未来的适配器中：
+// 未来完成时通过`complete`写入输出；如果适配器在此之前被丢弃（任务被取消，
+// 或者运行时关闭时还在队列中），就在`drop`中以`JoinError::Cancelled`完成句柄，
+// 这样等待句柄的任务不会永远挂起。
+struct JoinGuard<T> {
+    state: Option<Arc<Mutex<JoinState<T>>>>,
+    finished: Arc<AtomicBool>,
+}
+
+impl<T> JoinGuard<T> {
+    // 创建一对共享状态的守卫和句柄，并给任务分配一个新的编号。句柄还不能用来取消任务，见`Task::new`。
+    fn new(executor: Option<Handle>) -> (JoinGuard<T>, JoinHandle<T>) {
+        let id = task::Id::next();
+        let state = Arc::new(Mutex::new(JoinState {
+            output: None,
+            waker: None,
+            detached: false,
+            executor,
+            name: None,
+            id,
+        }));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let guard = JoinGuard {
+            state: Some(state.clone()),
+            finished: finished.clone(),
+        };
+        let join = JoinHandle {
+            state,
+            task: Weak::new(),
+            finished,
+            id,
+        };
+
+        (guard, join)
+    }
+
+    fn complete(mut self, output: T) {
+        if let Some(state) = self.state.take() {
+            JoinState::complete(&state, &self.finished, Ok(output));
+        }
+    }
+
+    // 未来或者闭包panic了，把panic的载荷交给句柄。
+    // 句柄已经被丢弃时没有人会看到它，载荷按照运行时的`UnhandledPanic`处理。
+    fn panic(mut self, payload: Box<dyn Any + Send>) {
+        let state = match self.state.take() {
+            Some(state) => state,
+            None => return,
+        };
+
+        let (executor, name) = {
+            let locked = state.lock().unwrap();
+            (locked.executor.clone(), locked.name.clone())
+        };
+        if let Some(executor) = executor {
+            executor.task_panicked(&*payload, name.as_deref());
+        }
+
+        // 检查和存储在同一次持有锁时完成，这样与同时被丢弃的句柄之间只有一方会处理这个载荷，
+        // 见`JoinHandle`的`drop`。
+        let mut locked = state.lock().unwrap();
+        self.finished.store(true, Ordering::SeqCst);
+
+        if locked.detached {
+            let executor = locked.executor.clone();
+            let id = locked.id;
+            drop(locked);
+
+            let meta = TaskMeta {
+                id,
+                name: name.as_deref(),
+            };
+            unhandled_task_panic(executor, &meta, payload);
+            return;
+        }
+
+        locked.output = Some(Err(JoinError::Panic(payload)));
+        let waker = locked.waker.take();
+        drop(locked);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for JoinGuard<T> {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            JoinState::complete(&state, &self.finished, Err(JoinError::Cancelled));
+        }
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// 取消任务。等价于`self.abort_handle().abort()`，见`AbortHandle::abort`。
+    ///
+    /// 任务已经完成时没有效果，等待句柄仍然得到任务的输出。
+    pub fn abort(&self) {
+        self.abort_handle().abort();
+    }
+
+    /// 返回一个可以用来取消这个任务的`AbortHandle`。
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle {
+            task: self.task.clone(),
+            finished: self.finished.clone(),
+        }
+    }
+
+    /// 任务的编号，和任务中`task::id()`返回的值相同。
+    pub fn id(&self) -> task::Id {
+        self.id
+    }
+
+    /// 任务是否已经完成或者已经被取消。
+    ///
+    /// 返回`true`之后，`try_join`和等待句柄都会立即得到结果。
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// 不等待地取得任务的结果。任务还没有结束，或者结果已经被取走时返回`None`。
+    pub fn try_join(&mut self) -> Option<Result<T, JoinError>> {
+        if !self.is_finished() {
+            return None;
+        }
+
+        self.state.lock().unwrap().output.take()
+    }
+
+    /// 阻塞当前线程，直到任务完成，并返回它的结果。
+    ///
+    /// 这是给运行时之外的同步代码准备的。不要在任务内部调用它：
+    /// 它会阻塞执行器线程，如果被等待的任务需要同一个线程才能取得进展，就会永远挂起。
+    pub fn join(mut self) -> Result<T, JoinError> {
+        let waker = waker::waker(Arc::new(ThreadWaker {
+            thread: thread::current(),
+        }));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(output) = Pin::new(&mut self).poll(&mut cx) {
+                return output;
+            }
+
+            // 任务完成时会通过唤醒者unpark这个线程。`park`可能会虚假返回，所以在循环中重新检查。
+            thread::park();
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.state.lock().unwrap().name.clone();
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id)
+            .field("name", &name)
+            .field("finished", &self.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if poll_budget(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(output) = state.output.take() {
+            return Poll::Ready(output);
+        }
+
+        // 任务还没有完成。存储当前任务的唤醒者，任务完成时会使用它。
+        // 和`Sleep`一样，句柄可能在两次调用`poll`之间转移到不同的任务，所以需要检查唤醒者是否一致。
+        match &state.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => state.waker = Some(cx.waker().clone()),
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        // 任务在句柄被丢弃之前就panic了，载荷还没有被取走：和任务脱离之后才panic一样处理。
+        let (payload, executor, name) = {
+            let mut state = self.state.lock().unwrap();
+            state.detached = true;
+
+            match state.output.take() {
+                Some(Err(JoinError::Panic(payload))) => {
+                    (payload, state.executor.clone(), state.name.clone())
+                }
+                _ => return,
+            }
+        };
+
+        let meta = TaskMeta {
+            id: self.id,
+            name: name.as_deref(),
+        };
+        unhandled_task_panic(executor, &meta, payload);
+    }
+}
+
+impl AbortHandle {
+    /// 取消任务。
+    ///
+    /// 任务不会再被轮询：下一次被执行器取出时，它的未来被直接丢弃，
+    /// 等待`JoinHandle`会得到`JoinError::Cancelled`。如果任务此刻正在另一个线程上被轮询，
+    /// 那次轮询会正常结束，取消在此之后生效。对已经完成的任务调用没有效果。
+    pub fn abort(&self) {
+        if let Some(task) = self.task.upgrade() {
+            task.aborted.store(true, Ordering::SeqCst);
+            // 任务可能正在等待一个永远不会就绪的资源，所以主动唤醒它一次，让执行器尽快丢弃它的未来。
+            ArcWake::wake_by_ref(&task);
+        }
+    }
+
+    /// 任务是否已经完成或者已经被取消。
+    ///
+    /// 返回`true`之后，等待对应的`JoinHandle`会立即得到结果。
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+}
+
+impl JoinError {
+    /// 任务是否因为被取消而没有完成。
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, JoinError::Cancelled)
+    }
+
+    /// 任务是否因为panic而没有完成。
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JoinError::Panic(_))
+    }
+
+    /// 消耗错误，返回panic的载荷，可以交给`std::panic::resume_unwind`继续展开。
+    ///
+    /// # Panics
+    ///
+    /// 错误不是由panic造成时panic。
+    pub fn into_panic(self) -> Box<dyn Any + Send> {
+        match self {
+            JoinError::Panic(payload) => payload,
+            JoinError::Cancelled => panic!("`JoinError::into_panic` called on a cancelled task"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => f.write_str("task was cancelled"),
+            JoinError::Panic(_) => f.write_str("task panicked"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// 任务无法被催生时`Handle::spawn`返回的错误。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// 运行时已经关闭。
+    Shutdown,
+    /// 有容量限制的调度通道已满，见`Builder::max_queue_depth`。
+    QueueFull,
+    /// 当前线程不在mini-tokio运行时之中，见`try_spawn`。
+    NoRuntime,
+    /// 存活的任务数已经达到`Builder::max_tasks`设置的上限。
+    AtCapacity,
+    /// 没有这个编号的工作线程，见`Handle::spawn_pinned`。
+    NoSuchWorker,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnError::Shutdown => f.write_str("the runtime has been shut down"),
+            SpawnError::QueueFull => f.write_str("the scheduled queue is full"),
+            SpawnError::AtCapacity => f.write_str("too many tasks are alive"),
+            SpawnError::NoSuchWorker => f.write_str("there is no worker with this index"),
+            SpawnError::NoRuntime => {
+                f.write_str("must be called from the context of a mini-tokio runtime")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+// `MiniTokio::block_on`中根未来的唤醒者。被唤醒时通知执行器循环重新轮询根未来。
+struct BlockOnWaker {
+    notify: channel::Sender<()>,
+}
+
+impl ArcWake for BlockOnWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        // 通道已满意味着已经有一个通知在等待处理，这次唤醒可以被合并。
+        let _ = arc_self.notify.try_send(());
+    }
+}
+
+// 被唤醒时unpark一个线程的唤醒者。供需要在同步代码中等待未来的地方使用。
+struct ThreadWaker {
+    thread: thread::Thread,
+}
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.thread.unpark();
+    }
+}
+
+// 从`Arc<W>`构造`Waker`。
+//
+// 默认手工实现`RawWakerVTable`，这样执行器不依赖`futures`板块。启用`futures-waker`特性时
+// 改用`futures::task`中的同名工具，方便对照：两者的`ArcWake`有相同的形状，实现它的类型不需要改变。
+mod waker {
+    #[cfg(feature = "futures-waker")]
+    pub(crate) use futures::task::{waker, ArcWake};
+
+    #[cfg(not(feature = "futures-waker"))]
+    pub(crate) use self::raw::{waker, ArcWake};
+
+    #[cfg(not(feature = "futures-waker"))]
+    mod raw {
+        use std::mem::ManuallyDrop;
+        use std::sync::Arc;
+        use std::task::{RawWaker, RawWakerVTable, Waker};
+
+        // 可以被唤醒的类型，和`futures::task::ArcWake`相同。
+        pub(crate) trait ArcWake: Send + Sync {
+            // 消耗一个引用的唤醒。
+            fn wake(self: Arc<Self>) {
+                Self::wake_by_ref(&self)
+            }
+
+            // 不消耗引用的唤醒。
+            fn wake_by_ref(arc_self: &Arc<Self>);
+        }
+
+        // 用`wake`构造一个唤醒者。唤醒者持有`wake`的一个强引用：每个克隆各持有一个，
+        // 最后一个唤醒者被丢弃（或者被`wake`消耗）时引用随之释放。
+        pub(crate) fn waker<W: ArcWake + 'static>(wake: Arc<W>) -> Waker {
+            let data = Arc::into_raw(wake) as *const ();
+
+            // 安全：`data`来自`Arc::into_raw`，它拥有的那个强引用交给了这个唤醒者，
+            // 下面的每个函数都按照同样的约定使用它。
+            unsafe { Waker::from_raw(RawWaker::new(data, vtable::<W>())) }
+        }
+
+        fn vtable<W: ArcWake + 'static>() -> &'static RawWakerVTable {
+            &RawWakerVTable::new(clone::<W>, wake::<W>, wake_by_ref::<W>, drop::<W>)
+        }
+
+        // 下面的函数只被`vtable`中的唤醒者调用，`data`总是`waker`中`Arc::into_raw`返回的指针，
+        // 而且调用者拥有一个强引用。
+
+        // 克隆为新的唤醒者增加一个强引用。
+        unsafe fn clone<W: ArcWake + 'static>(data: *const ()) -> RawWaker {
+            Arc::increment_strong_count(data as *const W);
+            RawWaker::new(data, vtable::<W>())
+        }
+
+        // `Waker::wake`消耗唤醒者，也就消耗它的强引用。
+        unsafe fn wake<W: ArcWake + 'static>(data: *const ()) {
+            ArcWake::wake(Arc::from_raw(data as *const W));
+        }
+
+        // `Waker::wake_by_ref`不消耗唤醒者：借用它的强引用，之后不释放。
+        unsafe fn wake_by_ref<W: ArcWake + 'static>(data: *const ()) {
+            let wake = ManuallyDrop::new(Arc::from_raw(data as *const W));
+            ArcWake::wake_by_ref(&wake);
+        }
+
+        unsafe fn drop<W: ArcWake + 'static>(data: *const ()) {
+            std::mem::drop(Arc::from_raw(data as *const W));
+        }
+    }
+}
+
+// 只用标准库实现的通道和双端队列，启用`std-only`特性时代替`crossbeam`。
+//
+// 只实现了运行时用到的那部分接口，语义和`crossbeam`相同：任意多个发送者和接收者，阻塞的`recv`，
+// 推入时叫醒等待的接收者，所有发送者（或者所有接收者）被丢弃时断开。每个通道是一个`Mutex<VecDeque<T>>`
+// 加上一个`Condvar`，所以比`crossbeam`的无锁实现慢，但行为是一样的。
+#[cfg(feature = "std-only")]
+mod std_queue {
+    pub(crate) mod channel {
+        use std::collections::VecDeque;
+        use std::fmt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Condvar, Mutex, Weak};
+        use std::time::Instant;
+
+        // 通道的共享状态。
+        struct Chan<T> {
+            state: Mutex<State<T>>,
+            // 有消息可取，或者所有发送者都被丢弃了。
+            not_empty: Condvar,
+            // 有位置可放，或者所有接收者都被丢弃了。
+            not_full: Condvar,
+        }
+
+        struct State<T> {
+            queue: VecDeque<T>,
+            capacity: Option<usize>,
+            senders: usize,
+            receivers: usize,
+            // 正在`select!`中等待这个通道的线程，见`Signal`。
+            selectors: Vec<Weak<Signal>>,
+        }
+
+        impl<T> State<T> {
+            fn disconnected(&self) -> bool {
+                self.senders == 0
+            }
+
+            // 叫醒在`select!`中等待的线程，顺便清理已经离开的等待者。
+            fn notify_selectors(&mut self) {
+                self.selectors.retain(|signal| match signal.upgrade() {
+                    Some(signal) => {
+                        signal.notify();
+                        true
+                    }
+                    None => false,
+                });
+            }
+        }
+
+        /// 通道的发送端。
+        pub(crate) struct Sender<T> {
+            chan: Arc<Chan<T>>,
+        }
+
+        /// 通道的接收端。
+        pub(crate) struct Receiver<T> {
+            chan: Arc<Chan<T>>,
+        }
+
+        /// `Sender::send`失败：所有接收者都被丢弃了。消息被还给调用者。
+        #[derive(Debug, PartialEq, Eq)]
+        pub(crate) struct SendError<T>(pub T);
+
+        /// `Sender::try_send`失败的原因。消息被还给调用者。
+        #[derive(Debug, PartialEq, Eq)]
+        pub(crate) enum TrySendError<T> {
+            Full(T),
+            Disconnected(T),
+        }
+
+        /// `Receiver::recv`失败：通道为空，而且所有发送者都被丢弃了。
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub(crate) struct RecvError;
+
+        /// `Receiver::try_recv`失败的原因。
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub(crate) enum TryRecvError {
+            Empty,
+            Disconnected,
+        }
+
+        impl fmt::Display for RecvError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("receiving on an empty and disconnected channel")
+            }
+        }
+
+        // 通道的容量为`None`时没有限制。容量为零的通道只被用作关闭信号：从不发送消息，
+        // 接收者在所有发送者被丢弃时醒来。所以这里不实现`crossbeam`的会合语义，只是永远没有位置。
+        fn new<T>(capacity: Option<usize>, senders: usize) -> (Sender<T>, Receiver<T>) {
+            let chan = Arc::new(Chan {
+                state: Mutex::new(State {
+                    queue: VecDeque::new(),
+                    capacity,
+                    senders,
+                    receivers: 1,
+                    selectors: Vec::new(),
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+            });
+
+            (Sender { chan: chan.clone() }, Receiver { chan })
+        }
+
+        /// 创建一个没有容量限制的通道。
+        pub(crate) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+            new(None, 1)
+        }
+
+        /// 创建一个最多容纳`capacity`条消息的通道。
+        pub(crate) fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+            new(Some(capacity), 1)
+        }
+
+        /// 返回一个永远收不到消息、也永远不会断开的接收者。
+        pub(crate) fn never<T>() -> Receiver<T> {
+            // 多计入一个不存在的发送者，发送者的计数永远不会归零。真正的发送者随即被丢弃，
+            // 通道在接收者被丢弃时照常被释放。
+            let (sender, receiver) = new(Some(0), 2);
+            drop(sender);
+            receiver
+        }
+
+        impl<T> Sender<T> {
+            /// 发送一条消息，通道已满时阻塞等待位置。
+            pub(crate) fn send(&self, value: T) -> Result<(), SendError<T>> {
+                let mut state = self.chan.state.lock().unwrap();
+
+                loop {
+                    if state.receivers == 0 {
+                        return Err(SendError(value));
+                    }
+                    if state
+                        .capacity
+                        .is_none_or(|capacity| state.queue.len() < capacity)
+                    {
+                        break;
+                    }
+                    state = self.chan.not_full.wait(state).unwrap();
+                }
+
+                self.push(state, value);
+                Ok(())
+            }
+
+            /// 尝试发送一条消息，不阻塞。
+            pub(crate) fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+                let state = self.chan.state.lock().unwrap();
+
+                if state.receivers == 0 {
+                    return Err(TrySendError::Disconnected(value));
+                }
+                if state
+                    .capacity
+                    .is_some_and(|capacity| state.queue.len() >= capacity)
+                {
+                    return Err(TrySendError::Full(value));
+                }
+
+                self.push(state, value);
+                Ok(())
+            }
+
+            // 在持有锁时推入消息，然后叫醒一个接收者和所有在`select!`中等待的线程。
+            fn push(&self, mut state: std::sync::MutexGuard<'_, State<T>>, value: T) {
+                state.queue.push_back(value);
+                state.notify_selectors();
+                drop(state);
+                self.chan.not_empty.notify_one();
+            }
+
+            /// 通道的容量，没有限制时为`None`。
+            pub(crate) fn capacity(&self) -> Option<usize> {
+                self.chan.state.lock().unwrap().capacity
+            }
+
+            /// 通道中的消息数。
+            pub(crate) fn len(&self) -> usize {
+                self.chan.state.lock().unwrap().queue.len()
+            }
+        }
+
+        impl<T> Clone for Sender<T> {
+            fn clone(&self) -> Sender<T> {
+                self.chan.state.lock().unwrap().senders += 1;
+                Sender {
+                    chan: self.chan.clone(),
+                }
+            }
+        }
+
+        impl<T> Drop for Sender<T> {
+            fn drop(&mut self) {
+                let mut state = self.chan.state.lock().unwrap();
+                state.senders -= 1;
+                if state.senders == 0 {
+                    state.notify_selectors();
+                    drop(state);
+                    self.chan.not_empty.notify_all();
+                }
+            }
+        }
+
+        impl<T> Receiver<T> {
+            /// 取出一条消息，通道为空时阻塞等待。通道为空而且所有发送者都被丢弃时返回错误。
+            pub(crate) fn recv(&self) -> Result<T, RecvError> {
+                let mut state = self.chan.state.lock().unwrap();
+
+                loop {
+                    if let Some(value) = self.pop(&mut state) {
+                        return Ok(value);
+                    }
+                    if state.disconnected() {
+                        return Err(RecvError);
+                    }
+                    state = self.chan.not_empty.wait(state).unwrap();
+                }
+            }
+
+            /// 尝试取出一条消息，不阻塞。
+            pub(crate) fn try_recv(&self) -> Result<T, TryRecvError> {
+                let mut state = self.chan.state.lock().unwrap();
+
+                match self.pop(&mut state) {
+                    Some(value) => Ok(value),
+                    None if state.disconnected() => Err(TryRecvError::Disconnected),
+                    None => Err(TryRecvError::Empty),
+                }
+            }
+
+            fn pop(&self, state: &mut State<T>) -> Option<T> {
+                let value = state.queue.pop_front()?;
+                if state.capacity.is_some() {
+                    self.chan.not_full.notify_one();
+                }
+                Some(value)
+            }
+
+            /// 通道中的消息数。
+            pub(crate) fn len(&self) -> usize {
+                self.chan.state.lock().unwrap().queue.len()
+            }
+
+            /// 通道是否为空。
+            pub(crate) fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+        }
+
+        impl<T> Clone for Receiver<T> {
+            fn clone(&self) -> Receiver<T> {
+                self.chan.state.lock().unwrap().receivers += 1;
+                Receiver {
+                    chan: self.chan.clone(),
+                }
+            }
+        }
+
+        impl<T> Drop for Receiver<T> {
+            fn drop(&mut self) {
+                let mut state = self.chan.state.lock().unwrap();
+                state.receivers -= 1;
+                if state.receivers == 0 {
+                    drop(state);
+                    self.chan.not_full.notify_all();
+                }
+            }
+        }
+
+        impl<T> fmt::Debug for Sender<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Sender").finish_non_exhaustive()
+            }
+        }
+
+        impl<T> fmt::Debug for Receiver<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Receiver").finish_non_exhaustive()
+            }
+        }
+
+        // 一个在`select!`中等待的线程。通道有了消息或者断开时设置标记并叫醒它。
+        #[derive(Default)]
+        pub(crate) struct Signal {
+            ready: Mutex<bool>,
+            condvar: Condvar,
+        }
+
+        impl Signal {
+            fn notify(&self) {
+                *self.ready.lock().unwrap() = true;
+                self.condvar.notify_one();
+            }
+        }
+
+        // `select!`等待的接收者，擦除了消息的类型。
+        pub(crate) trait Select {
+            // `recv`是否会立即返回：有消息，或者已经断开。
+            fn is_ready(&self) -> bool;
+            fn register(&self, signal: &Arc<Signal>);
+            fn unregister(&self, signal: &Arc<Signal>);
+        }
+
+        impl<T> Select for Receiver<T> {
+            fn is_ready(&self) -> bool {
+                let state = self.chan.state.lock().unwrap();
+                !state.queue.is_empty() || state.disconnected()
+            }
+
+            fn register(&self, signal: &Arc<Signal>) {
+                let mut state = self.chan.state.lock().unwrap();
+                state.selectors.push(Arc::downgrade(signal));
+            }
+
+            fn unregister(&self, signal: &Arc<Signal>) {
+                let mut state = self.chan.state.lock().unwrap();
+                let signal = Arc::downgrade(signal);
+                state.selectors.retain(|other| !other.ptr_eq(&signal));
+            }
+        }
+
+        impl<T> Receiver<T> {
+            // `select!`的一个分支：取出一条消息，已经断开时返回错误，和`recv`一样。
+            // 没有消息（被另一个接收者抢先取走了）时返回`None`。
+            pub(crate) fn try_select(&self) -> Option<Result<T, RecvError>> {
+                match self.try_recv() {
+                    Ok(value) => Some(Ok(value)),
+                    Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+                    Err(TryRecvError::Empty) => None,
+                }
+            }
+        }
+
+        // 等待直到某个接收者就绪，或者到了`deadline`，返回就绪的那个接收者的下标，超时时返回`None`。
+        //
+        // 几个接收者同时就绪时轮流选择，和`crossbeam`随机选择的效果一样：总是选第一个的话，
+        // 一个一直就绪的接收者（例如`block_on`中不停让出的根未来）会让其他分支永远得不到执行。
+        pub(crate) fn wait_ready(
+            receivers: &[&dyn Select],
+            deadline: Option<Instant>,
+        ) -> Option<usize> {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+            let signal = Arc::new(Signal::default());
+            for receiver in receivers {
+                receiver.register(&signal);
+            }
+
+            // 登记之后再检查一遍：之后的推入一定会设置标记。
+            let start = NEXT.fetch_add(1, Ordering::Relaxed);
+            let ready = loop {
+                let ready = (0..receivers.len())
+                    .map(|i| (start + i) % receivers.len())
+                    .find(|&i| receivers[i].is_ready());
+                if ready.is_some() {
+                    break ready;
+                }
+
+                let mut notified = signal.ready.lock().unwrap();
+                while !*notified {
+                    notified = match deadline {
+                        Some(deadline) => {
+                            let now = Instant::now();
+                            if now >= deadline {
+                                break;
+                            }
+                            signal
+                                .condvar
+                                .wait_timeout(notified, deadline - now)
+                                .unwrap()
+                                .0
+                        }
+                        None => signal.condvar.wait(notified).unwrap(),
+                    };
+                }
+                if !std::mem::replace(&mut *notified, false) {
+                    break None;
+                }
+            };
+
+            for receiver in receivers {
+                receiver.unregister(&signal);
+            }
+            ready
+        }
+
+        // `crossbeam::channel::select!`的一个子集：`recv(rx) -> 结果 => 分支`，最后可以有一个`default(超时) => 分支`。
+        //
+        // 和`crossbeam`不同，选中的接收者的消息可能被另一个接收者抢先取走，这时一个分支都不执行就返回。
+        // 运行时总是在循环中使用它，所以这只是多转一圈。分支在任何循环之外执行，其中的`break`作用于调用者的循环。
+        macro_rules! select {
+            (@parse [$($arms:tt)*] recv($rx:expr) -> $res:pat => $body:block $($rest:tt)*) => {
+                $crate::channel::select!(@parse [$($arms)* ($rx, $res, $body)] $($rest)*)
+            };
+            (@parse [$($arms:tt)*] recv($rx:expr) -> $res:pat => $body:expr, $($rest:tt)*) => {
+                $crate::channel::select!(@parse [$($arms)* ($rx, $res, $body)] $($rest)*)
+            };
+            (@parse [$($arms:tt)*] recv($rx:expr) -> $res:pat => $body:expr) => {
+                $crate::channel::select!(@parse [$($arms)* ($rx, $res, $body)])
+            };
+            (@parse [$(($rx:expr, $res:pat, $body:tt))*] $(default($timeout:expr) => $default:expr $(,)?)?) => {{
+                let deadline: Option<std::time::Instant> = None $(.or(Some(std::time::Instant::now() + $timeout)))?;
+                let ready = $crate::channel::wait_ready(&[$(&$rx),*], deadline);
+                $crate::channel::select!(@chain ready 0; [$(($rx, $res, $body))*] [$($default)?])
+            }};
+            (@chain $ready:ident $index:expr; [] []) => {{
+                let _ = $ready;
+            }};
+            (@chain $ready:ident $index:expr; [] [$default:expr]) => {
+                if $ready.is_none() {
+                    $default
+                }
+            };
+            (@chain $ready:ident $index:expr; [($rx:expr, $res:pat, $body:tt) $($rest:tt)*] [$($default:tt)*]) => {
+                if $ready == Some($index) {
+                    if let Some(result) = $rx.try_select() {
+                        let $res = result;
+                        $body
+                    }
+                } else {
+                    $crate::channel::select!(@chain $ready $index + 1; [$($rest)*] [$($default)*])
+                }
+            };
+            ($($tokens:tt)*) => {
+                $crate::channel::select!(@parse [] $($tokens)*)
+            };
+        }
+
+        pub(crate) use select;
+    }
+
+    pub(crate) mod deque {
+        use std::collections::VecDeque;
+        use std::sync::{Arc, Mutex};
+
+        /// 一个工作线程的本地队列，只有它自己推入和取出，其他线程通过`Stealer`窃取。
+        pub(crate) struct Worker<T> {
+            queue: Arc<Mutex<VecDeque<T>>>,
+        }
+
+        /// 从另一个工作线程的本地队列中窃取任务的句柄。
+        pub(crate) struct Stealer<T> {
+            queue: Arc<Mutex<VecDeque<T>>>,
+        }
+
+        /// `Stealer::steal_batch_and_pop`的结果。有锁的实现从不需要重试，保留`Retry`只是为了和`crossbeam`一致。
+        pub(crate) enum Steal<T> {
+            Empty,
+            Success(T),
+            Retry,
+        }
+
+        impl<T> Worker<T> {
+            /// 创建一个先进先出的队列。
+            pub(crate) fn new_fifo() -> Worker<T> {
+                Worker {
+                    queue: Arc::new(Mutex::new(VecDeque::new())),
+                }
+            }
+
+            pub(crate) fn push(&self, value: T) {
+                self.queue.lock().unwrap().push_back(value);
+            }
+
+            pub(crate) fn pop(&self) -> Option<T> {
+                self.queue.lock().unwrap().pop_front()
+            }
+
+            pub(crate) fn stealer(&self) -> Stealer<T> {
+                Stealer {
+                    queue: self.queue.clone(),
+                }
+            }
+        }
+
+        impl<T> Stealer<T> {
+            /// 窃取大约一半的任务放进`dest`，并返回其中最早的一个。
+            pub(crate) fn steal_batch_and_pop(&self, dest: &Worker<T>) -> Steal<T> {
+                // 不能同时持有两把锁：两个工作线程互相窃取时会死锁。
+                let mut batch: VecDeque<T> = {
+                    let mut queue = self.queue.lock().unwrap();
+                    let n = queue.len().div_ceil(2);
+                    queue.drain(..n).collect()
+                };
+
+                match batch.pop_front() {
+                    Some(first) => {
+                        dest.queue.lock().unwrap().extend(batch);
+                        Steal::Success(first)
+                    }
+                    None => Steal::Empty,
+                }
+            }
+
+            pub(crate) fn len(&self) -> usize {
+                self.queue.lock().unwrap().len()
+            }
+        }
+
+        impl<T> Clone for Stealer<T> {
+            fn clone(&self) -> Stealer<T> {
+                Stealer {
+                    queue: self.queue.clone(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // 第一次轮询时把唤醒者交给测试，之后记录被轮询的次数，直到`done`被设置。
+    struct CountPolls {
+        polls: Arc<AtomicUsize>,
+        waker: channel::Sender<Waker>,
+        done: Arc<AtomicBool>,
+    }
+
+    impl Future for CountPolls {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            self.polls.fetch_add(1, Ordering::SeqCst);
+
+            if self.done.load(Ordering::SeqCst) {
+                return Poll::Ready(());
+            }
+
+            let _ = self.waker.send(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn task_woken_twice_is_queued_once() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let polls = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let (waker_tx, waker_rx) = channel::unbounded();
+
+        let handle = mini_tokio.spawn(CountPolls {
+            polls: polls.clone(),
+            waker: waker_tx,
+            done: done.clone(),
+        });
+
+        let waker = waker_rx.recv().unwrap();
+        // 等第一次轮询结束，任务回到空闲状态。
+        thread::sleep(Duration::from_millis(20));
+        done.store(true, Ordering::SeqCst);
+
+        waker.wake_by_ref();
+        waker.wake_by_ref();
+        handle.join().unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn wake_during_poll_reschedules_after_poll() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        // 任务在轮询中唤醒自己，然后阻塞一段时间。唤醒只应该让它在这次轮询结束后再被轮询一次。
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let handle = mini_tokio.spawn(poll_fn(move |cx| {
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                cx.waker().wake_by_ref();
+                thread::sleep(Duration::from_millis(50));
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        }));
+
+        handle.join().unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn many_tasks_across_four_workers() {
+        let mini_tokio = MiniTokio::new_multi_thread(4);
+
+        // 每个任务在轮询中唤醒自己多次，这些唤醒会和其他工作线程的窃取交错。
+        // 任务在每次轮询时检查自己没有同时被另一个线程轮询。
+        let total = mini_tokio.block_on(async {
+            let handles: Vec<_> = (0..1000u64)
+                .map(|n| {
+                    let polling = AtomicBool::new(false);
+                    let mut remaining = 10;
+
+                    spawn(poll_fn(move |cx| {
+                        assert!(!polling.swap(true, Ordering::SeqCst));
+
+                        let poll = if remaining == 0 {
+                            Poll::Ready(n)
+                        } else {
+                            remaining -= 1;
+                            cx.waker().wake_by_ref();
+                            cx.waker().wake_by_ref();
+                            thread::yield_now();
+                            Poll::Pending
+                        };
+
+                        polling.store(false, Ordering::SeqCst);
+                        poll
+                    }))
+                })
+                .collect();
+
+            let mut total = 0;
+            for handle in handles {
+                total += handle.await.unwrap();
+            }
+            total
+        });
+
+        assert_eq!(total, 999 * 1000 / 2);
+    }
+
+    #[test]
+    fn cancelled_delay_releases_its_task() {
+        let mini_tokio = MiniTokio::new();
+
+        // 未来先完成，`Sleep`在截止时间之前被丢弃。定时器驱动中的条目不能让任务继续存活。
+        let handle = mini_tokio.spawn(async {
+            let future = async {
+                yield_now().await;
+                1
+            };
+            timeout(Duration::from_secs(60), future).await.unwrap()
+        });
+        let abort = handle.abort_handle();
+
+        assert_eq!(mini_tokio.block_on(handle).unwrap(), 1);
+        assert!(abort.task.upgrade().is_none());
+    }
+
+    #[test]
+    fn many_cancelled_delays() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        let completed = mini_tokio.block_on(async {
+            let handles: Vec<_> = (0..10_000)
+                .map(|_| spawn(timeout(Duration::from_secs(60), yield_now())))
+                .collect();
+
+            let mut completed = 0;
+            for handle in handles {
+                if handle.await.unwrap().is_ok() {
+                    completed += 1;
+                }
+            }
+            completed
+        });
+
+        assert_eq!(completed, 10_000);
+    }
+
+    #[test]
+    fn panicking_blocking_job_releases_its_thread() {
+        let mini_tokio = Builder::new().max_blocking_threads(1).build().unwrap();
+
+        let (first, second) = mini_tokio.block_on(async {
+            let first = spawn_blocking(|| panic!("boom")).await;
+            let second = timeout(Duration::from_secs(2), spawn_blocking(|| 5)).await;
+            (first, second)
+        });
+
+        assert!(first.unwrap_err().is_panic());
+        assert_eq!(second.unwrap().unwrap(), 5);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_configuration() {
+        let invalid: [fn(&mut Builder) -> &mut Builder; 8] = [
+            |builder| builder.worker_threads(0),
+            |builder| builder.max_blocking_threads(0),
+            |builder| builder.thread_stack_size(0),
+            |builder| builder.max_queue_depth(0),
+            |builder| builder.global_queue_interval(0),
+            |builder| builder.max_tasks(0),
+            |builder| builder.slow_poll_threshold(Duration::ZERO),
+            |builder| builder.deadlock_timeout(Duration::ZERO),
+        ];
+
+        for configure in invalid.iter() {
+            let err = configure(&mut Builder::new()).build().err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn builder_names_worker_threads() {
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            .thread_name("custom-worker")
+            .thread_stack_size(1 << 20)
+            .build()
+            .unwrap();
+
+        let name = mini_tokio
+            .spawn(async { thread::current().name().map(str::to_string) })
+            .join()
+            .unwrap();
+
+        assert_eq!(name.as_deref(), Some("custom-worker"));
+    }
+
+    #[test]
+    fn handle_block_on_from_another_thread() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let handle = mini_tokio.handle();
+
+        let output = thread::spawn(move || {
+            handle.block_on(async {
+                delay(Duration::from_millis(10)).await;
+                1
+            })
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(output, 1);
+    }
+
+    #[test]
+    fn handle_block_on_from_a_blocking_thread() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let handle = mini_tokio.handle();
+
+        let output = mini_tokio
+            .spawn(async move { spawn_blocking(move || handle.block_on(async { 2 })).await })
+            .join()
+            .unwrap();
+
+        assert_eq!(output.unwrap(), 2);
+    }
+
+    #[test]
+    fn handle_block_on_refuses_worker_threads() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let handle = mini_tokio.handle();
+
+        let refused = mini_tokio
+            .spawn(async move {
+                panic::catch_unwind(AssertUnwindSafe(|| handle.block_on(async {}))).is_err()
+            })
+            .join()
+            .unwrap();
+        assert!(refused);
+
+        let handle = mini_tokio.handle();
+        let refused = mini_tokio.block_on(async move {
+            panic::catch_unwind(AssertUnwindSafe(|| handle.block_on(async {}))).is_err()
+        });
+        assert!(refused);
+    }
+
+    #[test]
+    fn spawn_reports_a_full_queue() {
+        let mini_tokio = Builder::new().max_queue_depth(2).build().unwrap();
+        let handle = mini_tokio.handle();
+
+        // 没有线程在驱动运行时，调度通道中的任务不会被取走。
+        let first = handle.spawn(async { 1 }).unwrap();
+        let second = handle.spawn(async { 2 }).unwrap();
+        assert_eq!(handle.spawn(async { 3 }).err(), Some(SpawnError::QueueFull));
+
+        let spawned = panic::catch_unwind(AssertUnwindSafe(|| mini_tokio.spawn(async { 4 })));
+        assert!(spawned.is_err());
+
+        assert_eq!(mini_tokio.block_on(first).unwrap(), 1);
+        assert_eq!(mini_tokio.block_on(second).unwrap(), 2);
+    }
+
+    #[test]
+    fn wakes_overflow_a_full_queue() {
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            .max_queue_depth(2)
+            .build()
+            .unwrap();
+
+        // 同时被唤醒的任务比调度通道的容量多得多，多出来的唤醒进入溢出列表，不会丢失。
+        // 所有任务都在同一时刻醒来，在此之前调度通道有足够的空位给新催生的任务。
+        let wake_at = Instant::now() + Duration::from_millis(50);
+
+        let total = mini_tokio.block_on(async {
+            let mut handles = Vec::new();
+            for n in 0..20u64 {
+                handles.push(spawn(async move {
+                    delay(wake_at.saturating_duration_since(Instant::now())).await;
+                    for _ in 0..20 {
+                        yield_now().await;
+                    }
+                    n
+                }));
+                delay(Duration::from_millis(1)).await;
+            }
+
+            let mut total = 0;
+            for handle in handles {
+                total += handle.await.unwrap();
+            }
+            total
+        });
+
+        assert_eq!(total, 19 * 20 / 2);
+    }
+
+    #[test]
+    fn mpsc_two_producers_one_consumer() {
+        const N: u64 = 1000;
+
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        let (count, sum) = mini_tokio.block_on(async {
+            // 容量很小，生产者会频繁地在`send`上等待。
+            let (tx, mut rx) = mpsc::channel(4);
+            let tx2 = tx.clone();
+
+            spawn(async move {
+                for n in 0..N / 2 {
+                    tx.send(n).await.unwrap();
+                }
+            });
+            spawn(async move {
+                for n in N / 2..N {
+                    tx2.send(n).await.unwrap();
+                }
+            });
+
+            let consumer = spawn(async move {
+                let (mut count, mut sum) = (0, 0);
+                while let Some(n) = rx.recv().await {
+                    count += 1;
+                    sum += n;
+                }
+                (count, sum)
+            });
+
+            consumer.await.unwrap()
+        });
+
+        assert_eq!(count, N);
+        assert_eq!(sum, N * (N - 1) / 2);
+    }
+
+    #[test]
+    fn mpsc_send_after_receiver_dropped() {
+        let mini_tokio = MiniTokio::new();
+
+        let result = mini_tokio.block_on(async {
+            let (tx, rx) = mpsc::channel(1);
+            drop(rx);
+            tx.send(1).await
+        });
+
+        assert_eq!(result, Err(mpsc::SendError(1)));
+    }
+
+    #[test]
+    fn mpsc_cancelled_send_leaves_the_waiter_queue() {
+        let mini_tokio = MiniTokio::new();
+
+        let received = mini_tokio.block_on(async {
+            let (tx, mut rx) = mpsc::channel(1);
+            tx.send(0).await.unwrap();
+
+            // 第一个等待者超时被取消，第二个等待者应该在腾出位置后被唤醒。
+            let first = tx.clone();
+            let first = spawn(async move {
+                timeout(Duration::from_millis(10), first.send(1))
+                    .await
+                    .is_err()
+            });
+            let second = tx.clone();
+            let second = spawn(async move { second.send(2).await.unwrap() });
+
+            assert!(first.await.unwrap());
+            assert_eq!(rx.recv().await, Some(0));
+            second.await.unwrap();
+            rx.recv().await
+        });
+
+        assert_eq!(received, Some(2));
+    }
+
+    #[test]
+    fn run_returns_after_shutdown() {
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+
+        // 一个永远处于`Poll::Pending`的任务不能让`run`挂起。
+        mini_tokio.spawn(async {
+            delay(Duration::from_secs(60)).await;
+        });
+        mini_tokio.spawn(async move {
+            handle.shutdown();
+        });
+
+        mini_tokio.run();
+    }
+
+    #[test]
+    fn run_returns_after_shutdown_from_another_thread() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let handle = mini_tokio.handle();
+
+        mini_tokio.spawn(async {
+            delay(Duration::from_secs(60)).await;
+        });
+
+        let shutdown = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            handle.shutdown();
+        });
+
+        mini_tokio.run();
+        shutdown.join().unwrap();
+    }
+
+    #[test]
+    fn join_handles_of_different_output_types() {
+        #[derive(Debug, PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let mini_tokio = MiniTokio::new();
+
+        let outputs = mini_tokio.block_on(async {
+            let text = spawn(async { "hello".to_string() });
+            let number = spawn(async { 7u32 });
+            let point = spawn(async { Point { x: 1, y: 2 } });
+
+            (
+                text.await.unwrap(),
+                number.await.unwrap(),
+                point.await.unwrap(),
+            )
+        });
+
+        assert_eq!(outputs, ("hello".to_string(), 7, Point { x: 1, y: 2 }));
+    }
+
+    #[test]
+    fn block_on_a_delay() {
+        let mini_tokio = MiniTokio::new();
+        let start = Instant::now();
+
+        let output = mini_tokio.block_on(async {
+            delay(Duration::from_millis(20)).await;
+            "done"
+        });
+
+        assert_eq!(output, "done");
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn multi_thread_runs_blocking_tasks_in_parallel() {
+        // 每个任务在轮询中阻塞一段时间。单线程时它们只能一个接一个地执行。
+        fn run_tasks(mini_tokio: MiniTokio) -> Duration {
+            let start = Instant::now();
+
+            mini_tokio.block_on(async {
+                let handles: Vec<_> = (0..100)
+                    .map(|_| spawn(async { thread::sleep(Duration::from_millis(4)) }))
+                    .collect();
+
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            });
+
+            start.elapsed()
+        }
+
+        let single = run_tasks(MiniTokio::new());
+        let multi = run_tasks(MiniTokio::new_multi_thread(4));
+
+        assert!(
+            multi * 2 < single,
+            "single: {:?}, multi: {:?}",
+            single,
+            multi
+        );
+    }
+
+    #[test]
+    fn thousands_of_delays_fire_in_order() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+
+        mini_tokio.block_on({
+            let fired = fired.clone();
+            async move {
+                let handles: Vec<_> = (0..1000u64)
+                    .map(|n| {
+                        // 打乱催生的顺序，截止时间分布在0到100毫秒之间。
+                        let millis = n * 7919 % 100;
+                        let fired = fired.clone();
+                        spawn(async move {
+                            delay(Duration::from_millis(millis)).await;
+                            fired.lock().unwrap().push((millis, start.elapsed()));
+                        })
+                    })
+                    .collect();
+
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            }
+        });
+
+        let fired = fired.lock().unwrap();
+        assert_eq!(fired.len(), 1000);
+
+        // 没有延迟提前完成，并且完成的顺序大致符合截止时间的顺序。
+        let mut latest = 0;
+        for &(millis, elapsed) in fired.iter() {
+            assert!(elapsed >= Duration::from_millis(millis));
+            assert!(
+                millis + 20 >= latest,
+                "{}ms fired after {}ms",
+                millis,
+                latest
+            );
+            latest = latest.max(millis);
+        }
+    }
+
+    #[test]
+    fn idle_workers_steal_from_a_busy_worker() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let (waker_tx, waker_rx) = channel::unbounded::<Waker>();
+
+        // 50个任务第一次被轮询时交出自己的唤醒者，第二次被轮询时报告执行它的线程。
+        let sleepers: Vec<_> = (0..50)
+            .map(|_| {
+                let waker_tx = waker_tx.clone();
+                let mut woken = false;
+                mini_tokio.spawn(poll_fn(move |cx| {
+                    if woken {
+                        return Poll::Ready(thread::current().id());
+                    }
+                    woken = true;
+                    waker_tx.send(cx.waker().clone()).unwrap();
+                    Poll::Pending
+                }))
+            })
+            .collect();
+
+        let wakers: Vec<_> = (0..50).map(|_| waker_rx.recv().unwrap()).collect();
+
+        // 在一个工作线程上唤醒所有任务，它们都进入这个线程的本地队列。这个线程随后阻塞，
+        // 只有当另一个工作线程从它的本地队列中窃取时，这些任务才会在别的线程上执行。
+        let busy = mini_tokio
+            .spawn(async move {
+                for waker in wakers {
+                    waker.wake();
+                }
+                thread::sleep(Duration::from_millis(50));
+                thread::current().id()
+            })
+            .join()
+            .unwrap();
+
+        let stolen = sleepers
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&id| id != busy)
+            .count();
+
+        assert!(stolen > 0);
+    }
+
+    #[test]
+    fn timeout_fast_and_slow_futures() {
+        let mini_tokio = MiniTokio::new();
+
+        let (fast, slow) = mini_tokio.block_on(async {
+            let fast = timeout(Duration::from_millis(50), async { 1 }).await;
+            let slow = timeout(Duration::from_millis(10), async {
+                delay(Duration::from_secs(60)).await;
+                2
+            })
+            .await;
+            (fast, slow)
+        });
+
+        assert_eq!(fast, Ok(1));
+        assert_eq!(slow, Err(Elapsed(())));
+        assert_eq!(slow.unwrap_err().to_string(), "deadline has elapsed");
+    }
+
+    #[test]
+    fn interval_ticks_without_drift() {
+        let mini_tokio = MiniTokio::new();
+        let period = Duration::from_millis(20);
+
+        let elapsed = mini_tokio.block_on(async move {
+            let mut interval = interval(period);
+            let start = Instant::now();
+            for _ in 0..5 {
+                interval.tick().await;
+            }
+            start.elapsed()
+        });
+
+        // 第一次滴答立即完成，之后的四次各自等待一个周期。
+        assert!(elapsed >= period * 4);
+        assert!(elapsed < period * 4 + Duration::from_millis(40));
+    }
+
+    #[test]
+    fn missed_tick_behaviors() {
+        let deadline = Instant::now();
+        let period = Duration::from_millis(10);
+        // 在截止时间之后又过去了三个半周期。
+        let now = deadline + Duration::from_millis(35);
+
+        let next = |behavior: MissedTickBehavior| behavior.next_deadline(deadline, now, period);
+
+        assert_eq!(next(MissedTickBehavior::Burst), deadline + period);
+        assert_eq!(next(MissedTickBehavior::Delay), now + period);
+        assert_eq!(
+            next(MissedTickBehavior::Skip),
+            deadline + Duration::from_millis(40)
+        );
+    }
+
+    #[test]
+    fn blocking_pool_caps_threads_and_runs_queued_jobs_in_order() {
+        const CAP: usize = 2;
+
+        let mini_tokio = Builder::new()
+            .max_blocking_threads(CAP)
+            .thread_keep_alive(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+
+        mini_tokio.block_on({
+            let started = started.clone();
+            let threads = threads.clone();
+            async move {
+                let handles: Vec<_> = (0..CAP + 3)
+                    .map(|n| {
+                        let started = started.clone();
+                        let threads = threads.clone();
+                        spawn_blocking(move || {
+                            started.lock().unwrap().push(n);
+                            threads.lock().unwrap().insert(thread::current().id());
+                            thread::sleep(Duration::from_millis(10));
+                        })
+                    })
+                    .collect();
+
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            }
+        });
+
+        assert_eq!(threads.lock().unwrap().len(), CAP);
+
+        // 前`CAP`个闭包立即开始，开始的先后不确定；排队的闭包按提交的顺序开始。
+        let started = started.lock().unwrap();
+        assert_eq!(started[CAP..], [CAP, CAP + 1, CAP + 2]);
+
+        // 空闲超过`keep_alive`之后，线程退出。
+        let blocking = &mini_tokio.handle.shared.blocking;
+        assert_eq!(blocking.state.lock().unwrap().threads, CAP);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(blocking.state.lock().unwrap().threads, 0);
+    }
+
+    // 两个任务各自记录三步，每一步之后让出执行器。
+    fn spawn_yielding_tasks(mini_tokio: &MiniTokio, log: &Arc<Mutex<Vec<(char, usize)>>>) {
+        for name in ['a', 'b'] {
+            let log = log.clone();
+            mini_tokio.spawn(async move {
+                for step in 0..3 {
+                    log.lock().unwrap().push((name, step));
+                    yield_now().await;
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn yield_now_interleaves_tasks() {
+        let expected = [('a', 0), ('b', 0), ('a', 1), ('b', 1), ('a', 2), ('b', 2)];
+
+        // 由`block_on`驱动。
+        let mini_tokio = MiniTokio::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        spawn_yielding_tasks(&mini_tokio, &log);
+        mini_tokio.block_on(async {
+            while log.lock().unwrap().len() < 6 {
+                delay(Duration::from_millis(1)).await;
+            }
+        });
+        assert_eq!(*log.lock().unwrap(), expected);
+
+        // 由`run`驱动。关闭的任务最后被催生，它等到两个任务都完成之后才关闭执行器。
+        let mini_tokio = MiniTokio::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        spawn_yielding_tasks(&mini_tokio, &log);
+        let handle = mini_tokio.handle();
+        let done = log.clone();
+        mini_tokio.spawn(async move {
+            while done.lock().unwrap().len() < 6 {
+                yield_now().await;
+            }
+            handle.shutdown();
+        });
+        mini_tokio.run();
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn block_in_place_in_single_thread_mode_runs_the_closure() {
+        let mini_tokio = MiniTokio::new();
+
+        let out = mini_tokio.block_on(async {
+            let local = String::from("borrowed");
+            let worker = thread::current().id();
+
+            spawn(async {
+                task::block_in_place(|| {
+                    thread::sleep(Duration::from_millis(1));
+                })
+            })
+            .await
+            .unwrap();
+
+            // 闭包在当前线程上执行，可以借用任务中的局部状态。
+            task::block_in_place(|| {
+                assert_eq!(thread::current().id(), worker);
+                local.len()
+            })
+        });
+
+        assert_eq!(out, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "`block_in_place` must be called from within a MiniTokio runtime")]
+    fn block_in_place_outside_of_a_runtime_panics() {
+        task::block_in_place(|| {});
+    }
+
+    #[test]
+    fn abort_before_the_first_poll() {
+        let mini_tokio = MiniTokio::new();
+        let polled = Arc::new(AtomicBool::new(false));
+
+        // 运行时还没有被驱动，任务还在调度通道中。
+        let handle = mini_tokio.spawn({
+            let polled = polled.clone();
+            async move {
+                polled.store(true, Ordering::SeqCst);
+            }
+        });
+        handle.abort_handle().abort();
+
+        let result = mini_tokio.block_on(handle);
+        assert!(matches!(result, Err(JoinError::Cancelled)));
+        assert!(!polled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn abort_after_the_first_poll() {
+        let mini_tokio = MiniTokio::new();
+        let polls = Arc::new(AtomicUsize::new(0));
+
+        let result = mini_tokio.block_on({
+            let polls = polls.clone();
+            async move {
+                let handle = spawn({
+                    let polls = polls.clone();
+                    async move {
+                        polls.fetch_add(1, Ordering::SeqCst);
+                        std::future::pending::<()>().await;
+                    }
+                });
+
+                // 让任务被轮询一次，它返回`Poll::Pending`并且不会再被唤醒。
+                while polls.load(Ordering::SeqCst) == 0 {
+                    yield_now().await;
+                }
+
+                handle.abort_handle().abort();
+                handle.await
+            }
+        });
+
+        assert!(matches!(result, Err(JoinError::Cancelled)));
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn spawn_blocking_does_not_stall_the_executor() {
+        let mini_tokio = MiniTokio::new();
+
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let (ticks, blocking) = mini_tokio.block_on(async {
+            let blocking = spawn_blocking({
+                let finished = finished.clone();
+                move || {
+                    thread::sleep(Duration::from_millis(200));
+                    finished.store(true, Ordering::SeqCst);
+                    7
+                }
+            });
+
+            // 阻塞的闭包执行期间，执行器线程上的任务继续取得进展。
+            let ticker = spawn({
+                let finished = finished.clone();
+                async move {
+                    let mut ticks = 0;
+                    for _ in 0..5 {
+                        delay(Duration::from_millis(5)).await;
+                        ticks += 1;
+                    }
+                    assert!(!finished.load(Ordering::SeqCst));
+                    ticks
+                }
+            });
+
+            (ticker.await.unwrap(), blocking.await.unwrap())
+        });
+
+        assert_eq!((ticks, blocking), (5, 7));
+    }
+
+    #[test]
+    fn oneshot_orderings() {
+        let mini_tokio = MiniTokio::new();
+
+        // 值在接收者第一次被轮询之前就已经发送。
+        let (tx, rx) = oneshot::channel();
+        tx.send(1).unwrap();
+        assert_eq!(mini_tokio.block_on(rx), Ok(1));
+
+        // 发送者没有发送就被丢弃。
+        let (tx, rx) = oneshot::channel::<i32>();
+        drop(tx);
+        assert!(mini_tokio.block_on(rx).is_err());
+
+        // 接收者已经被丢弃，值被还给发送者。
+        let (tx, rx) = oneshot::channel();
+        drop(rx);
+        assert_eq!(tx.send(3), Err(3));
+
+        // 接收者先挂起，值由另一个任务在之后发送。
+        let (tx, rx) = oneshot::channel();
+        let value = mini_tokio.block_on(async {
+            spawn(async {
+                delay(Duration::from_millis(10)).await;
+                tx.send(4).unwrap();
+            });
+            rx.await
+        });
+        assert_eq!(value, Ok(4));
+    }
+
+    #[test]
+    fn run_until_drops_outstanding_tasks() {
+        for mini_tokio in [MiniTokio::new(), MiniTokio::new_multi_thread(2)] {
+            let handle = mini_tokio.handle();
+            let (background, short) = mini_tokio.run_until(async {
+                // 一个永远不会完成的后台任务，和一个在根未来完成之后才到期的延迟。
+                let background = spawn(std::future::pending::<()>());
+                let short = spawn(delay(Duration::from_millis(20)));
+
+                let sum = spawn(async { 1 + 2 });
+                assert_eq!(sum.await.unwrap(), 3);
+                (background, short)
+            });
+
+            assert!(handle.is_shutdown());
+            assert!(matches!(background.join(), Err(JoinError::Cancelled)));
+            // 延迟到期时唤醒的是一个已经关闭的运行时。
+            assert!(matches!(short.join(), Err(JoinError::Cancelled)));
+        }
+    }
+
+    #[test]
+    fn async_mutex_counter() {
+        let mini_tokio = MiniTokio::new_multi_thread(4);
+        let counter = Arc::new(AsyncMutex::new(0));
+
+        mini_tokio.block_on({
+            let counter = counter.clone();
+            async move {
+                let handles: Vec<_> = (0..20)
+                    .map(|_| {
+                        let counter = counter.clone();
+                        spawn(async move {
+                            for _ in 0..10 {
+                                // 守卫跨越等待点持有，其他任务这期间只能等待。
+                                let mut guard = counter.lock().await;
+                                let value = *guard;
+                                yield_now().await;
+                                *guard = value + 1;
+                            }
+                        })
+                    })
+                    .collect();
+
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            }
+        });
+
+        assert_eq!(*mini_tokio.block_on(counter.lock()), 200);
+    }
+
+    #[test]
+    fn async_mutex_wakes_waiters_in_order() {
+        let mini_tokio = MiniTokio::new();
+        let mutex = Arc::new(AsyncMutex::new(Vec::new()));
+
+        let order = mini_tokio.block_on({
+            let mutex = mutex.clone();
+            async move {
+                let guard = mutex.lock().await;
+
+                let handles: Vec<_> = (0..3)
+                    .map(|n| {
+                        let mutex = mutex.clone();
+                        spawn(async move { mutex.lock().await.push(n) })
+                    })
+                    .collect();
+
+                // 让三个任务都开始等待，然后释放锁。
+                for _ in 0..3 {
+                    yield_now().await;
+                }
+                drop(guard);
+
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+                mutex.lock().await.clone()
+            }
+        });
+
+        assert_eq!(order, [0, 1, 2]);
+    }
+
+    #[test]
+    fn abort_while_the_task_is_being_polled() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let (started_tx, started_rx) = channel::bounded(1);
+        let resumed = Arc::new(AtomicBool::new(false));
+
+        let handle = mini_tokio.spawn({
+            let resumed = resumed.clone();
+            async move {
+                // 在轮询中阻塞一会儿，取消在这期间从另一个线程到达。
+                started_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(50));
+                yield_now().await;
+                resumed.store(true, Ordering::SeqCst);
+            }
+        });
+
+        started_rx.recv().unwrap();
+        handle.abort();
+
+        // 正在进行的那次轮询正常结束，之后任务不再被轮询。
+        let err = handle.join().unwrap_err();
+        assert!(err.is_cancelled());
+        assert!(!resumed.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn abort_after_completion_keeps_the_output() {
+        let mini_tokio = MiniTokio::new();
+
+        let output = mini_tokio.block_on(async {
+            let handle = spawn(async { 5 });
+            while handle.state.lock().unwrap().output.is_none() {
+                yield_now().await;
+            }
+
+            handle.abort();
+            handle.await
+        });
+
+        assert_eq!(output.unwrap(), 5);
+    }
+
+    #[test]
+    fn abort_handle_from_another_thread_while_awaiting() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        let handle = mini_tokio.spawn(std::future::pending::<()>());
+        let abort = handle.abort_handle();
+        assert!(!abort.is_finished());
+
+        // 一个任务在等待句柄，另一个线程上的监督者通过克隆的`AbortHandle`取消任务。
+        let waiter = mini_tokio.spawn(handle);
+        let supervisor = thread::spawn({
+            let abort = abort.clone();
+            move || {
+                thread::sleep(Duration::from_millis(10));
+                abort.abort();
+            }
+        });
+
+        assert!(waiter.join().unwrap().unwrap_err().is_cancelled());
+        assert!(abort.is_finished());
+        supervisor.join().unwrap();
+    }
+
+    #[test]
+    fn abort_handle_does_not_keep_the_output_alive() {
+        let mini_tokio = MiniTokio::new();
+        let output = Arc::new(());
+
+        let handle = mini_tokio.spawn({
+            let output = output.clone();
+            async move { output }
+        });
+        let abort = handle.abort_handle();
+
+        mini_tokio.block_on(async {
+            while !abort.is_finished() {
+                yield_now().await;
+            }
+        });
+
+        // 输出还在句柄中，丢弃句柄之后只剩下这里的一份。
+        assert_eq!(Arc::strong_count(&output), 2);
+        drop(handle);
+        assert_eq!(Arc::strong_count(&output), 1);
+        assert!(abort.is_finished());
+    }
+
+    #[test]
+    fn semaphore_limits_concurrency() {
+        let mini_tokio = MiniTokio::new_multi_thread(4);
+        let semaphore = Arc::new(Semaphore::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        mini_tokio.block_on({
+            let semaphore = semaphore.clone();
+            let running = running.clone();
+            let max_running = max_running.clone();
+            async move {
+                let handles: Vec<_> = (0..5)
+                    .map(|_| {
+                        let semaphore = semaphore.clone();
+                        let running = running.clone();
+                        let max_running = max_running.clone();
+                        spawn(async move {
+                            let _permit = semaphore.acquire().await;
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            max_running.fetch_max(now, Ordering::SeqCst);
+
+                            delay(Duration::from_millis(10)).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                        })
+                    })
+                    .collect();
+
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            }
+        });
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn semaphore_try_acquire() {
+        let semaphore = Semaphore::new(1);
+
+        let permit = semaphore.try_acquire().unwrap();
+        assert!(semaphore.try_acquire().is_none());
+
+        drop(permit);
+        assert!(semaphore.try_acquire().is_some());
+    }
+
+    #[test]
+    fn notify_before_wait() {
+        let mini_tokio = MiniTokio::new();
+        let notify = Notify::new();
+
+        // `notify_one`存储许可，之后的`notified`立即完成。多次通知只存储一个许可。
+        notify.notify_one();
+        notify.notify_one();
+        mini_tokio.block_on(notify.notified());
+        assert!(!notify.state.lock().unwrap().permit);
+
+        // `notify_waiters`不存储许可。
+        notify.notify_waiters();
+        let elapsed = mini_tokio.block_on(timeout(Duration::from_millis(20), notify.notified()));
+        assert!(elapsed.is_err());
+    }
+
+    #[test]
+    fn notify_after_wait() {
+        let mini_tokio = MiniTokio::new();
+        let notify = Arc::new(Notify::new());
+        let woken = Arc::new(AtomicUsize::new(0));
+
+        mini_tokio.block_on({
+            let notify = notify.clone();
+            let woken = woken.clone();
+            async move {
+                let handles: Vec<_> = (0..3)
+                    .map(|_| {
+                        let notify = notify.clone();
+                        let woken = woken.clone();
+                        spawn(async move {
+                            notify.notified().await;
+                            woken.fetch_add(1, Ordering::SeqCst);
+                        })
+                    })
+                    .collect();
+
+                // 等三个任务都开始等待。
+                while notify.state.lock().unwrap().waiters.len() < 3 {
+                    yield_now().await;
+                }
+
+                // `notify_one`只唤醒一个任务。
+                notify.notify_one();
+                while woken.load(Ordering::SeqCst) < 1 {
+                    yield_now().await;
+                }
+                delay(Duration::from_millis(10)).await;
+                assert_eq!(woken.load(Ordering::SeqCst), 1);
+
+                // `notify_waiters`唤醒剩下的所有任务。
+                notify.notify_waiters();
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            }
+        });
+
+        assert_eq!(woken.load(Ordering::SeqCst), 3);
+        assert!(!notify.state.lock().unwrap().permit);
+    }
+
+    #[test]
+    fn join_handle_is_finished_and_try_join() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            let (tx, rx) = oneshot::channel();
+            let mut handle = spawn(async move { rx.await.unwrap() + 1 });
+
+            yield_now().await;
+            assert!(!handle.is_finished());
+            assert!(handle.try_join().is_none());
+
+            tx.send(1).unwrap();
+            while !handle.is_finished() {
+                yield_now().await;
+            }
+
+            assert_eq!(handle.try_join().unwrap().unwrap(), 2);
+            assert!(handle.try_join().is_none());
+        });
+    }
+
+    #[test]
+    fn select_the_shorter_delay() {
+        let mini_tokio = MiniTokio::new();
+
+        let winner = mini_tokio.block_on(select(
+            async {
+                delay(Duration::from_millis(200)).await;
+                "long"
+            },
+            async {
+                delay(Duration::from_millis(10)).await;
+                "short"
+            },
+        ));
+        assert_eq!(winner, Either::Right("short"));
+
+        // 两者都已经就绪时第一个未来获胜。
+        let winner = mini_tokio.block_on(select(async { 1 }, async { 2 }));
+        assert_eq!(winner, Either::Left(1));
+    }
+
+    #[test]
+    fn select_drops_the_loser() {
+        let mini_tokio = MiniTokio::new();
+        let witness = Arc::new(());
+
+        mini_tokio.block_on(select(
+            async {
+                delay(Duration::from_millis(10)).await;
+            },
+            {
+                let witness = witness.clone();
+                async move {
+                    delay(Duration::from_secs(60)).await;
+                    drop(witness);
+                }
+            },
+        ));
+
+        // 输掉的未来在`select`返回时被丢弃，它持有的引用也随之释放。
+        assert_eq!(Arc::strong_count(&witness), 1);
+    }
+
+    #[test]
+    fn panicking_task_does_not_take_down_the_executor() {
+        for mini_tokio in [MiniTokio::new(), MiniTokio::new_multi_thread(2)] {
+            let (panicked, well_behaved) = mini_tokio.block_on(async {
+                let panicked = spawn(async {
+                    yield_now().await;
+                    panic!("boom");
+                });
+                let well_behaved = spawn(async {
+                    for _ in 0..3 {
+                        yield_now().await;
+                    }
+                    5
+                });
+
+                (panicked.await, well_behaved.await)
+            });
+
+            let err = panicked.unwrap_err();
+            assert!(err.is_panic());
+            assert_eq!(*err.into_panic().downcast::<&str>().unwrap(), "boom");
+            assert_eq!(well_behaved.unwrap(), 5);
+
+            // 执行器线程仍然在执行任务。
+            assert_eq!(
+                mini_tokio.block_on(mini_tokio.spawn(async { 6 })).unwrap(),
+                6
+            );
+        }
+    }
+
+    #[test]
+    fn join_two_delays_concurrently() {
+        let mini_tokio = MiniTokio::new();
+        let start = Instant::now();
+
+        let outputs = mini_tokio.block_on(join(
+            async {
+                delay(Duration::from_millis(100)).await;
+                1
+            },
+            async {
+                delay(Duration::from_millis(60)).await;
+                2
+            },
+        ));
+
+        let elapsed = start.elapsed();
+        assert_eq!(outputs, (1, 2));
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(160), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn unhandled_panic_shuts_down_the_runtime() {
+        for workers in [None, Some(2)] {
+            let mut builder = Builder::new();
+            builder.unhandled_panic(UnhandledPanic::ShutdownRuntime);
+            if let Some(n) = workers {
+                builder.worker_threads(n);
+            }
+            let mini_tokio = builder.build().unwrap();
+
+            let pending = mini_tokio.spawn(std::future::pending::<()>());
+            // 句柄被丢弃，任务脱离。
+            drop(mini_tokio.spawn(async {
+                yield_now().await;
+                panic!("boom");
+            }));
+
+            let payload = panic::catch_unwind(AssertUnwindSafe(|| mini_tokio.run())).unwrap_err();
+            assert_eq!(*payload.downcast::<&str>().unwrap(), "boom");
+            assert!(mini_tokio.handle.is_shutdown());
+            assert!(pending.join().unwrap_err().is_cancelled());
+        }
+    }
+
+    #[test]
+    fn unhandled_panic_in_block_on() {
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            .unhandled_panic(UnhandledPanic::ShutdownRuntime)
+            .build()
+            .unwrap();
+
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            mini_tokio.block_on(async {
+                drop(spawn(async { panic!("boom") }));
+                std::future::pending::<()>().await;
+            })
+        }))
+        .unwrap_err();
+        assert_eq!(*payload.downcast::<&str>().unwrap(), "boom");
+    }
+
+    #[test]
+    fn handled_and_ignored_panics_keep_the_runtime_running() {
+        let mini_tokio = Builder::new()
+            .unhandled_panic(UnhandledPanic::ShutdownRuntime)
+            .build()
+            .unwrap();
+
+        // 还有人持有句柄的任务的panic交给句柄，不会关闭运行时。
+        let handled = mini_tokio.block_on(mini_tokio.spawn(async { panic!("boom") }));
+        assert!(handled.unwrap_err().is_panic());
+        assert!(!mini_tokio.handle.is_shutdown());
+
+        // 默认的策略忽略脱离的任务的panic。
+        let mini_tokio = MiniTokio::new();
+        let output = mini_tokio.block_on(async {
+            drop(spawn(async { panic!("boom") }));
+            spawn(async { 5 }).await.unwrap()
+        });
+        assert_eq!(output, 5);
+        assert!(!mini_tokio.handle.is_shutdown());
+    }
+
+    #[test]
+    fn futures_unordered_yields_in_completion_order() {
+        let mini_tokio = MiniTokio::new();
+
+        let order = mini_tokio.block_on(async {
+            let mut set = FuturesUnordered::new();
+            for n in [7u64, 2, 9, 0, 5, 3, 8, 1, 6, 4] {
+                set.push(async move {
+                    delay(Duration::from_millis(10 + n * 10)).await;
+                    n
+                });
+            }
+            assert_eq!(set.len(), 10);
+
+            let mut order = Vec::new();
+            while let Some(n) = set.next().await {
+                order.push(n);
+            }
+            assert!(set.is_empty());
+            order
+        });
+
+        assert_eq!(order, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn futures_unordered_polls_only_woken_futures() {
+        let mini_tokio = MiniTokio::new();
+        let polls: Vec<_> = (0..3).map(|_| Arc::new(AtomicUsize::new(0))).collect();
+
+        mini_tokio.block_on(async {
+            let mut set = FuturesUnordered::new();
+            let mut senders = Vec::new();
+            for polls in &polls {
+                let (tx, rx) = oneshot::channel::<()>();
+                senders.push(tx);
+                let polls = polls.clone();
+                set.push(Box::pin(async move {
+                    let mut rx = rx;
+                    poll_fn(|cx| {
+                        polls.fetch_add(1, Ordering::SeqCst);
+                        Pin::new(&mut rx).poll(cx)
+                    })
+                    .await
+                }));
+            }
+
+            // 第一次轮询所有的未来，它们都还没有完成。
+            assert_eq!(select(set.next(), async {}).await, Either::Right(()));
+
+            // 只有第二个未来被唤醒，其他的不会被再次轮询。
+            senders.remove(1).send(()).unwrap();
+            assert!(set.next().await.unwrap().is_ok());
+        });
+
+        let polls: Vec<_> = polls.iter().map(|n| n.load(Ordering::SeqCst)).collect();
+        assert_eq!(polls, [1, 2, 1]);
+    }
+
+    #[test]
+    fn yielding_tasks_are_not_lost_when_the_queue_is_full() {
+        // 调度通道只能容纳4个任务，让出执行器的唤醒会进入溢出列表。
+        let mini_tokio = Builder::new().max_queue_depth(4).build().unwrap();
+
+        let total = mini_tokio.block_on(async {
+            let handles: Vec<_> = (0..4u64)
+                .map(|n| {
+                    spawn(async move {
+                        for _ in 0..10 {
+                            task::yield_now().await;
+                        }
+                        n
+                    })
+                })
+                .collect();
+
+            let mut total = 0;
+            for handle in handles {
+                total += handle.await.unwrap();
+            }
+            total
+        });
+
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn panicking_task_does_not_stop_run() {
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+
+        let panicked = mini_tokio.spawn(async { panic!("boom") });
+        let finished = mini_tokio.spawn(async move {
+            yield_now().await;
+            handle.shutdown();
+            5
+        });
+
+        mini_tokio.run();
+        assert!(panicked.join().unwrap_err().is_panic());
+        assert_eq!(finished.join().unwrap(), 5);
+    }
+
+    #[test]
+    fn greedy_task_does_not_starve_others() {
+        let mini_tokio = MiniTokio::new();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let iterations = mini_tokio.block_on({
+            let stop = stop.clone();
+            async move {
+                // 截止时间已经过去的延迟总是就绪，没有预算的话这个循环永远不会让出执行器。
+                let greedy = spawn({
+                    let stop = stop.clone();
+                    async move {
+                        let mut iterations = 0;
+                        while !stop.load(Ordering::SeqCst) {
+                            delay(Duration::from_millis(0)).await;
+                            iterations += 1;
+                        }
+                        iterations
+                    }
+                });
+                spawn(async move { stop.store(true, Ordering::SeqCst) });
+
+                greedy.await.unwrap()
+            }
+        });
+
+        // 预算在第一次轮询中用完，第二次轮询完成被打断的那次延迟之后就看到了停止的标记。
+        assert_eq!(iterations, BUDGET_PER_POLL + 1);
+    }
+
+    #[test]
+    fn unhandled_panic_observed_when_the_handle_is_dropped() {
+        let mini_tokio = Builder::new()
+            .unhandled_panic(UnhandledPanic::ShutdownRuntime)
+            .build()
+            .unwrap();
+
+        // 任务在句柄被丢弃之前就panic了，没有人取走载荷。
+        let handle = mini_tokio.spawn(async { panic!("boom") });
+        let abort = handle.abort_handle();
+        mini_tokio.block_on(async {
+            while !abort.is_finished() {
+                yield_now().await;
+            }
+        });
+        assert!(!mini_tokio.handle.is_shutdown());
+
+        drop(handle);
+        assert!(mini_tokio.handle.is_shutdown());
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| mini_tokio.run())).unwrap_err();
+        assert_eq!(*payload.downcast::<&str>().unwrap(), "boom");
+    }
+
+    #[test]
+    fn on_task_panic_hook_fires_for_detached_tasks() {
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let mini_tokio = Builder::new()
+            .on_task_panic({
+                let panics = panics.clone();
+                move |info| {
+                    let message = info.message().unwrap_or("").to_string();
+                    panics.lock().unwrap().push(message);
+                }
+            })
+            .build()
+            .unwrap();
+
+        mini_tokio.block_on(async {
+            drop(spawn(async { panic!("boom") }));
+            let n = 3;
+            drop(spawn(async move { panic!("boom {}", n) }));
+
+            // 正常完成的任务不会调用回调。
+            spawn(async {}).await.unwrap();
+            while panics.lock().unwrap().len() < 2 {
+                yield_now().await;
+            }
+        });
+
+        assert_eq!(*panics.lock().unwrap(), ["boom", "boom 3"]);
+    }
+
+    #[test]
+    fn redundant_wakes_do_not_grow_the_queue() {
+        let mini_tokio = MiniTokio::new();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let (waker_tx, waker_rx) = channel::unbounded();
+
+        let handle = mini_tokio.spawn(CountPolls {
+            polls: polls.clone(),
+            waker: waker_tx,
+            done: done.clone(),
+        });
+
+        // 第一次轮询之后任务空闲，调度通道为空。
+        mini_tokio.block_on(async {
+            while polls.load(Ordering::SeqCst) == 0 {
+                yield_now().await;
+            }
+        });
+        let waker = waker_rx.try_recv().unwrap();
+        assert!(mini_tokio.scheduled.is_empty());
+
+        // 两个资源先后就绪：任务只排队一次。
+        waker.wake_by_ref();
+        waker.wake_by_ref();
+        assert_eq!(mini_tokio.scheduled.len(), 1);
+
+        done.store(true, Ordering::SeqCst);
+        mini_tokio.block_on(handle).unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn local_set_runs_tasks_that_are_not_send() {
+        let mini_tokio = MiniTokio::new();
+        let local = task::LocalSet::new();
+        let shared = Rc::new(Cell::new(0));
+
+        let value = mini_tokio.block_on(local.run_until({
+            let shared = shared.clone();
+            async move {
+                let task = task::spawn_local({
+                    let shared = shared.clone();
+                    async move {
+                        // 唤醒来自定时器线程，任务仍然在驱动`LocalSet`的线程上被轮询。
+                        delay(Duration::from_millis(10)).await;
+                        shared.set(shared.get() + 1);
+                        shared.get()
+                    }
+                });
+
+                task.await.unwrap()
+            }
+        }));
+
+        assert_eq!(value, 1);
+        assert_eq!(shared.get(), 1);
+    }
+
+    #[test]
+    fn stale_waker_after_completion() {
+        let mini_tokio = MiniTokio::new();
+        let witness = Arc::new(());
+        let (waker_tx, waker_rx) = channel::unbounded();
+
+        let handle = mini_tokio.spawn({
+            let witness = witness.clone();
+            let mut polled = false;
+            poll_fn(move |cx| {
+                let _witness = &witness;
+                // 第一次轮询时把唤醒者交出去并立即唤醒自己，第二次轮询完成。
+                if !polled {
+                    polled = true;
+                    waker_tx.send(cx.waker().clone()).unwrap();
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Poll::Ready(())
+            })
+        });
+        mini_tokio.block_on(handle).unwrap();
+
+        // 唤醒者还让任务存活着，但未来已经被丢弃，它捕获的资源已经释放。
+        let waker = waker_rx.recv().unwrap();
+        assert_eq!(Arc::strong_count(&witness), 1);
+
+        // 迟到的唤醒不会让已经完成的未来再被轮询。
+        waker.wake_by_ref();
+        waker.wake();
+        assert!(mini_tokio.scheduled.is_empty());
+    }
+
+    task_local! {
+        static SCOPE_NAME: &'static str;
+    }
+
+    #[test]
+    fn task_local_values_are_scoped_per_task() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        let names = mini_tokio.block_on(async {
+            let handles: Vec<_> = ["a", "b"]
+                .iter()
+                .map(|&name| {
+                    spawn(SCOPE_NAME.scope(name, async {
+                        let mut seen = Vec::new();
+                        for _ in 0..5 {
+                            // 两个任务在同一个线程上交错执行时，各自只看到自己的值。
+                            yield_now().await;
+                            seen.push(SCOPE_NAME.with(|name| *name));
+                        }
+                        seen
+                    }))
+                })
+                .collect();
+
+            let mut names = Vec::new();
+            for handle in handles {
+                names.push(handle.await.unwrap());
+            }
+            names
+        });
+
+        assert_eq!(names, [["a"; 5], ["b"; 5]]);
+        assert_eq!(SCOPE_NAME.try_with(|name| *name), None);
+    }
+
+    #[test]
+    fn task_local_is_hidden_from_tasks_polled_inside_its_scope() {
+        let outer = MiniTokio::new();
+
+        let (spawned, nested) = outer.block_on(SCOPE_NAME.scope("outer", async {
+            // 在`with`的闭包中驱动另一个运行时：那里的任务在这次轮询中被轮询，
+            // 根未来再次进入同一个变量的`scope`。
+            SCOPE_NAME.with(|_| {
+                MiniTokio::new().block_on(async {
+                    let spawned = spawn(async { SCOPE_NAME.try_with(|name| *name) })
+                        .await
+                        .unwrap();
+                    let nested = SCOPE_NAME
+                        .scope("nested", async { SCOPE_NAME.with(|name| *name) })
+                        .await;
+                    (spawned, nested)
+                })
+            })
+        }));
+
+        assert_eq!(spawned, None);
+        assert_eq!(nested, "nested");
+    }
+
+    #[test]
+    fn nested_task_local_scopes() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(SCOPE_NAME.scope("outer", async {
+            SCOPE_NAME
+                .scope("inner", async {
+                    yield_now().await;
+                    assert_eq!(SCOPE_NAME.with(|name| *name), "inner");
+                })
+                .await;
+
+            assert_eq!(SCOPE_NAME.with(|name| *name), "outer");
+        }));
+
+        assert_eq!(SCOPE_NAME.try_with(|name| *name), None);
+    }
+
+    #[test]
+    fn try_spawn_reports_why_it_failed() {
+        assert_eq!(try_spawn(async {}).err(), Some(SpawnError::NoRuntime));
+        let spawned = panic::catch_unwind(|| spawn(async {}));
+        assert!(spawned.is_err());
+
+        let mini_tokio = MiniTokio::new();
+        let output = mini_tokio.block_on(async { try_spawn(async { 1 }).unwrap().await });
+        assert_eq!(output.unwrap(), 1);
+
+        let handle = mini_tokio.handle();
+        let refused = mini_tokio.block_on(async move {
+            handle.shutdown();
+            try_spawn(async {}).err()
+        });
+        assert_eq!(refused, Some(SpawnError::Shutdown));
+    }
+
+    #[test]
+    fn a_hundred_wakes_before_running_poll_the_task_once() {
+        let mini_tokio = MiniTokio::new();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let (waker_tx, waker_rx) = channel::unbounded();
+
+        let handle = mini_tokio.spawn(CountPolls {
+            polls: polls.clone(),
+            waker: waker_tx,
+            done: done.clone(),
+        });
+        mini_tokio.block_on(async {
+            while polls.load(Ordering::SeqCst) == 0 {
+                yield_now().await;
+            }
+        });
+        let waker = waker_rx.try_recv().unwrap();
+
+        done.store(true, Ordering::SeqCst);
+        for _ in 0..100 {
+            waker.wake_by_ref();
+        }
+        assert_eq!(mini_tokio.scheduled.len(), 1);
+
+        mini_tokio.block_on(handle).unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn dropping_the_runtime_drops_tasks_kept_alive_by_wakers() {
+        struct SetOnDrop(Arc<AtomicBool>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let mini_tokio = MiniTokio::new();
+        let sleeping = Arc::new(AtomicBool::new(false));
+        let parked = Arc::new(AtomicBool::new(false));
+        let (waker_tx, waker_rx) = channel::unbounded();
+
+        // 一个任务的唤醒者在定时器线程中，另一个的唤醒者在测试手里，另一个从未被轮询过。
+        let guard = SetOnDrop(sleeping.clone());
+        let sleeper = mini_tokio.spawn(async move {
+            let _guard = guard;
+            delay(Duration::from_secs(3600)).await;
+        });
+        let guard = SetOnDrop(parked.clone());
+        let _parked = mini_tokio.spawn(async move {
+            let _guard = guard;
+            CountPolls {
+                polls: Arc::new(AtomicUsize::new(0)),
+                waker: waker_tx,
+                done: Arc::new(AtomicBool::new(false)),
+            }
+            .await;
+        });
+        mini_tokio.block_on(async {
+            while waker_rx.is_empty() {
+                yield_now().await;
+            }
+        });
+        let queued = Arc::new(AtomicBool::new(false));
+        let guard = SetOnDrop(queued.clone());
+        let _queued = mini_tokio.spawn(async move {
+            let _guard = guard;
+        });
+        let _waker = waker_rx.try_recv().unwrap();
+        assert!(!sleeping.load(Ordering::SeqCst) && !parked.load(Ordering::SeqCst));
+
+        drop(mini_tokio);
+        assert!(sleeping.load(Ordering::SeqCst));
+        assert!(parked.load(Ordering::SeqCst));
+        assert!(queued.load(Ordering::SeqCst));
+        assert!(sleeper.join().unwrap_err().is_cancelled());
+    }
+
+    #[test]
+    fn builder_with_every_option() {
+        let panics = Arc::new(AtomicUsize::new(0));
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            .thread_name("configured-worker")
+            .max_queue_depth(16)
+            .on_task_panic({
+                let panics = panics.clone();
+                move |_| {
+                    panics.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build()
+            .unwrap();
+
+        let name = mini_tokio
+            .spawn(async { thread::current().name().map(str::to_string) })
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("configured-worker"));
+
+        let panicked = mini_tokio.spawn(async { panic!("boom") }).join();
+        assert!(panicked.unwrap_err().is_panic());
+        assert_eq!(panics.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn a_million_spawns_into_a_bounded_queue() {
+        const TASKS: usize = 1_000_000;
+        const CAPACITY: usize = 256;
+
+        let mini_tokio = Builder::new()
+            .max_queue_depth(CAPACITY)
+            .spawn_backpressure(SpawnBackpressure::YieldAndRetry)
+            .build()
+            .unwrap();
+        let shared = mini_tokio.handle().shared;
+        let completed = Arc::new(AtomicUsize::new(0));
+
+        mini_tokio.block_on(async {
+            // 在一个任务中催生：让出执行器的任务排在调度通道中所有任务的后面，
+            // 根未来则可能在执行任何任务之前就被再次轮询。
+            let spawner = spawn({
+                let completed = completed.clone();
+                async move {
+                    for i in 0..TASKS {
+                        let completed = completed.clone();
+                        spawn(async move {
+                            completed.fetch_add(1, Ordering::Relaxed);
+                        });
+
+                        // 调度通道满了时催生用完了预算，这里让出执行器，先执行排在前面的任务。
+                        // 除了这个任务自己，还没有被执行的任务都在调度通道中，
+                        // 溢出列表中最多只有让出之前催生的那一个。
+                        poll_fn(poll_budget).await;
+                        if i % 4096 == 0 {
+                            assert!(shared.tasks.lock().unwrap().len() <= CAPACITY + 2);
+                        }
+                    }
+                }
+            });
+            spawner.await.unwrap();
+
+            while completed.load(Ordering::Relaxed) < TASKS {
+                yield_now().await;
+            }
+        });
+
+        // 在运行时之外催生任务时没有执行器可以让出，调度通道已满时照常报告错误。
+        let handle = mini_tokio.handle();
+        for _ in 0..CAPACITY {
+            handle.spawn(async {}).unwrap();
+        }
+        assert_eq!(handle.spawn(async {}).err(), Some(SpawnError::QueueFull));
+    }
+
+    #[test]
+    fn tasks_spawned_into_a_full_queue_do_not_see_the_spawners_task_local() {
+        const TASKS: usize = 32;
+
+        let mini_tokio = Builder::new()
+            .max_queue_depth(4)
+            .spawn_backpressure(SpawnBackpressure::YieldAndRetry)
+            .build()
+            .unwrap();
+
+        let seen = mini_tokio.block_on(SCOPE_NAME.scope("spawner", async {
+            let joins: Vec<_> = (0..TASKS)
+                .map(|_| spawn(async { SCOPE_NAME.try_with(|name| *name) }))
+                .collect();
+
+            let mut seen = Vec::new();
+            for join in joins {
+                seen.push(join.await.unwrap());
+            }
+            seen
+        }));
+
+        // 调度通道满了之后的催生不会在催生者的作用域中执行排在前面的任务。
+        assert_eq!(seen, [None; TASKS]);
+    }
+
+    #[test]
+    fn spawn_outside_a_runtime_explains_the_panic() {
+        let payload = match panic::catch_unwind(|| spawn(async {})) {
+            Ok(_) => panic!("spawn outside a runtime did not panic"),
+            Err(payload) => payload,
+        };
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert_eq!(
+            message,
+            "failed to spawn a task: must be called from the context of a mini-tokio runtime"
+        );
+    }
+
+    // 每次被轮询时记录自己的名字并保存唤醒者，然后唤醒`wake`中的唤醒者（如果有的话）。
+    // 用来观察工作线程按什么顺序执行被唤醒的任务。
+    struct Relay {
+        name: char,
+        log: Arc<Mutex<Vec<char>>>,
+        own: Arc<Mutex<Option<Waker>>>,
+        wake: Vec<Arc<Mutex<Option<Waker>>>>,
+    }
+
+    impl Future for Relay {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            let mut log = self.log.lock().unwrap();
+            if log.len() >= 64 {
+                return Poll::Ready(());
+            }
+            log.push(self.name);
+            drop(log);
+
+            *self.own.lock().unwrap() = Some(cx.waker().clone());
+            for waker in &self.wake {
+                if let Some(waker) = waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            }
+
+            Poll::Pending
+        }
+    }
+
+    fn wait_for(log: &Arc<Mutex<Vec<char>>>, len: usize) -> Vec<char> {
+        while log.lock().unwrap().len() < len {
+            thread::sleep(Duration::from_millis(1));
+        }
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    #[test]
+    fn the_most_recently_woken_task_runs_next() {
+        // 只用工作线程执行任务：测试线程不调用`block_on`，催生的任务都由这个工作线程从调度通道中取走。
+        let mini_tokio = MiniTokio::new_multi_thread(1);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let wakers: Vec<_> = (0..3).map(|_| Arc::new(Mutex::new(None))).collect();
+
+        for (name, own) in ['1', '2', '3'].iter().zip(&wakers) {
+            mini_tokio.spawn(Relay {
+                name: *name,
+                log: log.clone(),
+                own: own.clone(),
+                wake: Vec::new(),
+            });
+            wait_for(&log, 1);
+        }
+
+        // 同一次轮询中依次唤醒三个任务：最后一个被唤醒的任务最先执行，其余的按唤醒的顺序执行。
+        mini_tokio.spawn(Relay {
+            name: 'w',
+            log: log.clone(),
+            own: Arc::new(Mutex::new(None)),
+            wake: wakers,
+        });
+        assert_eq!(wait_for(&log, 4), ['w', '3', '1', '2']);
+    }
+
+    #[test]
+    fn ping_pong_through_the_lifo_slot_does_not_starve_the_local_queue() {
+        let mini_tokio = MiniTokio::new_multi_thread(1);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (a, b, c) = (
+            Arc::new(Mutex::new(None)),
+            Arc::new(Mutex::new(None)),
+            Arc::new(Mutex::new(None)),
+        );
+
+        mini_tokio.spawn(Relay {
+            name: 'c',
+            log: log.clone(),
+            own: c.clone(),
+            wake: Vec::new(),
+        });
+        wait_for(&log, 1);
+        mini_tokio.spawn(Relay {
+            name: 'b',
+            log: log.clone(),
+            own: b.clone(),
+            wake: vec![a.clone()],
+        });
+        wait_for(&log, 1);
+
+        // `a`先唤醒`c`再唤醒`b`：`b`占据LIFO槽，`c`进入本地队列。之后`a`和`b`互相唤醒，
+        // 连续使用槽的次数达到上限时`c`得到执行。
+        mini_tokio.spawn(Relay {
+            name: 'a',
+            log: log.clone(),
+            own: a,
+            wake: vec![c, b],
+        });
+
+        let mut expected = vec!['a'];
+        expected.extend(['b', 'a'].iter().cycle().take(LIFO_SLOT_LIMIT));
+        expected.push('c');
+        let log = wait_for(&log, expected.len());
+        assert_eq!(log[..expected.len()], expected[..]);
+    }
+
+    #[test]
+    fn handle_spawns_from_another_thread() {
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+
+        let join = thread::spawn(move || handle.spawn(async { 7 }).unwrap())
+            .join()
+            .unwrap();
+
+        assert_eq!(mini_tokio.block_on(join).unwrap(), 7);
+    }
+
+    #[test]
+    fn priorities_are_weighted_without_starving_low() {
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let tasks = [
+            (Priority::Low, 'l'),
+            (Priority::Normal, 'n'),
+            (Priority::High, 'h'),
+        ];
+        let polls = 2 * tasks.len() * PRIORITY_CYCLE.len();
+
+        // 在`run`之前催生所有任务，`run`开始时每个队列中都有7个任务。
+        // 每个任务在让出前后各记录一次，被重新唤醒时它应该回到自己的优先级的队列。
+        for &(priority, name) in tasks.iter() {
+            for _ in 0..PRIORITY_CYCLE.len() {
+                let log = log.clone();
+                let shutdown = handle.clone();
+                let spawned = handle.spawn_with_priority(
+                    async move {
+                        log.lock().unwrap().push(name);
+                        yield_now().await;
+                        let mut log = log.lock().unwrap();
+                        log.push(name);
+                        if log.len() == polls {
+                            shutdown.shutdown();
+                        }
+                    },
+                    priority,
+                );
+                assert!(spawned.is_ok());
+            }
+        }
+        mini_tokio.run();
+
+        // 前14次轮询中有8次是高优先级的：第8次只能是一个被重新唤醒的高优先级任务。
+        let log = log.lock().unwrap();
+        let first = &log[..2 * PRIORITY_CYCLE.len()];
+        let count = |name| first.iter().filter(|&&polled| polled == name).count();
+        assert_eq!((count('h'), count('n'), count('l')), (8, 4, 2));
+        assert_eq!(log.len(), polls);
+    }
+
+    #[test]
+    fn metrics_count_spawned_and_completed_tasks() {
+        const TASKS: u64 = 20;
+
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+        let joins: Vec<_> = (0..TASKS).map(|_| mini_tokio.spawn(yield_now())).collect();
+        mini_tokio.spawn(async move {
+            for join in joins {
+                join.await.unwrap();
+            }
+            handle.shutdown();
+        });
+
+        let queued = mini_tokio.metrics();
+        assert_eq!(queued.tasks_spawned, TASKS + 1);
+        assert_eq!(queued.current_queue_depth, TASKS as usize + 1);
+        assert_eq!(queued.tasks_completed, 0);
+
+        mini_tokio.run();
+
+        let metrics = mini_tokio.metrics();
+        assert_eq!(metrics.tasks_spawned, metrics.tasks_completed);
+        assert_eq!(metrics.current_queue_depth, 0);
+        // 每个任务让出一次，至少被轮询两次。
+        assert!(metrics.total_polls > 2 * TASKS);
+    }
+
+    #[test]
+    fn a_self_waking_task_does_not_starve_the_scheduled_queue() {
+        const INTERVAL: usize = 8;
+
+        // 每次被轮询时都唤醒自己，这个任务一直占据工作线程的LIFO槽和本地队列。
+        struct Spin {
+            polls: Arc<AtomicUsize>,
+            done: Arc<AtomicBool>,
+        }
+
+        impl Future for Spin {
+            type Output = ();
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if self.done.load(Ordering::SeqCst) {
+                    return Poll::Ready(());
+                }
+
+                self.polls.fetch_add(1, Ordering::SeqCst);
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        let mini_tokio = Builder::new()
+            .worker_threads(1)
+            .global_queue_interval(INTERVAL)
+            .build()
+            .unwrap();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let spin = mini_tokio.spawn(Spin {
+            polls: polls.clone(),
+            done: done.clone(),
+        });
+        while polls.load(Ordering::SeqCst) < 100 {
+            thread::yield_now();
+        }
+
+        let handle = mini_tokio.handle();
+        let (spawned_at, ran_at) = thread::spawn({
+            let polls = polls.clone();
+            let done = done.clone();
+            move || {
+                let spawned_at = polls.load(Ordering::SeqCst);
+                let ran_at = handle
+                    .spawn(async move {
+                        done.store(true, Ordering::SeqCst);
+                        polls.load(Ordering::SeqCst)
+                    })
+                    .unwrap()
+                    .join()
+                    .unwrap();
+                (spawned_at, ran_at)
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert!(ran_at - spawned_at <= 2 * INTERVAL);
+        mini_tokio.block_on(spin).unwrap();
+    }
+
+    #[test]
+    fn sleep_until_fixed_deadlines_in_order() {
+        let mini_tokio = MiniTokio::new();
+        let t0 = Instant::now();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // 以相反的顺序催生，完成的顺序只取决于截止时间。
+        let joins: Vec<_> = [150, 100, 50]
+            .iter()
+            .map(|&ms| {
+                let log = log.clone();
+                mini_tokio.spawn(async move {
+                    sleep_until(t0 + Duration::from_millis(ms)).await;
+                    log.lock().unwrap().push((ms, t0.elapsed()));
+                })
+            })
+            .collect();
+        mini_tokio.block_on(async {
+            for join in joins {
+                join.await.unwrap();
+            }
+        });
+
+        let log = log.lock().unwrap();
+        assert_eq!(
+            log.iter().map(|&(ms, _)| ms).collect::<Vec<_>>(),
+            [50, 100, 150]
+        );
+        for &(ms, elapsed) in log.iter() {
+            assert!(elapsed >= Duration::from_millis(ms));
+        }
+
+        // 已经过去的截止时间立即完成。
+        mini_tokio.block_on(sleep_until(t0));
+    }
+
+    #[test]
+    fn metrics_track_alive_tasks_and_deduplicated_wakeups() {
+        let mini_tokio = MiniTokio::new();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let (waker_tx, waker_rx) = channel::unbounded();
+
+        let handle = mini_tokio.spawn(CountPolls {
+            polls: polls.clone(),
+            waker: waker_tx,
+            done: done.clone(),
+        });
+        mini_tokio.block_on(async {
+            while polls.load(Ordering::SeqCst) == 0 {
+                yield_now().await;
+            }
+        });
+        let waker = waker_rx.try_recv().unwrap();
+        assert_eq!(mini_tokio.metrics().tasks_alive, 1);
+
+        // 第一次唤醒把任务排进队列，之后的两次被合并。
+        let before = mini_tokio.metrics().wakeups_deduplicated;
+        waker.wake_by_ref();
+        waker.wake_by_ref();
+        waker.wake_by_ref();
+        assert_eq!(mini_tokio.metrics().wakeups_deduplicated - before, 2);
+
+        done.store(true, Ordering::SeqCst);
+        mini_tokio.block_on(handle).unwrap();
+        assert_eq!(mini_tokio.metrics().tasks_alive, 0);
+
+        // 被取消的任务和运行时关闭时被丢弃的任务也不再存活。
+        let aborted = mini_tokio.spawn(std::future::pending::<()>());
+        // 第二个任务的唤醒者留在`_wakers`中，任务本身只有在运行时被丢弃时才会被关闭。
+        let (waker_tx, _wakers) = channel::unbounded();
+        let _dropped = mini_tokio.spawn(CountPolls {
+            polls: polls.clone(),
+            waker: waker_tx,
+            done: Arc::new(AtomicBool::new(false)),
+        });
+        mini_tokio.block_on(yield_now());
+        aborted.abort();
+        assert!(mini_tokio.block_on(aborted).unwrap_err().is_cancelled());
+        assert_eq!(mini_tokio.metrics().tasks_alive, 1);
+        let handle = mini_tokio.handle();
+        drop(mini_tokio);
+        assert_eq!(handle.metrics().tasks_alive, 0);
+    }
+
+    #[test]
+    fn sleep_reset_moves_the_deadline() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            // 开始等待，在截止时间之前把它往后推：旧的截止时间不再唤醒任务，也不会让它完成。
+            let start = Instant::now();
+            let mut sleeping = sleep(Duration::from_millis(20));
+            let first = select(&mut sleeping, delay(Duration::from_millis(5))).await;
+            assert_eq!(first, Either::Right(()));
+            sleeping.reset(start + Duration::from_millis(60));
+            (&mut sleeping).await;
+            assert!(start.elapsed() >= Duration::from_millis(60));
+
+            // 已经完成的`Sleep`被重置后重新变为未完成。
+            assert!(sleeping.is_elapsed());
+            let deadline = Instant::now() + Duration::from_millis(20);
+            sleeping.reset(deadline);
+            assert!(!sleeping.is_elapsed());
+            assert_eq!(sleeping.deadline(), deadline);
+            (&mut sleeping).await;
+            assert!(Instant::now() >= deadline);
+
+            // 正在等待的`Sleep`被提前时，任务在新的截止时间被唤醒。
+            let mut sleeping = sleep(Duration::from_secs(60));
+            let first = select(&mut sleeping, delay(Duration::from_millis(5))).await;
+            assert_eq!(first, Either::Right(()));
+            sleeping.reset(Instant::now() + Duration::from_millis(10));
+            assert!(timeout(Duration::from_secs(5), sleeping).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn per_worker_metrics() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        let joins: Vec<_> = (0..10)
+            .map(|_| {
+                mini_tokio.spawn(async {
+                    thread::sleep(Duration::from_millis(2));
+                    yield_now().await;
+                })
+            })
+            .collect();
+        for join in joins {
+            join.join().unwrap();
+        }
+
+        // 等两个工作线程都空闲下来：它们在阻塞等待之前都尝试过窃取。
+        let metrics = loop {
+            let metrics = mini_tokio.metrics();
+            if (0..2).all(|idx| metrics.worker_park_count(idx) > 0) {
+                break metrics;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        assert_eq!(metrics.worker_count(), 2);
+        let polls: u64 = (0..2).map(|idx| metrics.worker_poll_count(idx)).sum();
+        assert_eq!(polls, metrics.total_polls);
+        assert!(polls >= 20);
+        let busy: Duration = (0..2).map(|idx| metrics.worker_busy_duration(idx)).sum();
+        assert!(busy >= Duration::from_millis(20));
+        for idx in 0..2 {
+            assert!(metrics.worker_steal_failure_count(idx) > 0);
+        }
+
+        assert_eq!(MiniTokio::new().metrics().worker_count(), 1);
+    }
+
+    #[test]
+    fn delay_queue_yields_in_deadline_order() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            let start = Instant::now();
+            let mut queue = DelayQueue::new();
+            queue.insert("a", Duration::from_millis(30));
+            queue.insert("b", Duration::from_millis(10));
+            let c = queue.insert("c", Duration::from_millis(20));
+            queue.insert("d", Duration::from_millis(40));
+
+            assert_eq!(queue.remove(c), Some("c"));
+            assert_eq!(queue.remove(c), None);
+            assert_eq!(queue.len(), 3);
+
+            // 被移除的位置被复用，旧的键不会指向新的元素。
+            let e = queue.insert("e", Duration::from_millis(50));
+            assert_ne!(e, c);
+            assert_eq!(queue.remove(c), None);
+
+            let mut expired = Vec::new();
+            while let Some(item) = queue.poll_expired().await {
+                expired.push((item, start.elapsed()));
+            }
+
+            let items: Vec<_> = expired.iter().map(|&(item, _)| item).collect();
+            assert_eq!(items, ["b", "a", "d", "e"]);
+            for (&(_, elapsed), ms) in expired.iter().zip([10, 30, 40, 50].iter()) {
+                assert!(elapsed >= Duration::from_millis(*ms));
+            }
+            assert!(queue.is_empty());
+        });
+    }
+
+    #[test]
+    fn max_tasks_caps_alive_tasks() {
+        let mini_tokio = Builder::new().max_tasks(2).build().unwrap();
+        let handle = mini_tokio.handle();
+
+        // 等待一个`oneshot`的任务：发送端还在，它的唤醒者一直被接收端的共享状态持有。
+        let (tx, rx) = oneshot::channel();
+        let first = handle.spawn(rx).unwrap();
+        let (_tx2, rx2) = oneshot::channel::<()>();
+        let second = handle.spawn(rx2).unwrap();
+        assert_eq!(handle.spawn(async {}).err(), Some(SpawnError::AtCapacity));
+
+        // 完成的任务归还名额，即使它的唤醒者还被别人持有。
+        tx.send(1).unwrap();
+        assert_eq!(mini_tokio.block_on(first).unwrap().unwrap(), 1);
+        let third = handle.spawn(async { 3 }).unwrap();
+        assert_eq!(mini_tokio.block_on(third).unwrap(), 3);
+
+        // 被取消的任务也归还名额。
+        let (_tx4, rx4) = oneshot::channel::<()>();
+        let fourth = handle.spawn(rx4).unwrap();
+        assert_eq!(handle.spawn(async {}).err(), Some(SpawnError::AtCapacity));
+        second.abort();
+        assert!(mini_tokio.block_on(second).unwrap_err().is_cancelled());
+        assert!(handle.spawn(async {}).is_ok());
+        drop(fourth);
+    }
+
+    #[test]
+    fn dropped_sleeps_leave_the_timer_heap() {
+        // `MockClock`有自己的定时器驱动，堆中只有这个测试登记的条目。
+        let clock = MockClock::new();
+        let mini_tokio = MiniTokio::with_clock(clock.clone());
+        let entries = || clock.inner.timer.entries.lock().unwrap().len();
+
+        mini_tokio.block_on(async {
+            let mut sleeps: Vec<_> = (0..1000).map(|_| sleep(Duration::from_secs(60))).collect();
+
+            // 每个`Sleep`在第一次被轮询时登记它的截止时间。每次轮询的预算只够登记`BUDGET_PER_POLL`个，
+            // 所以分几次轮询，中间让出执行器。
+            for chunk in sleeps.chunks_mut(BUDGET_PER_POLL) {
+                poll_fn(|cx| {
+                    for sleep in chunk.iter_mut() {
+                        assert!(Pin::new(sleep).poll(cx).is_pending());
+                    }
+                    Poll::Ready(())
+                })
+                .await;
+                yield_now().await;
+            }
+            assert_eq!(entries(), 1000);
+
+            drop(sleeps);
+            assert_eq!(entries(), 0);
+        });
+    }
+
+    #[test]
+    fn shutdown_timeout_abandons_what_does_not_finish_in_time() {
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+
+        // 这个任务在期限内完成，完成之前尝试催生的任务被拒绝。
+        let (result_tx, result_rx) = std::sync::mpsc::channel();
+        handle
+            .spawn(async move {
+                delay(Duration::from_millis(20)).await;
+                let _ = result_tx.send(try_spawn(async {}).err());
+            })
+            .unwrap();
+
+        // 这个任务永远等不到发送端，阻塞闭包也一直等到测试结束才返回。
+        let (_tx, rx) = oneshot::channel::<()>();
+        handle.spawn(rx).unwrap();
+        let (block_tx, block_rx) = std::sync::mpsc::channel::<()>();
+        drop(handle.spawn_blocking(move || {
+            let _ = block_rx.recv();
+        }));
+
+        let start = Instant::now();
+        let summary = mini_tokio.shutdown_timeout(Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(
+            summary,
+            ShutdownSummary {
+                completed: 1,
+                abandoned: 1,
+                blocking_abandoned: 1,
+            }
+        );
+        assert_eq!(result_rx.recv().unwrap(), Some(SpawnError::Shutdown));
+        assert!(handle.spawn(async {}).is_err());
+        drop(block_tx);
+    }
+
+    #[test]
+    fn mock_clock_advances_only_when_told() {
+        let clock = MockClock::new();
+        let mini_tokio = MiniTokio::with_clock(clock.clone());
+        let t0 = clock.now();
+        let start = Instant::now();
+
+        let elapsed = mini_tokio.block_on(async {
+            let sleeping = spawn(async {
+                delay(Duration::from_secs(10)).await;
+                current_clock().now()
+            });
+
+            // 等任务第一次被轮询、把截止时间登记到这个时钟中。
+            while clock.inner.timer.entries.lock().unwrap().is_empty() {
+                yield_now().await;
+            }
+
+            // 时间没有前进，任务还在等待。
+            clock.advance(Duration::from_secs(9));
+            assert!(!sleeping.is_finished());
+
+            clock.advance(Duration::from_secs(1));
+            sleeping.await.unwrap() - t0
+        });
+
+        assert_eq!(elapsed, Duration::from_secs(10));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn shutdown_background_returns_without_joining_workers() {
+        struct SetOnDrop(Arc<AtomicBool>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let handle = mini_tokio.handle();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+
+        // 发送端留在测试手里，任务一直停在等待中。
+        let (tx, rx) = oneshot::channel::<()>();
+        let guard = SetOnDrop(dropped.clone());
+        handle
+            .spawn(async move {
+                let _guard = guard;
+                started_tx.send(()).unwrap();
+                let _ = rx.await;
+            })
+            .unwrap();
+        started_rx.recv().unwrap();
+
+        let start = Instant::now();
+        mini_tokio.shutdown_background();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // 任务可能还没有从发送`started`的那次轮询中返回，那样它的未来由工作线程在轮询结束后丢弃。
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !dropped.load(Ordering::SeqCst) {
+            assert!(Instant::now() < deadline, "the task was not dropped");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // 再次关闭是无害的，之后催生的任务被干净地拒绝。
+        handle.shutdown();
+        assert_eq!(handle.spawn(async {}).err(), Some(SpawnError::Shutdown));
+
+        // 工作线程自己退出，释放它们持有的共享状态。
+        drop(tx);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Arc::strong_count(&handle.shared) > 1 {
+            assert!(Instant::now() < deadline, "workers did not exit");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn watch_receivers_observe_the_latest_value() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            let (tx, mut rx1) = watch::channel(0);
+            let mut rx2 = rx1.clone();
+
+            // 每个接收者把看到的值记下来，直到发送者被丢弃。
+            let observe = |mut rx: watch::Receiver<i32>| {
+                let seen = Arc::new(Mutex::new(vec![*rx.borrow()]));
+                let log = seen.clone();
+                let join = spawn(async move {
+                    while rx.changed().await.is_ok() {
+                        log.lock().unwrap().push(*rx.borrow());
+                    }
+                });
+                (seen, join)
+            };
+            let (first, first_join) = observe(rx1.clone());
+            let (second, second_join) = observe(rx2.clone());
+
+            for value in 1..=3 {
+                tx.send(value).unwrap();
+                // 让两个接收者都看到这个值再发送下一个。
+                for seen in [&first, &second] {
+                    while seen.lock().unwrap().last() != Some(&value) {
+                        yield_now().await;
+                    }
+                }
+            }
+
+            // 接收者来不及观察的中间值被跳过，只有最新的值是重要的。
+            tx.send(4).unwrap();
+            tx.send(5).unwrap();
+            assert!(rx1.changed().await.is_ok());
+            assert_eq!(*rx1.borrow(), 5);
+            assert!(rx2.changed().await.is_ok());
+            assert_eq!(*rx2.borrow(), 5);
+
+            drop(tx);
+            assert!(rx1.changed().await.is_err());
+            first_join.await.unwrap();
+            second_join.await.unwrap();
+            assert_eq!(*first.lock().unwrap(), vec![0, 1, 2, 3, 5]);
+            assert_eq!(*second.lock().unwrap(), vec![0, 1, 2, 3, 5]);
+
+            // 所有接收者都被丢弃之后发送失败。
+            let (tx, rx) = watch::channel("a");
+            drop(rx);
+            assert_eq!(tx.send("b"), Err(watch::SendError("b")));
+            assert_eq!(*tx.borrow(), "a");
+        });
+    }
+
+    #[test]
+    fn nested_block_on_is_rejected_only_for_the_same_runtime() {
+        fn panic_message(payload: Box<dyn Any + Send>) -> String {
+            match payload.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(payload) => *payload.downcast::<String>().unwrap(),
+            }
+        }
+
+        let outer = MiniTokio::new();
+        let inner = MiniTokio::new();
+
+        // 在这个运行时自己的`block_on`中再次进入它。
+        let payload = outer
+            .block_on(async { panic::catch_unwind(AssertUnwindSafe(|| outer.block_on(async {}))) })
+            .unwrap_err();
+        assert!(panic_message(payload).contains("`MiniTokio::block_on` was called"));
+
+        // 在这个运行时的任务中等待它的句柄。
+        let handle = outer.handle();
+        let payload = outer
+            .block_on(outer.spawn(async move {
+                panic::catch_unwind(AssertUnwindSafe(|| handle.block_on(async {})))
+            }))
+            .unwrap()
+            .unwrap_err();
+        assert!(panic_message(payload).contains("`Handle::block_on` was called"));
+
+        // 在另一个运行时中是允许的。内层的任务在内层的`block_on`中被轮询。
+        let output = outer.block_on(async { inner.block_on(inner.spawn(async { 1 })) });
+        assert_eq!(output.unwrap(), 1);
+
+        let driven = MiniTokio::new_multi_thread(1);
+        let handle = driven.handle();
+        let output = outer
+            .block_on(outer.spawn(async move { handle.block_on(async { 2 }) }))
+            .unwrap();
+        assert_eq!(output, 2);
+    }
+
+    #[test]
+    fn broadcast_delivers_every_message_to_every_receiver() {
+        let mini_tokio = MiniTokio::new();
+
+        let received = mini_tokio.block_on(async {
+            let (tx, rx) = broadcast::channel(8);
+            let receivers = vec![rx, tx.subscribe(), tx.subscribe()];
+
+            let joins: Vec<_> = receivers
+                .into_iter()
+                .map(|mut rx| {
+                    spawn(async move {
+                        let mut received = Vec::new();
+                        while let Ok(value) = rx.recv().await {
+                            received.push(value);
+                        }
+                        received
+                    })
+                })
+                .collect();
+
+            let producer = spawn(async move {
+                for value in 0..5 {
+                    assert_eq!(tx.send(value).unwrap(), 3);
+                    yield_now().await;
+                }
+            });
+            producer.await.unwrap();
+
+            let mut received = Vec::new();
+            for join in joins {
+                received.push(join.await.unwrap());
+            }
+            received
+        });
+
+        assert_eq!(received, vec![vec![0, 1, 2, 3, 4]; 3]);
+    }
+
+    #[test]
+    fn broadcast_reports_lagging_receivers() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            let (tx, mut slow) = broadcast::channel(2);
+            let mut fast = tx.subscribe();
+
+            for value in 0..5 {
+                tx.send(value).unwrap();
+                assert_eq!(fast.recv().await, Ok(value));
+            }
+
+            // 只有最后两条消息还在缓冲区中，慢的接收者错过了前三条，然后从最旧的那条继续。
+            assert_eq!(slow.recv().await, Err(broadcast::RecvError::Lagged(3)));
+            assert_eq!(slow.recv().await, Ok(3));
+            assert_eq!(slow.recv().await, Ok(4));
+
+            drop(tx);
+            assert_eq!(slow.recv().await, Err(broadcast::RecvError::Closed));
+            assert_eq!(fast.recv().await, Err(broadcast::RecvError::Closed));
+        });
+    }
+
+    #[test]
+    fn idle_workers_do_not_miss_wakeups_from_other_threads() {
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+        let done = Arc::new(AtomicUsize::new(0));
+        let run_for = Duration::from_secs(2);
+
+        // 几个外部线程不停地催生任务并从外部唤醒它们。工作线程在两次任务之间常常刚好进入park，
+        // 任何丢失的唤醒都会让某个`join`永远等待，被下面的期限发现。
+        let threads: Vec<_> = (0..3)
+            .map(|_| {
+                let handle = mini_tokio.handle();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let start = Instant::now();
+                    let mut i = 0u64;
+                    while start.elapsed() < run_for {
+                        let (tx, rx) = oneshot::channel();
+                        let join = handle.spawn(async move { rx.await.unwrap() + 1 }).unwrap();
+                        if i.is_multiple_of(2) {
+                            thread::yield_now();
+                        }
+                        tx.send(i).unwrap();
+                        assert_eq!(join.join().unwrap(), i + 1);
+                        done.fetch_add(1, Ordering::Relaxed);
+                        i += 1;
+                    }
+                })
+            })
+            .collect();
+
+        let deadline = Instant::now() + run_for + Duration::from_secs(20);
+        for thread in threads {
+            while !thread.is_finished() {
+                assert!(Instant::now() < deadline, "a wakeup was lost");
+                thread::sleep(Duration::from_millis(10));
+            }
+            thread.join().unwrap();
+        }
+        assert!(done.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn stream_adapters_are_lazy() {
+        use stream::StreamExt;
+
+        let mini_tokio = MiniTokio::new();
+        let mapped = Arc::new(AtomicUsize::new(0));
+
+        let counter = mapped.clone();
+        let mut evens = stream::iter(vec![1, 2, 3, 4, 5, 6, 7, 8])
+            .map(move |x| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                x * 3
+            })
+            .filter(|x| x % 2 == 0)
+            .take(3);
+
+        // 还没有被轮询，闭包一次都没有被调用。
+        assert_eq!(mapped.load(Ordering::SeqCst), 0);
+
+        let collected = mini_tokio.block_on(async {
+            let mut collected = Vec::new();
+            while let Some(x) = evens.next().await {
+                collected.push(x);
+            }
+            collected
+        });
+
+        assert_eq!(collected, vec![6, 12, 18]);
+        // 取到第三个值之后内层的流不再被轮询，7和8没有被变换。
+        assert_eq!(mapped.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn spawn_and_wake_after_shutdown_do_not_vanish_silently() {
+        struct SetOnDrop(Arc<AtomicBool>);
+
+        impl Drop for SetOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let mini_tokio = MiniTokio::new();
+        let handle = mini_tokio.handle();
+
+        // 一个停在等待中的任务，它的唤醒者在`oneshot`的共享状态中。
+        let dropped = Arc::new(AtomicBool::new(false));
+        let guard = SetOnDrop(dropped.clone());
+        let (tx, rx) = oneshot::channel::<()>();
+        let waiting = mini_tokio.spawn(async move {
+            let _guard = guard;
+            let _ = rx.await;
+        });
+        mini_tokio.block_on(async {
+            while handle.metrics().total_polls == 0 {
+                yield_now().await;
+            }
+        });
+
+        // 关闭时未来立即被丢弃，而不是等到唤醒者被释放。
+        handle.shutdown();
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(waiting.join().unwrap_err().is_cancelled());
+
+        // 唤醒一个属于已经关闭的运行时的任务什么都不做。
+        tx.send(()).unwrap_err();
+        drop(mini_tokio);
+
+        // 可以失败的催生返回错误，不返回错误的`spawn`带着原因panic。
+        assert_eq!(handle.spawn(async {}).err(), Some(SpawnError::Shutdown));
+        let _enter = handle.enter();
+        let payload = panic::catch_unwind(|| spawn(async {})).err().unwrap();
+        assert_eq!(
+            payload.downcast_ref::<String>().unwrap(),
+            "failed to spawn a task: the runtime has been shut down"
+        );
+    }
+
+    #[test]
+    fn join_set_yields_results_in_completion_order() {
+        let mini_tokio = MiniTokio::new();
+
+        let order = mini_tokio.block_on(async {
+            let mut set = task::JoinSet::new();
+            for i in [7u64, 2, 9, 0, 5, 3, 8, 1, 6, 4] {
+                set.spawn(async move {
+                    delay(Duration::from_millis(10 + i * 15)).await;
+                    i
+                });
+            }
+            assert_eq!(set.len(), 10);
+
+            let mut order = Vec::new();
+            while let Some(result) = set.join_next().await {
+                order.push(result.unwrap());
+            }
+            assert!(set.is_empty());
+            order
+        });
+
+        assert_eq!(order, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn join_set_aborts_its_tasks() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            let mut set = task::JoinSet::new();
+            set.spawn(async { 1 });
+            assert_eq!(set.join_next().await.unwrap().unwrap(), 1);
+
+            for _ in 0..2 {
+                set.spawn(async {
+                    delay(Duration::from_secs(3600)).await;
+                    2
+                });
+            }
+            set.abort_all();
+            assert!(set.join_next().await.unwrap().unwrap_err().is_cancelled());
+            assert!(set.join_next().await.unwrap().unwrap_err().is_cancelled());
+            assert!(set.join_next().await.is_none());
+
+            // 丢弃集合取消其中的任务。
+            let mut set = task::JoinSet::new();
+            let abort = set.spawn(delay(Duration::from_secs(3600)));
+            drop(set);
+            while !abort.is_finished() {
+                yield_now().await;
+            }
+        });
+    }
+
+    #[test]
+    fn interval_is_a_stream() {
+        use stream::StreamExt;
+
+        let mini_tokio = MiniTokio::new();
+        let start = Instant::now();
+
+        let ticks = mini_tokio.block_on(async {
+            let mut ticks = interval(Duration::from_millis(10)).take(3);
+            let mut collected = Vec::new();
+            while let Some(tick) = ticks.next().await {
+                collected.push(tick);
+            }
+            collected
+        });
+
+        // 第一个滴答立即到达，之后每隔一个周期一个。
+        assert_eq!(ticks, vec![(); 3]);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn poll_fn_builds_a_leaf_future() {
+        let mini_tokio = MiniTokio::new();
+        let mut polls = 0;
+
+        let output = mini_tokio.block_on(poll_fn(|cx| {
+            polls += 1;
+            if polls < 3 {
+                // 唤醒自己，下一次轮询再试。
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            } else {
+                Poll::Ready(polls * 10)
+            }
+        }));
+
+        assert_eq!(output, 30);
+        assert_eq!(polls, 3);
+    }
+
+    #[test]
+    fn named_tasks_surface_their_name() {
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let mini_tokio = Builder::new()
+            .on_task_panic({
+                let names = names.clone();
+                move |info| {
+                    let name = info.task_name().map(str::to_string);
+                    names.lock().unwrap().push(name);
+                }
+            })
+            .build()
+            .unwrap();
+
+        mini_tokio.block_on(async {
+            let join = task::Builder::new()
+                .name("accept-loop")
+                .spawn(async { panic!("boom") });
+            assert!(format!("{:?}", join).contains("accept-loop"));
+            assert!(join.await.is_err());
+
+            // 没有名字的任务和`spawn`催生的任务一样。
+            let join = task::Builder::new().spawn(async { 1 });
+            assert!(format!("{:?}", join).contains("None"));
+            assert_eq!(join.await.unwrap(), 1);
+            assert!(spawn(async { panic!("unnamed") }).await.is_err());
+        });
+
+        assert_eq!(
+            *names.lock().unwrap(),
+            [Some("accept-loop".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn join_set_collects_every_index() {
+        let mini_tokio = MiniTokio::new();
+
+        let mut indices = mini_tokio.block_on(async {
+            let mut set = task::JoinSet::new();
+            for i in 0..5 {
+                set.spawn(async move {
+                    yield_now().await;
+                    i
+                });
+            }
+            assert_eq!(set.len(), 5);
+
+            let mut indices = Vec::new();
+            while let Some(result) = set.join_next().await {
+                indices.push(result.unwrap());
+            }
+            assert!(set.is_empty());
+            indices
+        });
+
+        indices.sort_unstable();
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn task_ids_are_unique_and_visible_inside_the_task() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            assert_eq!(task::try_id(), None);
+
+            let mut ids = Vec::new();
+            for _ in 0..3 {
+                let join = spawn(async {
+                    yield_now().await;
+                    task::id()
+                });
+                let id = join.id();
+                assert_eq!(join.await.unwrap(), id);
+                ids.push(id);
+            }
+
+            // 任务结束之后编号也不会被重新使用。
+            assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+            let local = task::LocalSet::new();
+            local
+                .run_until(async {
+                    let join = task::spawn_local(async { task::id() });
+                    let id = join.id();
+                    assert_eq!(join.await.unwrap(), id);
+                    assert!(id > ids[2]);
+                })
+                .await;
+
+            let outside = spawn_blocking(task::try_id).await.unwrap();
+            assert_eq!(outside, None);
+        });
+    }
+
+    #[test]
+    fn try_join_returns_the_first_error() {
+        struct DropFlag(Arc<AtomicBool>);
+
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let mini_tokio = MiniTokio::new();
+        let dropped = Arc::new(AtomicBool::new(false));
+
+        let result = mini_tokio.block_on({
+            let flag = DropFlag(dropped.clone());
+            async move {
+                let slow = async move {
+                    let _flag = flag;
+                    delay(Duration::from_secs(60)).await;
+                    Ok::<_, &str>(1)
+                };
+                let failing = async { Err::<u32, _>("refused") };
+                try_join(slow, failing).await
+            }
+        });
+
+        assert_eq!(result, Err("refused"));
+        // 还没有完成的未来在提前返回时被丢弃。
+        assert!(dropped.load(Ordering::SeqCst));
+
+        let both = mini_tokio.block_on(try_join(async { Ok::<_, ()>(1) }, async {
+            delay(Duration::from_millis(10)).await;
+            Ok(2)
+        }));
+        assert_eq!(both, Ok((1, 2)));
+    }
+
+    #[test]
+    fn thread_hooks_run_on_every_runtime_thread() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let stops = Arc::new(AtomicUsize::new(0));
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            .on_thread_start({
+                let starts = starts.clone();
+                move || {
+                    starts.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .on_thread_stop({
+                let stops = stops.clone();
+                move || {
+                    stops.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build()
+            .unwrap();
+
+        mini_tokio.block_on(async {
+            spawn_blocking(|| {}).await.unwrap();
+        });
+
+        // 两个工作线程、一个阻塞线程和`block_on`的线程。工作线程可能还没有开始运行。
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while starts.load(Ordering::SeqCst) < 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(starts.load(Ordering::SeqCst), 4);
+        assert_eq!(stops.load(Ordering::SeqCst), 1);
+
+        mini_tokio.shutdown();
+        mini_tokio.run();
+        drop(mini_tokio);
+
+        // 阻塞线程在看到关闭之后自己退出，不被等待。
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stops.load(Ordering::SeqCst) < 4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(stops.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn panicking_thread_hook_does_not_kill_the_thread() {
+        let mini_tokio = Builder::new()
+            .on_thread_start(|| panic!("arena setup failed"))
+            .build()
+            .unwrap();
+
+        let output = mini_tokio.block_on(async { spawn(async { 1 }).await.unwrap() });
+        assert_eq!(output, 1);
+    }
+
+    #[test]
+    fn timeout_at_uses_an_absolute_deadline() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            // 截止时间已经过去：还没有就绪的未来立即超时。
+            let past = Instant::now() - Duration::from_millis(10);
+            let result = timeout_at(past, delay(Duration::from_secs(60))).await;
+            assert_eq!(result, Err(Elapsed(())));
+
+            // 截止时间还很远：未来先完成。
+            let future = Instant::now() + Duration::from_secs(60);
+            let result = timeout_at(future, async {
+                delay(Duration::from_millis(10)).await;
+                7
+            })
+            .await;
+            assert_eq!(result, Ok(7));
+        });
+    }
+
+    #[test]
+    fn unhandled_panic_hook_sees_detached_panics() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        set_unhandled_panic_hook({
+            let seen = seen.clone();
+            move |meta, payload| {
+                // 钩子是进程范围的，只记录这个测试中的任务。
+                let name = match meta.name() {
+                    Some(name) if name.starts_with("hook-test") => name.to_string(),
+                    _ => return,
+                };
+                if name == "hook-test-bad" {
+                    panic!("the hook itself panicked");
+                }
+                let message = *payload.downcast::<&str>().unwrap();
+                seen.lock().unwrap().push((name, message));
+            }
+        });
+
+        let mini_tokio = MiniTokio::new();
+        mini_tokio.block_on(async {
+            let join = task::Builder::new()
+                .name("hook-test-detached")
+                .spawn(async { panic!("boom") });
+            drop(join);
+
+            // 钩子自己panic不会影响执行器。
+            drop(
+                task::Builder::new()
+                    .name("hook-test-bad")
+                    .spawn(async { panic!("boom") }),
+            );
+
+            // 句柄看到的panic不会交给钩子。
+            let join = task::Builder::new()
+                .name("hook-test-observed")
+                .spawn(async { panic!("observed") });
+            assert!(join.await.is_err());
+
+            while seen.lock().unwrap().is_empty() {
+                yield_now().await;
+            }
+            assert_eq!(spawn(async { 1 }).await.unwrap(), 1);
+        });
+
+        // 钩子先被调用，然后运行时按照`ShutdownRuntime`被关闭，重新抛出的载荷带着同样的消息。
+        let mini_tokio = Builder::new()
+            .unhandled_panic(UnhandledPanic::ShutdownRuntime)
+            .build()
+            .unwrap();
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            mini_tokio.block_on(async {
+                drop(
+                    task::Builder::new()
+                        .name("hook-test-fatal")
+                        .spawn(async { panic!("fatal") }),
+                );
+                delay(Duration::from_secs(60)).await;
+            })
+        }))
+        .unwrap_err();
+        assert_eq!(*payload.downcast::<&str>().unwrap(), "fatal");
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                ("hook-test-detached".to_string(), "boom"),
+                ("hook-test-fatal".to_string(), "fatal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cancelling_a_parent_token_wakes_child_tasks() {
+        let mini_tokio = MiniTokio::new();
+
+        mini_tokio.block_on(async {
+            let parent = CancellationToken::new();
+            let child = parent.child_token();
+            let grandchild = child.child_token();
+
+            let waiter = spawn({
+                let grandchild = grandchild.clone();
+                async move {
+                    grandchild.cancelled().await;
+                    grandchild.is_cancelled()
+                }
+            });
+
+            // 取消子令牌不影响父令牌。
+            let sibling = parent.child_token();
+            sibling.cancel();
+            assert!(!parent.is_cancelled());
+            assert!(!child.is_cancelled());
+
+            delay(Duration::from_millis(10)).await;
+            assert!(!waiter.is_finished());
+
+            parent.cancel();
+            assert!(waiter.await.unwrap());
+            assert!(child.is_cancelled());
+
+            // 已经被取消的令牌的子令牌一开始就是被取消的。
+            let late = parent.child_token();
+            assert!(late.is_cancelled());
+            late.cancelled().await;
+        });
+    }
+
+    #[test]
+    fn workers_can_be_added_and_removed_while_tasks_run() {
+        let mini_tokio = MiniTokio::new_multi_thread(1);
+        let handle = mini_tokio.handle();
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let wait_for_workers = |n: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while handle.metrics().worker_count() != n && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(handle.metrics().worker_count(), n);
+        };
+
+        const TASKS: usize = 200;
+        let joins: Vec<_> = (0..TASKS)
+            .map(|_| {
+                let done = done.clone();
+                handle
+                    .spawn(async move {
+                        for _ in 0..50 {
+                            yield_now().await;
+                        }
+                        done.fetch_add(1, Ordering::SeqCst);
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        for _ in 0..3 {
+            handle.add_worker().unwrap();
+        }
+        wait_for_workers(4);
+
+        for _ in 0..3 {
+            handle.remove_worker().unwrap();
+        }
+        // 最后一个工作线程不能被移除。
+        let err = handle.remove_worker().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        wait_for_workers(1);
+
+        for join in joins {
+            join.join().unwrap();
+        }
+        assert_eq!(done.load(Ordering::SeqCst), TASKS);
+
+        // 单线程模式下不能改变工作线程的数量。
+        let single = MiniTokio::new();
+        assert!(single.handle().add_worker().is_err());
+        assert!(single.handle().remove_worker().is_err());
+    }
+
+    #[test]
+    fn reactor_wakes_a_task_waiting_for_readability() {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+
+        let (reader, mut writer) = UnixStream::pair().unwrap();
+        reader.set_nonblocking(true).unwrap();
+        let mut reader = mio::net::UnixStream::from_std(reader);
+
+        let mini_tokio = MiniTokio::new();
+
+        let received = mini_tokio.block_on(async move {
+            let registration = Registration::new(&mut reader, mio::Interest::READABLE).unwrap();
+
+            let read = spawn(async move {
+                let mut buf = [0; 16];
+                let n = poll_fn(|cx| registration.poll_read_io(cx, || reader.read(&mut buf)))
+                    .await
+                    .unwrap();
+                buf[..n].to_vec()
+            });
+
+            spawn(async move {
+                // 读取的任务先开始等待，读到`WouldBlock`之后才有数据到达。
+                delay(Duration::from_millis(20)).await;
+                writer.write_all(b"ping").unwrap();
+            });
+
+            read.await.unwrap()
+        });
+
+        assert_eq!(received, b"ping");
+    }
+
+    #[test]
+    fn pinned_workers_wrap_around_the_listed_cores() {
+        let cores = [2, 5, 7];
+        let mapped: Vec<_> = (0..7).map(|i| affinity::core_for(&cores, i)).collect();
+        assert_eq!(mapped, [2, 5, 7, 2, 5, 7, 2]);
+
+        assert_eq!(affinity::core_for(&[3], 0), 3);
+        assert_eq!(affinity::core_for(&[3], 4), 3);
+    }
+
+    #[test]
+    fn pin_workers_reports_problems_instead_of_failing() {
+        let err = Builder::new()
+            .worker_threads(1)
+            .pin_workers(Vec::new())
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            // 不存在的核：支持绑定的平台上绑定失败，其他平台上根本不支持。两种情况都只是警告。
+            .pin_workers(vec![usize::MAX])
+            .on_warning({
+                let warnings = warnings.clone();
+                move |message| warnings.lock().unwrap().push(message.to_string())
+            })
+            .build()
+            .unwrap();
+
+        let output = mini_tokio.block_on(async { spawn(async { 1 }).await.unwrap() });
+        assert_eq!(output, 1);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let expected = if affinity::SUPPORTED { 3 } else { 1 };
+        while warnings.lock().unwrap().len() < expected && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(warnings.lock().unwrap().len(), expected);
+    }
+
+    #[test]
+    fn tcp_stream_echoes_through_a_local_listener() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // 一个同步的回显服务器：读到对端关闭写方向为止，然后关闭连接。
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut buf = [0; 64];
+            loop {
+                match socket.read(&mut buf).unwrap() {
+                    0 => break,
+                    n => socket.write_all(&buf[..n]).unwrap(),
+                }
+            }
+        });
+
+        let mini_tokio = MiniTokio::new();
+        let echoed = mini_tokio.block_on(async move {
+            let mut stream = net::TcpStream::connect(addr).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), addr);
+
+            stream.write_all(b"hello, ").await.unwrap();
+            stream.write_all(b"world").await.unwrap();
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+
+            // 回显可能分几次到达，读到`Ok(0)`（服务器关闭了连接）为止。
+            let mut echoed = Vec::new();
+            let mut buf = [0; 4];
+            loop {
+                match stream.read(&mut buf).await.unwrap() {
+                    0 => break,
+                    n => echoed.extend_from_slice(&buf[..n]),
+                }
+            }
+            echoed
+        });
+
+        server.join().unwrap();
+        assert_eq!(echoed, b"hello, world");
+    }
+
+    #[test]
+    fn tcp_connect_reports_refused_connections() {
+        // 绑定之后立即关闭，这个端口上没有人在监听。
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let mini_tokio = MiniTokio::new();
+        let result = mini_tokio.block_on(net::TcpStream::connect(addr));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn spawn_pinned_keeps_tasks_on_one_worker() {
+        let rt = MiniTokio::new_multi_thread(2);
+        let handle = rt.handle();
+        let release = Arc::new(AtomicBool::new(false));
+
+        // 每个任务记录它每次被轮询时所在的线程，同时不停地让出，给其他工作线程窃取的机会。
+        let spawn = |worker: Option<usize>| {
+            let release = release.clone();
+            let future = async move {
+                let mut threads = vec![thread::current().id()];
+                while !release.load(Ordering::SeqCst) {
+                    yield_now().await;
+                    threads.push(thread::current().id());
+                }
+                threads
+            };
+            match worker {
+                Some(worker) => handle.spawn_pinned(worker, future).unwrap(),
+                None => handle.spawn_pinned_auto(future).unwrap(),
+            }
+        };
+        let joins: Vec<_> = (0..8)
+            .map(|i| spawn(if i % 2 == 0 { Some(i / 2 % 2) } else { None }))
+            .collect();
+        assert!(matches!(
+            handle.spawn_pinned(2, async {}),
+            Err(SpawnError::NoSuchWorker)
+        ));
+
+        thread::sleep(Duration::from_millis(50));
+        release.store(true, Ordering::SeqCst);
+        for join in joins {
+            let threads = join.join().unwrap();
+            assert!(threads.iter().all(|id| *id == threads[0]));
+        }
+        assert_eq!(handle.metrics().tasks_repinned, 0);
+
+        // 工作线程退休后，固定在它上面的任务被重新固定到剩下的工作线程上，而不是被丢弃。
+        release.store(false, Ordering::SeqCst);
+        let joins: Vec<_> = (0..8).map(|i| spawn(Some(i % 2))).collect();
+        handle.remove_worker().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while handle.metrics().worker_count() != 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(handle.metrics().worker_count(), 1);
+        release.store(true, Ordering::SeqCst);
+        for join in joins {
+            join.join().unwrap();
+        }
+        assert!(handle.metrics().tasks_repinned > 0);
+
+        // 退休的工作线程的编号不能再用来固定任务。
+        let rejected = (0..2).filter(|&i| handle.spawn_pinned(i, async {}).is_err());
+        assert_eq!(rejected.count(), 1);
+        assert!(MiniTokio::new()
+            .handle()
+            .spawn_pinned_auto(async {})
+            .is_err());
+    }
+
+    #[test]
+    fn tcp_listener_accepts_a_connection_from_a_client_task() {
+        let mini_tokio = MiniTokio::new();
+        let (peer, client_addr, polls) = mini_tokio.block_on(async {
+            let listener = net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            // 客户端稍后才连接，期间`accept`应该一直挂起，而不是被反复轮询。
+            let client = spawn(async move {
+                sleep(Duration::from_millis(50)).await;
+                let stream = net::TcpStream::connect(addr).await.unwrap();
+                stream.local_addr().unwrap()
+            });
+
+            let polls = Arc::new(AtomicUsize::new(0));
+            let mut accept = Box::pin(listener.accept());
+            let counted = polls.clone();
+            let (mut stream, peer) = poll_fn(|cx| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                accept.as_mut().poll(cx)
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(stream.peer_addr().unwrap(), peer);
+            let client_addr = client.await.unwrap();
+            // 客户端已经关闭了连接。
+            assert_eq!(stream.read(&mut [0; 4]).await.unwrap(), 0);
+            (peer, client_addr, polls.load(Ordering::SeqCst))
+        });
+
+        assert_eq!(peer, client_addr);
+        assert!(polls <= 3, "accept was polled {} times", polls);
+    }
+
+    #[test]
+    fn slow_polls_are_reported_with_the_task_and_duration() {
+        let slow = Arc::new(Mutex::new(Vec::new()));
+        let mini_tokio = Builder::new()
+            .slow_poll_threshold(Duration::from_millis(20))
+            .on_slow_poll({
+                let slow = slow.clone();
+                move |meta, elapsed| {
+                    let name = meta.name().map(str::to_string);
+                    slow.lock().unwrap().push((meta.id(), name, elapsed));
+                }
+            })
+            .build()
+            .unwrap();
+
+        let blocking = mini_tokio.block_on(async {
+            // 只有阻塞了线程的那一次轮询被报告，等待定时器的轮询不算。
+            let fast = spawn(async { sleep(Duration::from_millis(50)).await });
+            let blocking = task::Builder::new().name("blocking").spawn(async {
+                yield_now().await;
+                thread::sleep(Duration::from_millis(40));
+            });
+            let id = blocking.id();
+            blocking.await.unwrap();
+            fast.await.unwrap();
+            id
+        });
+
+        let slow = slow.lock().unwrap();
+        assert_eq!(slow.len(), 1);
+        let (id, name, elapsed) = &slow[0];
+        assert_eq!(*id, blocking);
+        assert_eq!(name.as_deref(), Some("blocking"));
+        assert!(*elapsed >= Duration::from_millis(40));
+
+        // 没有设置回调时通过`on_warning`报告，关闭检测之后不再报告。
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let build = |disable: bool| {
+            let warnings = warnings.clone();
+            let mut builder = Builder::new();
+            builder
+                .slow_poll_threshold(Duration::from_millis(10))
+                .on_warning(move |message| warnings.lock().unwrap().push(message.to_string()));
+            if disable {
+                builder.disable_slow_poll_detection();
+            }
+            builder.build().unwrap()
+        };
+        for disable in [false, true] {
+            let mini_tokio = build(disable);
+            mini_tokio.block_on(async {
+                spawn(async { thread::sleep(Duration::from_millis(20)) })
+                    .await
+                    .unwrap();
+            });
+        }
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("took"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn copy_pumps_an_in_memory_reader_into_a_writer() {
+        use io::AsyncWrite;
+
+        // 每次最多接受3个字节，而且每隔一次先返回`Pending`，`copy`必须写完剩下的部分。
+        struct Trickle {
+            written: Vec<u8>,
+            ready: bool,
+            flushed: bool,
+        }
+
+        impl AsyncWrite for Trickle {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                let ready = self.ready;
+                self.ready = !ready;
+                if !ready {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                let n = buf.len().min(3);
+                self.written.extend_from_slice(&buf[..n]);
+                Poll::Ready(Ok(n))
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                self.flushed = true;
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let data: Vec<u8> = (0..20_000).map(|i| (i % 251) as u8).collect();
+        let mini_tokio = MiniTokio::new();
+        let (copied, writer, into_vec) = mini_tokio.block_on(async {
+            let mut reader = &data[..];
+            let mut writer = Trickle {
+                written: Vec::new(),
+                ready: false,
+                flushed: false,
+            };
+            let copied = io::copy(&mut reader, &mut writer).await.unwrap();
+            assert!(reader.is_empty());
+
+            let mut into_vec = Vec::new();
+            io::copy(&mut &b"hello"[..], &mut into_vec).await.unwrap();
+            (copied, writer, into_vec)
+        });
+
+        assert_eq!(copied, data.len() as u64);
+        assert_eq!(writer.written, data);
+        assert!(writer.flushed);
+        assert_eq!(into_vec, b"hello");
+    }
+
+    #[test]
+    fn dump_lists_live_tasks_from_another_thread() {
+        let rt = Builder::new()
+            .worker_threads(2)
+            .disable_slow_poll_detection()
+            .build()
+            .unwrap();
+        let handle = rt.handle();
+        let before = Instant::now();
+
+        // 一个任务在等待永远不会到来的消息，另一个任务阻塞在轮询中，直到测试放行它。
+        let (tx, rx) = oneshot::channel::<()>();
+        let waiting = {
+            let _guard = rt.enter();
+            task::Builder::new().name("waiting").spawn(async move {
+                let _ = rx.await;
+            })
+        };
+        let started = Arc::new(AtomicBool::new(false));
+        let release = Arc::new(AtomicBool::new(false));
+        let running = handle
+            .spawn({
+                let started = started.clone();
+                let release = release.clone();
+                async move {
+                    started.store(true, Ordering::SeqCst);
+                    while !release.load(Ordering::SeqCst) {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+            })
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let dump = loop {
+            let dump = handle.dump();
+            let idle = dump
+                .tasks()
+                .iter()
+                .any(|task| task.state() == TaskState::Idle);
+            if (idle && started.load(Ordering::SeqCst)) || Instant::now() > deadline {
+                break dump;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        let tasks = dump.tasks();
+        assert_eq!(tasks.len(), 2, "{}", dump);
+        assert_eq!(tasks[0].id(), waiting.id());
+        assert_eq!(tasks[0].name(), Some("waiting"));
+        assert_eq!(tasks[0].state(), TaskState::Idle);
+        assert_eq!(tasks[0].polls(), 1);
+        assert_eq!(tasks[1].id(), running.id());
+        assert_eq!(tasks[1].name(), None);
+        assert_eq!(tasks[1].state(), TaskState::Running);
+        assert!(tasks.iter().all(|task| task.spawned_at() >= before));
+        assert!(dump
+            .to_string()
+            .contains("(\"waiting\"): Idle, polled 1 times"));
+
+        release.store(true, Ordering::SeqCst);
+        drop(tx);
+        running.join().unwrap();
+        waiting.join().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !handle.dump().tasks().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(handle.dump().tasks().is_empty());
+    }
+
+    #[test]
+    fn greedy_channel_reader_lets_other_tasks_run() {
+        const MESSAGES: usize = 10 * BUDGET_PER_POLL;
+
+        let mini_tokio = MiniTokio::new();
+        let received = mini_tokio.block_on(async {
+            // 通道里总是有消息，没有预算的话接收的循环永远不会让出执行器，另一个任务永远轮不到。
+            let (tx, mut rx) = mpsc::channel(MESSAGES);
+            for i in 0..MESSAGES {
+                tx.send(i).await.unwrap();
+            }
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let greedy = spawn({
+                let stop = stop.clone();
+                async move {
+                    let mut received = 0;
+                    while !stop.load(Ordering::SeqCst) {
+                        rx.recv().await.unwrap();
+                        received += 1;
+                    }
+                    received
+                }
+            });
+            let other = spawn(async move { stop.store(true, Ordering::SeqCst) });
+
+            other.await.unwrap();
+            greedy.await.unwrap()
+        });
+
+        // 另一个任务在接收者用完第一份预算之后就执行了。
+        assert!(received < MESSAGES, "received {} messages", received);
+    }
+
+    #[test]
+    fn deadlock_is_reported_when_no_task_can_make_progress() {
+        let handle = Arc::new(OnceLock::<Handle>::new());
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let mini_tokio = Builder::new()
+            .on_deadlock({
+                let handle = handle.clone();
+                let reports = reports.clone();
+                move |dump| {
+                    reports.lock().unwrap().push(dump.clone());
+                    handle.get().unwrap().shutdown();
+                }
+            })
+            .deadlock_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        handle.set(mini_tokio.handle()).ok().unwrap();
+
+        // 两个任务各自等待对方手中的发送者，谁也不会发送：它们的唤醒者永远不会被调用。
+        let (tx_a, rx_a) = oneshot::channel::<()>();
+        let (tx_b, rx_b) = oneshot::channel::<()>();
+        let a = mini_tokio.spawn(async move {
+            let _tx_b = tx_b;
+            let _ = rx_a.await;
+        });
+        let b = mini_tokio.spawn(async move {
+            let _tx_a = tx_a;
+            let _ = rx_b.await;
+        });
+        let ids = [a.id(), b.id()];
+
+        // 没有检测的话`run`永远不会返回。
+        mini_tokio.run();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        let stuck: Vec<_> = reports[0].tasks().iter().map(TaskInfo::id).collect();
+        assert_eq!(stuck, ids);
+        assert!(reports[0]
+            .tasks()
+            .iter()
+            .all(|task| task.state() == TaskState::Idle));
+    }
+
+    #[test]
+    fn pending_timers_are_not_reported_as_deadlocks() {
+        let reports = Arc::new(AtomicUsize::new(0));
+        let mini_tokio = Builder::new()
+            .worker_threads(2)
+            .on_deadlock({
+                let reports = reports.clone();
+                move |_| {
+                    reports.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build()
+            .unwrap();
+
+        // 所有工作线程都会休眠，但定时器还会唤醒这个任务。
+        let join = mini_tokio.spawn(sleep(Duration::from_millis(50)));
+        join.join().unwrap();
+        assert_eq!(reports.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn task_woken_late_by_a_plain_thread_is_not_reported_as_a_deadlock() {
+        let reports = Arc::new(AtomicUsize::new(0));
+        let mini_tokio = Builder::new()
+            .worker_threads(1)
+            .on_deadlock({
+                let reports = reports.clone();
+                move |_| {
+                    reports.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .build()
+            .unwrap();
+
+        // 运行时里只有这个等待的任务，发送者在一个普通线程手中，运行时看不到它。
+        let (tx, rx) = oneshot::channel();
+        let join = mini_tokio.spawn(rx);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            tx.send(5).unwrap();
+        });
+
+        assert_eq!(join.join().unwrap().unwrap(), 5);
+        sender.join().unwrap();
+        assert_eq!(reports.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn ten_thousand_tiny_tasks_complete_on_four_workers() {
+        const TASKS: usize = 10_000;
+
+        let mini_tokio = MiniTokio::new_multi_thread(4);
+        let done = Arc::new(AtomicUsize::new(0));
+
+        // 一半的任务从任务内部催生，进入那个工作线程的本地队列，由空闲的工作线程窃取；
+        // 另一半从外部催生，经过共享的调度通道。
+        let spawner = mini_tokio.spawn({
+            let done = done.clone();
+            async move {
+                let joins: Vec<_> = (0..TASKS / 2)
+                    .map(|_| {
+                        let done = done.clone();
+                        spawn(async move {
+                            yield_now().await;
+                            done.fetch_add(1, Ordering::Relaxed);
+                        })
+                    })
+                    .collect();
+                for join in joins {
+                    join.await.unwrap();
+                }
+            }
+        });
+        let joins: Vec<_> = (0..TASKS / 2)
+            .map(|_| {
+                let done = done.clone();
+                mini_tokio.spawn(async move {
+                    done.fetch_add(1, Ordering::Relaxed);
+                })
+            })
+            .collect();
+
+        spawner.join().unwrap();
+        for join in joins {
+            join.join().unwrap();
+        }
+        assert_eq!(done.load(Ordering::Relaxed), TASKS);
+    }
+
+    #[test]
+    fn waker_clones_hold_one_reference_each() {
+        #[derive(Default)]
+        struct Counter {
+            wakes: AtomicUsize,
+        }
+
+        impl ArcWake for Counter {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.wakes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let counter = Arc::new(Counter::default());
+        let waker = waker::waker(counter.clone());
+        assert_eq!(Arc::strong_count(&counter), 2);
+
+        let clone = waker.clone();
+        assert!(clone.will_wake(&waker));
+        assert_eq!(Arc::strong_count(&counter), 3);
+
+        // `wake_by_ref`借用引用，`wake`消耗引用，`drop`释放引用。
+        clone.wake_by_ref();
+        assert_eq!(Arc::strong_count(&counter), 3);
+        clone.wake();
+        assert_eq!(Arc::strong_count(&counter), 2);
+        let clone = waker.clone();
+        clone.wake();
+        assert_eq!(Arc::strong_count(&counter), 2);
+        drop(waker);
+        assert_eq!(Arc::strong_count(&counter), 1);
+        assert_eq!(counter.wakes.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn waking_a_task_after_it_is_gone_is_harmless() {
+        let stored = Arc::new(Mutex::new(Vec::new()));
+        let mini_tokio = MiniTokio::new_multi_thread(2);
+
+        // 任务把唤醒者的几个克隆交出去，然后完成或者被取消。
+        let finished = mini_tokio.spawn({
+            let stored = stored.clone();
+            poll_fn(move |cx| {
+                stored.lock().unwrap().push(cx.waker().clone());
+                stored.lock().unwrap().push(cx.waker().clone());
+                Poll::Ready(())
+            })
+        });
+        let finished_abort = finished.abort_handle();
+        finished.join().unwrap();
+
+        let aborted = mini_tokio.spawn({
+            let stored = stored.clone();
+            poll_fn(move |cx| {
+                stored.lock().unwrap().push(cx.waker().clone());
+                Poll::<()>::Pending
+            })
+        });
+        let aborted_abort = aborted.abort_handle();
+        while stored.lock().unwrap().len() < 3 {
+            thread::yield_now();
+        }
+        aborted.abort();
+        assert!(aborted.join().unwrap_err().is_cancelled());
+
+        // 唤醒者让任务保持存活，但唤醒一个已经结束的任务什么也不做。
+        let mut wakers = std::mem::take(&mut *stored.lock().unwrap());
+        assert!(finished_abort.task.upgrade().is_some());
+        wakers.pop().unwrap().wake();
+        for waker in &wakers {
+            waker.wake_by_ref();
+        }
+
+        // 运行时被丢弃之后唤醒也是一样。最后一个唤醒者被丢弃时任务被释放，没有泄漏引用。
+        // 工作线程在后台退出，它们的队列中可能还有被取消的那个任务，所以等它们退出。
+        drop(mini_tokio);
+        for waker in wakers.drain(..) {
+            waker.wake();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while aborted_abort.task.upgrade().is_some() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(finished_abort.task.upgrade().is_none());
+        assert!(aborted_abort.task.upgrade().is_none());
+    }
+
+    #[test]
+    fn ping_pong_tasks_are_polled_once_per_message() {
+        const ROUNDS: u64 = 1_000;
+
+        let mini_tokio = MiniTokio::new_multi_thread(1);
+        let (ping_tx, mut ping_rx) = mpsc::channel(1);
+        let (pong_tx, mut pong_rx) = mpsc::channel(1);
+
+        // 每条消息唤醒另一个任务，它进入LIFO槽，在这个任务挂起之后立即被轮询。
+        let ping = mini_tokio.spawn(async move {
+            for i in 0..ROUNDS {
+                ping_tx.send(i).await.unwrap();
+                assert_eq!(pong_rx.recv().await, Some(i));
+            }
+        });
+        let pong = mini_tokio.spawn(async move {
+            let mut received = 0;
+            while let Some(i) = ping_rx.recv().await {
+                assert_eq!(i, received);
+                pong_tx.send(i).await.unwrap();
+                received += 1;
+            }
+            received
+        });
+
+        ping.join().unwrap();
+        assert_eq!(pong.join().unwrap(), ROUNDS);
+
+        // 每个来回中两个任务各被轮询一次，没有多余的唤醒。另外最多两次轮询在开始和结束时：
+        // 先被轮询的任务等待另一个任务开始，`pong`最后看到通道被关闭。
+        let polls = mini_tokio.metrics().total_polls;
+        assert!(polls <= 2 * ROUNDS + 2, "{} polls", polls);
     }
 }